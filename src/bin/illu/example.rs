@@ -1,4 +1,4 @@
-use illuminator::prelude::*;
+use illuminator::{prelude::*, render::debug::DebugIntegrator};
 use num_traits::Zero;
 use rayon::prelude::*;
 
@@ -49,7 +49,11 @@ pub fn bvh_example(save_path: Option<&str>) {
     println!("BVH example completed! Output saved to {save_path}");
 }
 
-pub fn gaussian_splatting_example(ply_path: Option<&str>, (w, h): (usize, usize)) {
+pub fn gaussian_splatting_example(
+    ply_path: Option<&str>,
+    (w, h): (usize, usize),
+    bvh_cache: Option<&str>,
+) {
     use illuminator::{prelude::*, splat::render::SplatsRenderer};
     use image::RgbImage;
     use std::path::Path;
@@ -58,9 +62,9 @@ pub fn gaussian_splatting_example(ply_path: Option<&str>, (w, h): (usize, usize)
     println!("Running Gaussian Splatting tracing example...");
 
     let read_path = &path_or_default(ply_path, "point_cloud.ply");
-    let rdr = SplatsRenderer::from_ply(read_path);
-    if rdr.is_err() {
-        println!("Read file at {read_path} Error.");
+    let rdr = SplatsRenderer::from_ply(read_path, bvh_cache);
+    if let Err(e) = &rdr {
+        println!("Read file at {read_path} Error. {e}");
         return;
     }
 
@@ -101,6 +105,163 @@ pub fn gaussian_splatting_example(ply_path: Option<&str>, (w, h): (usize, usize)
         .expect("Failed to save Gaussian Splatting example image");
 }
 
+pub fn surfel_example(ply_path: Option<&str>, (w, h): (usize, usize), integrator: Option<&str>) {
+    use illuminator::{
+        core::vec::Vector,
+        splat::surfel::{read_surfels, render_surfels},
+    };
+    use std::path::Path;
+    use std::time::Instant;
+
+    println!("Running surfel preview example...");
+
+    let read_path = &path_or_default(ply_path, "points.ply");
+    let surfels = read_surfels(read_path);
+    if surfels.is_err() {
+        println!("Read file at {read_path} Error.");
+        return;
+    }
+
+    let surfels = surfels.unwrap();
+    let mut bvh = BVH::new(surfels.len());
+    surfels.into_iter().for_each(|s| bvh.push(s));
+    bvh.build(4, false);
+
+    // whole cloud in view from -x
+    let bounds = bvh.bounds();
+    let target = bounds.centroid();
+    let dist = bounds.diagonal().norm();
+    let cam_pos = target - Vec3f::vec([dist, 0., 0.]);
+    let cam = Camera::new(cam_pos, target - cam_pos, 60., 0.01 * dist, 4. * dist);
+
+    // unknown integrator name renders nothing
+    let integrator = match integrator {
+        Some(name) => match debug_integrator(name, 0.1 * dist) {
+            Some(integrator) => Some(integrator),
+            None => return,
+        },
+        None => None,
+    };
+
+    let start = Instant::now();
+
+    let img: RawImage<Rgb<u8>> = match integrator {
+        Some(integrator) => integrator.render(&bvh, &cam, (w, h)),
+        None => render_surfels(&bvh, &cam, (w, h)),
+    };
+
+    println!("Rendering used {:.2}s", start.elapsed().as_secs_f32());
+
+    let fname = Path::new(read_path)
+        .with_extension("png")
+        .to_string_lossy()
+        .into_owned();
+    let rgbimg = RgbImage::from(img);
+    rgbimg
+        .save(fname)
+        .expect("Failed to save surfel example image");
+}
+
+pub fn debug_example(save_path: Option<&str>, (w, h): (usize, usize), integrator: Option<&str>) {
+    use illuminator::{
+        core::{quaternion::Quat, transform::Transform},
+        raycast::{instance::Instance, primitive::DynPrimitive, triangle::Triangle},
+        render::{
+            debug::id_colour,
+            scene::{Material, Scene},
+        },
+    };
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    println!("Running debug integrator example...");
+
+    let Some(integrator) = debug_integrator(integrator.unwrap_or("normal"), 0.5) else {
+        return;
+    };
+
+    // ball on a tile, instanced over a grid with turns and sizes
+    let mut blas: BVH<Arc<dyn DynPrimitive>> = BVH::new(3);
+    let corner = |x: f32, z: f32| Vec3f::vec([x, 0., z]);
+    blas.push(Arc::new(Triangle::new(
+        corner(-0.5, 0.5),
+        corner(0.5, 0.5),
+        corner(0.5, -0.5),
+    )));
+    blas.push(Arc::new(Triangle::new(
+        corner(-0.5, 0.5),
+        corner(0.5, -0.5),
+        corner(-0.5, -0.5),
+    )));
+    blas.push(Arc::new(Sphere::new(Vec3f::vec([0.1, 0.25, 0.]), 0.25)));
+    blas.build(1, false);
+    let blas = Arc::new(blas);
+
+    let instances: Vec<_> = (0..16)
+        .map(|i| {
+            let pos = Vec3f::vec([(i % 4) as f32 - 1.5, 0., (i / 4) as f32 - 1.5]);
+            let rot = Quat::angle_axis(i as f32 * 25., Vec3f::vec([0., 1., 0.]));
+            let scale = Vec3f::vec([0.6 + 0.025 * i as f32; 3]);
+            Instance::new(i, blas.clone(), Transform::new(pos, rot, scale))
+        })
+        .collect();
+
+    let mut cam = Camera::new(
+        Vec3f::vec([0., 3., 4.]),
+        Vec3f::vec([0., 0., -1.]),
+        45.,
+        1.,
+        8.,
+    );
+    cam.look_at(Vec3f::zero());
+
+    let start = Instant::now();
+
+    let img: RawImage<Rgb<u8>> = if integrator == DebugIntegrator::Albedo {
+        // instances painted in colour of their id
+        let mut scene = Scene::new(Vec3f::zero());
+        for inst in instances {
+            let paint = Material::diffuse(Vec3f::vec(id_colour(inst.id)));
+            let material = scene.add_material(paint);
+            scene.push(inst, material);
+        }
+        scene.build(2, false);
+        integrator.render_scene(&scene, &cam, (w, h))
+    } else {
+        let mut tlas = BVH::new(instances.len());
+        instances.into_iter().for_each(|inst| tlas.push(inst));
+        tlas.build(2, false);
+        integrator.render_instances(&tlas, &cam, (w, h))
+    };
+
+    println!("Rendering used {:.2}s", start.elapsed().as_secs_f32());
+
+    let save_path = &path_or_default(save_path, "debug_example.png");
+    let rgbimg = RgbImage::from(img);
+    rgbimg
+        .save(save_path)
+        .expect("Failed to save debug example image");
+
+    println!("Debug example completed! Output saved to {save_path}");
+}
+
+/// integrator by name, ao radius follows scene size
+fn debug_integrator(name: &str, ao_radius: f32) -> Option<DebugIntegrator> {
+    match DebugIntegrator::from_name(name) {
+        Ok(DebugIntegrator::AmbientOcclusion { samples, .. }) => {
+            Some(DebugIntegrator::AmbientOcclusion {
+                radius: ao_radius,
+                samples,
+            })
+        }
+        Ok(integrator) => Some(integrator),
+        Err(e) => {
+            println!("{e}");
+            None
+        }
+    }
+}
+
 fn path_or_default(path: Option<&str>, default: &str) -> String {
     let default_path = if std::path::Path::new("Cargo.toml").exists() {
         format!("./target/{default}")
@@ -17,6 +17,14 @@ struct Args {
 
     #[arg(short, long, value_name = "RESOLUTION")]
     res: Option<String>,
+
+    /// ao, normal, depth, uv, primid, instid, bary or albedo
+    #[arg(short, long, value_name = "INTEGRATOR")]
+    integrator: Option<String>,
+
+    /// tree file of 3dgs, written once and reused while ply is unchanged
+    #[arg(long, value_name = "PATH")]
+    bvh_cache: Option<String>,
 }
 
 fn main() {
@@ -26,7 +34,7 @@ fn main() {
         Some(name) => match name.as_str() {
             //  --example bvh [--path "./target/bvh.png"]
             "bvh" => example::bvh_example(args.path.as_deref()),
-            //  --example 3dgs --path "./target/point_cloud.ply" [--res "256x256"]
+            //  --example 3dgs --path "./target/point_cloud.ply" [--res "256x256"] [--bvh-cache "./target/point_cloud.bvh"]
             "3dgs" => {
                 let res = {
                     let def_res = (256, 256);
@@ -34,7 +42,31 @@ fn main() {
                         .map_or(def_res, |res| parse_resolution(&res).unwrap_or(def_res))
                 };
 
-                example::gaussian_splatting_example(args.path.as_deref(), res);
+                example::gaussian_splatting_example(
+                    args.path.as_deref(),
+                    res,
+                    args.bvh_cache.as_deref(),
+                );
+            }
+            //  --example surfel --path "./target/points.ply" [--res "256x256"] [--integrator ao]
+            "surfel" => {
+                let res = {
+                    let def_res = (256, 256);
+                    args.res
+                        .map_or(def_res, |res| parse_resolution(&res).unwrap_or(def_res))
+                };
+
+                example::surfel_example(args.path.as_deref(), res, args.integrator.as_deref());
+            }
+            //  --example debug [--path "./target/debug.png"] [--res "256x256"] [--integrator bary]
+            "debug" => {
+                let res = {
+                    let def_res = (256, 256);
+                    args.res
+                        .map_or(def_res, |res| parse_resolution(&res).unwrap_or(def_res))
+                };
+
+                example::debug_example(args.path.as_deref(), res, args.integrator.as_deref());
             }
             _ => {
                 eprintln!("Unknown example: {name}");
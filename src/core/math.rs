@@ -13,6 +13,26 @@ pub fn gamma(n: i32) -> f32 {
     (n as f32 * MACHINE_EPSILON32) / (1. - n as f32 * MACHINE_EPSILON32)
 }
 
+/// real roots of a*t^2 + b*t + c = 0 in ascending order, linear if a is 0.
+/// avoids cancellation of -b + sqrt(discriminant), pbrt 6.2.2
+pub fn quadratic(a: f32, b: f32, c: f32) -> Option<(f32, f32)> {
+    if a == 0. {
+        if b == 0. {
+            return None;
+        }
+        let t = -c / b;
+        return Some((t, t));
+    }
+
+    let discrim = b * b - 4. * a * c;
+    if discrim < 0. {
+        return None;
+    }
+    let q = -0.5 * (b + discrim.sqrt().copysign(b));
+    let (t0, t1) = if q == 0. { (0., 0.) } else { (q / a, c / q) };
+    Some((t0.min(t1), t0.max(t1)))
+}
+
 /// returned value no large than size-2
 /// returens 0 if pred all false, size - 2 if all true
 /// eg: for pred of elements =2: [2,2,3]->1 ,[2,2,2]-> 1  
@@ -48,6 +68,18 @@ pub fn sigmoid(x: f32) -> f32 {
     1. / (1. + (-x).exp())
 }
 
+/// two unit vectors orthogonal to unit n and to each other,
+/// without branches on n, Duff et al. 2017
+pub fn coordinate_system(n: Vec3f) -> (Vec3f, Vec3f) {
+    let sign = 1f32.copysign(n[2]);
+    let a = -1. / (sign + n[2]);
+    let b = n[0] * n[1] * a;
+    (
+        Vec3f::vec([1. + sign * n[0] * n[0] * a, sign * b, -sign * n[0]]),
+        Vec3f::vec([b, sign + n[1] * n[1] * a, -n[1]]),
+    )
+}
+
 /// think of v0 is forward, this returns [v0,up,right]
 pub fn orthogonalization(v0: Vec3f, v1: Vec3f) -> (Vec3f, Vec3f, Vec3f) {
     let (v0, v1) = {
@@ -79,3 +111,38 @@ fn test_split() {
     let i = split_index(arr.len(), |i| arr[i] == 1);
     assert_eq!(i, 0);
 }
+
+#[test]
+fn test_quadratic() {
+    assert_eq!(quadratic(1., -3., 2.), Some((1., 2.)));
+    assert_eq!(quadratic(-1., 3., -2.), Some((1., 2.)));
+    assert_eq!(quadratic(1., 0., -4.), Some((-2., 2.)));
+    assert_eq!(quadratic(0., 2., -4.), Some((2., 2.)));
+    assert!(quadratic(1., 0., 4.).is_none());
+    assert!(quadratic(0., 0., 1.).is_none());
+    // tiny root of large b, naive formula loses it
+    let (t0, t1) = quadratic(1., 1e4, 1.).unwrap();
+    assert!((t1 + 1e-4).abs() < 1e-9);
+    assert!((t0 + 1e4).abs() < 1.);
+}
+
+#[test]
+fn test_coordinate_system() {
+    use rand::Rng;
+
+    let mut rng = rand::rng();
+    let mut normals: Vec<Vec3f> = (0..256)
+        .map(|_| Vec3f::vec(std::array::from_fn(|_| rng.random_range(-1.0..1.))).normalize())
+        .collect();
+    normals.push(Vec3f::vec([0., 0., 1.]));
+    normals.push(Vec3f::vec([0., 0., -1.]));
+    normals.push(Vec3f::vec([1., 0., 0.]));
+
+    for n in normals {
+        let (u, v) = coordinate_system(n);
+        assert!((u.norm() - 1.).abs() < 1e-5 && (v.norm() - 1.).abs() < 1e-5);
+        assert!(u.dot(v).abs() < 1e-5 && u.dot(n).abs() < 1e-5 && v.dot(n).abs() < 1e-5);
+        // right handed
+        assert!((u.cross(v) - n).norm() < 1e-5);
+    }
+}
@@ -7,5 +7,6 @@ pub mod quaternion;
 pub mod sampling;
 pub mod spherical;
 pub mod tensor;
+pub mod transform;
 pub mod vec;
 pub mod tsrmath;
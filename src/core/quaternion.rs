@@ -87,6 +87,43 @@ impl Quaternion {
         Self::wxyz(self.w, -self.i, -self.j, -self.k)
     }
 
+    pub fn dot(&self, other: &Self) -> f32 {
+        self.w * other.w + self.i * other.i + self.j * other.j + self.k * other.k
+    }
+
+    /// rotation angle in radian between unit quaternions
+    pub fn angle(&self, other: &Self) -> f32 {
+        2. * self.dot(other).abs().min(1.).acos()
+    }
+
+    /// spherical interpolation of unit quaternions along shorter arc, constant angular speed
+    pub fn slerp(&self, other: &Self, t: f32) -> Self {
+        let d = self.dot(other);
+        // q and -q are same rotation
+        let (other, d) = if d < 0. {
+            (Self::wxyz(-other.w, -other.i, -other.j, -other.k), -d)
+        } else {
+            (*other, d)
+        };
+
+        let (a, b) = if d > 0.9995 {
+            // nearly same, lerp then renormalize avoids dividing by sin of tiny angle
+            (1. - t, t)
+        } else {
+            let theta = d.acos();
+            let sin = theta.sin();
+            (((1. - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+        let q = [
+            a * self.w + b * other.w,
+            a * self.i + b * other.i,
+            a * self.j + b * other.j,
+            a * self.k + b * other.k,
+        ];
+        let norm = q.iter().map(|x| x * x).sum::<f32>().sqrt();
+        Self::new(q.map(|x| x / norm))
+    }
+
 
     #[rustfmt::skip]
     pub fn to_matrix(&self) -> Mat3x3f {
@@ -129,3 +166,25 @@ fn test_quaternion() {
 
     assert_eq!(vr[1], 0.5f32);
 }
+
+#[test]
+fn test_slerp() {
+    let y = Vec3f::vec([0., 1., 0.]);
+    let (q0, q1) = (Quat::identity(), Quat::angle_axis(90., y));
+    let half = q0.slerp(&q1, 0.5);
+    let expected = Quat::angle_axis(45., y);
+    assert!((half.dot(&expected) - 1.).abs() < 1e-6);
+    assert!((q0.angle(&half) - 45f32.to_radians()).abs() < 1e-3);
+    assert!((q0.slerp(&q1, 0.).dot(&q0) - 1.).abs() < 1e-6);
+    assert!((q0.slerp(&q1, 1.).dot(&q1) - 1.).abs() < 1e-6);
+
+    // negated end takes shorter arc too
+    let neg = Quat::wxyz(-q1.w, -q1.i, -q1.j, -q1.k);
+    assert!((q0.slerp(&neg, 0.5).dot(&expected).abs() - 1.).abs() < 1e-6);
+
+    // 300 degrees is -60 the short way
+    let q2 = Quat::angle_axis(300., y);
+    let v = q0.slerp(&q2, 0.5).transform_vec(Vec3f::vec([1., 0., 0.]));
+    let e = Quat::angle_axis(-30., y).transform_vec(Vec3f::vec([1., 0., 0.]));
+    assert!((v - e).norm() < 1e-5);
+}
@@ -34,17 +34,24 @@ pub fn sample_uni_disk_concentric(p: [Real; 2]) -> [Real; 2] {
         return [0.; 2];
     }
 
-    let (theta, r) = {
+    let (r, theta) = {
         if x.abs() > y.abs() {
             (x, PI / 4. * (y / x))
         } else {
-            (x, PI / 4. * (x / y))
+            (y, PI / 2. - PI / 4. * (x / y))
         }
     };
 
     [r * theta.cos(), r * theta.sin()]
 }
 
+/// cosine weighted direction around +z, pdf is z / pi
+pub fn sample_cos_hemisphere(p: [Real; 2]) -> [Real; 3] {
+    let [x, y] = sample_uni_disk_concentric(p);
+    let z = (1. - x * x - y * y).max(0.).sqrt();
+    [x, y, z]
+}
+
 /// d: point on unit sphere surface
 /// return u,v in [0,1]^2
 pub fn unitsphere2square(d: [f32; 3]) -> [Real; 2] {
@@ -84,3 +91,169 @@ pub fn square2unitsphere(p: [Real; 2]) -> [Real; 3] {
 
     [x, y, z]
 }
+
+/// piecewise constant density over [0,1] proportional to func
+#[derive(Debug, Clone)]
+pub struct Distribution1D {
+    pub func: Vec<Real>,
+    /// n + 1 entries, from 0 to 1
+    pub cdf: Vec<Real>,
+    /// integral of func over [0,1]
+    pub integral: Real,
+}
+
+impl Distribution1D {
+    /// negative values count as 0, all zero func samples uniformly
+    pub fn new(func: &[Real]) -> Self {
+        let n = func.len().max(1);
+        let func: Vec<Real> = (0..n)
+            .map(|i| func.get(i).map_or(0., |f| f.max(0.)))
+            .collect();
+        let mut cdf = vec![0.; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i] / n as Real;
+        }
+        let integral = cdf[n];
+        if integral == 0. {
+            (1..=n).for_each(|i| cdf[i] = i as Real / n as Real);
+        } else {
+            (1..=n).for_each(|i| cdf[i] /= integral);
+        }
+        Distribution1D {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    /// x in [0,1), its density and index of its piece
+    pub fn sample(&self, u: Real) -> (Real, Real, usize) {
+        let n = self.count();
+        // last piece whose cdf start is not above u
+        let i = self.cdf[..n].partition_point(|&c| c <= u).saturating_sub(1);
+        let width = self.cdf[i + 1] - self.cdf[i];
+        let du = if width > 0. {
+            (u - self.cdf[i]) / width
+        } else {
+            0.
+        };
+        let x = ((i as Real + du) / n as Real).min(ONE_MINUS_EPSILON);
+        (x, self.pdf_piece(i), i)
+    }
+
+    fn pdf_piece(&self, i: usize) -> Real {
+        if self.integral == 0. {
+            1.
+        } else {
+            self.func[i] / self.integral
+        }
+    }
+
+    /// density at x in [0,1]
+    pub fn pdf(&self, x: Real) -> Real {
+        let i = ((x * self.count() as Real) as usize).min(self.count() - 1);
+        self.pdf_piece(i)
+    }
+}
+
+/// piecewise constant density over [0,1]^2, rows of func along v, x varies fastest
+#[derive(Debug, Clone)]
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[Real], nu: usize, nv: usize) -> Self {
+        assert_eq!(func.len(), nu * nv);
+        let conditional: Vec<Distribution1D> = (0..nv)
+            .map(|v| Distribution1D::new(&func[v * nu..(v + 1) * nu]))
+            .collect();
+        let marginal: Vec<Real> = conditional.iter().map(|d| d.integral).collect();
+        Distribution2D {
+            conditional,
+            marginal: Distribution1D::new(&marginal),
+        }
+    }
+
+    /// point in [0,1)^2 and its density
+    pub fn sample(&self, u: [Real; 2]) -> ([Real; 2], Real) {
+        let (v, pdf_v, iv) = self.marginal.sample(u[1]);
+        let (x, pdf_u, _) = self.conditional[iv].sample(u[0]);
+        ([x, v], pdf_v * pdf_u)
+    }
+
+    pub fn pdf(&self, p: [Real; 2]) -> Real {
+        let nv = self.conditional.len();
+        let iv = ((p[1] * nv as Real) as usize).min(nv - 1);
+        self.marginal.pdf(p[1]) * self.conditional[iv].pdf(p[0])
+    }
+}
+
+#[test]
+fn test_sample_disk() {
+    // stratified square covers disk evenly, quarter of points in each quadrant
+    let n = 64;
+    let mut quadrants = [0; 4];
+    let mut inner = 0;
+    for i in 0..n * n {
+        let p = [(i % n) as f32 + 0.5, (i / n) as f32 + 0.5].map(|v| v / n as f32);
+        let [x, y] = sample_uni_disk_concentric(p);
+        let r = (x * x + y * y).sqrt();
+        assert!(r <= 1. + 1e-6);
+        quadrants[(x < 0.) as usize + 2 * (y < 0.) as usize] += 1;
+        inner += (r < 0.5f32.sqrt()) as usize;
+    }
+    assert!(quadrants.iter().all(|&q| q == n * n / 4), "{quadrants:?}");
+    // half of area lies within radius sqrt(1/2)
+    assert!((inner as f32 / (n * n) as f32 - 0.5).abs() < 0.02);
+
+    // mean of cos over cosine weighted hemisphere is 2/3
+    let mean = (0..n * n)
+        .map(|i| {
+            let p = [(i % n) as f32 + 0.5, (i / n) as f32 + 0.5].map(|v| v / n as f32);
+            let d = sample_cos_hemisphere(p);
+            assert!((d[0] * d[0] + d[1] * d[1] + d[2] * d[2] - 1.).abs() < 1e-5);
+            d[2]
+        })
+        .sum::<f32>()
+        / (n * n) as f32;
+    assert!((mean - 2. / 3.).abs() < 0.01, "{mean}");
+}
+
+#[test]
+fn test_distribution() {
+    let d = Distribution1D::new(&[1., 3., 0., 4.]);
+    assert_eq!(d.integral, 2.);
+    assert_eq!(d.cdf, vec![0., 0.125, 0.5, 0.5, 1.]);
+    // empty piece never sampled, density matches func
+    for i in 0..64 {
+        let (x, pdf, piece) = d.sample((i as Real + 0.5) / 64.);
+        assert_ne!(piece, 2);
+        assert_eq!(piece, (x * 4.) as usize);
+        assert_eq!(pdf, d.pdf(x));
+        assert_eq!(pdf, d.func[piece] / 2.);
+    }
+    assert_eq!(d.sample(0.3).0, 0.25 + 0.25 * (0.3 - 0.125) / 0.375);
+    assert_eq!(Distribution1D::new(&[0.; 3]).sample(0.5).1, 1.);
+
+    // sample counts follow density
+    let func: Vec<Real> = (0..12).map(|i| (i % 4 + i / 4) as Real).collect();
+    let d = Distribution2D::new(&func, 4, 3);
+    let n = 128;
+    let mut counts = [0.; 12];
+    for i in 0..n * n {
+        let u = [(i % n) as Real + 0.5, (i / n) as Real + 0.5].map(|v| v / n as Real);
+        let (p, pdf) = d.sample(u);
+        assert_eq!(pdf, d.pdf(p));
+        counts[(p[1] * 3.) as usize * 4 + (p[0] * 4.) as usize] += 1.;
+    }
+    let total: Real = func.iter().sum();
+    for (c, f) in counts.iter().zip(func.iter()) {
+        assert!((c / (n * n) as Real - f / total).abs() < 0.01);
+    }
+}
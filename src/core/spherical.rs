@@ -18,6 +18,11 @@ pub fn xyz2spherical(xyz: Vec3f) -> Vec3f {
     Vec3f::vec([r, theta, phi])
 }
 
+/// y-up, z-forward, azimuth phi of xyz as in xyz2spherical, mapped to [0,1)
+pub fn azimuth_u(xyz: Vec3f) -> f32 {
+    (xyz[0].atan2(xyz[2]) / (2. * PI)).rem_euclid(1.)
+}
+
 /// evaluate Associated Legendre Polynomial P(l,m) at x
 pub fn sh_legendre(l: i32, m: i32, x: f32) -> f32 {
     assert!(m >= 0);
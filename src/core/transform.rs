@@ -0,0 +1,196 @@
+use crate::core::{
+    matrix::Matrix,
+    quaternion::Quat,
+    tensor::{Mat3x3f, Vec3f},
+};
+
+/// translation, rotation and scale, applied as T * R * S.
+/// inverse is derived analytically since Matrix::inverse is not available
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    pub pos: Vec3f,
+    pub rot: Quat,
+    pub scale: Vec3f,
+    /// R * S
+    m: Mat3x3f,
+    /// S^-1 * R^T
+    inv_m: Mat3x3f,
+}
+
+impl Transform {
+    pub fn new(pos: Vec3f, rot: Quat, scale: Vec3f) -> Self {
+        let r = rot.to_matrix();
+        let m = Mat3x3f::mat(
+            [3, 3],
+            std::array::from_fn(|k| {
+                let (i, j) = (k / 3, k % 3);
+                r[(i, j)] * scale[j]
+            }),
+        );
+        let inv_m = Mat3x3f::mat(
+            [3, 3],
+            std::array::from_fn(|k| {
+                let (i, j) = (k / 3, k % 3);
+                r[(j, i)] / scale[i]
+            }),
+        );
+
+        Transform {
+            pos,
+            rot,
+            scale,
+            m,
+            inv_m,
+        }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(Vec3f::vec([0.; 3]), Quat::identity(), Vec3f::vec([1.; 3]))
+    }
+
+    pub fn translate(pos: Vec3f) -> Self {
+        Self::new(pos, Quat::identity(), Vec3f::vec([1.; 3]))
+    }
+
+    /// rotation then translation, keeps lengths so object and world t agree
+    pub fn rigid(pos: Vec3f, rot: Quat) -> Self {
+        Self::new(pos, rot, Vec3f::vec([1.; 3]))
+    }
+
+    /// object to world
+    pub fn point(&self, p: Vec3f) -> Vec3f {
+        self.m.matmulvec(p) + self.pos
+    }
+
+    pub fn vector(&self, v: Vec3f) -> Vec3f {
+        self.m.matmulvec(v)
+    }
+
+    /// world to object
+    pub fn inv_point(&self, p: Vec3f) -> Vec3f {
+        self.inv_m.matmulvec(p - self.pos)
+    }
+
+    pub fn inv_vector(&self, v: Vec3f) -> Vec3f {
+        self.inv_m.matmulvec(v)
+    }
+
+    /// object normal to world, by inverse transpose (R * S^-1), not normalized
+    pub fn normal(&self, n: Vec3f) -> Vec3f {
+        Vec3f::vec(std::array::from_fn(|i| {
+            (0..3).map(|j| self.inv_m[(j, i)] * n[j]).sum()
+        }))
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+/// transforms keyed by time, position and scale interpolated linearly, rotation by slerp.
+/// clamped to first and last key outside their times
+#[derive(Debug, Clone)]
+pub struct MotionTransform {
+    keys: Vec<(f32, Transform)>,
+}
+
+impl MotionTransform {
+    /// keys are sorted by time, at least one is needed
+    pub fn new(mut keys: Vec<(f32, Transform)>) -> Self {
+        assert!(!keys.is_empty());
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        MotionTransform { keys }
+    }
+
+    /// not moving
+    pub fn fixed(t: Transform) -> Self {
+        MotionTransform {
+            keys: vec![(0., t)],
+        }
+    }
+
+    pub fn keys(&self) -> &[(f32, Transform)] {
+        &self.keys
+    }
+
+    pub fn is_fixed(&self) -> bool {
+        self.keys.len() == 1
+    }
+
+    pub fn at(&self, time: f32) -> Transform {
+        let next = self.keys.partition_point(|k| k.0 <= time);
+        if next == 0 {
+            return self.keys[0].1;
+        }
+        if next == self.keys.len() {
+            return self.keys[next - 1].1;
+        }
+
+        let ((t0, a), (t1, b)) = (self.keys[next - 1], self.keys[next]);
+        let s = (time - t0) / (t1 - t0);
+        Transform::new(
+            a.pos * (1. - s) + b.pos * s,
+            a.rot.slerp(&b.rot, s),
+            a.scale * (1. - s) + b.scale * s,
+        )
+    }
+}
+
+#[test]
+fn test_transform() {
+    let t = Transform::new(
+        Vec3f::vec([1., 2., 3.]),
+        Quat::angle_axis(90., Vec3f::vec([0., 1., 0.])),
+        Vec3f::vec([2., 3., 4.]),
+    );
+
+    let p = Vec3f::vec([1., 0., 0.]);
+    let wp = t.point(p);
+    // x scaled by 2 then rotated to -z
+    assert!((wp[0] - 1.).abs() < 1e-5);
+    assert!((wp[1] - 2.).abs() < 1e-5);
+    assert!((wp[2] - 1.).abs() < 1e-5);
+
+    let q = Vec3f::vec([-0.3, 0.7, 5.]);
+    let back = t.inv_point(t.point(q));
+    let back_v = t.inv_vector(t.vector(q));
+    for i in 0..3 {
+        assert!((back[i] - q[i]).abs() < 1e-5);
+        assert!((back_v[i] - q[i]).abs() < 1e-5);
+    }
+}
+
+#[test]
+fn test_motion_transform() {
+    let y = Vec3f::vec([0., 1., 0.]);
+    let motion = MotionTransform::new(vec![
+        (
+            1.,
+            Transform::new(
+                Vec3f::vec([4., 0., 0.]),
+                Quat::angle_axis(90., y),
+                Vec3f::vec([3.; 3]),
+            ),
+        ),
+        (0., Transform::identity()),
+    ]);
+    assert_eq!(motion.keys()[0].0, 0.);
+    assert!(!motion.is_fixed());
+
+    // half way moved, turned and scaled half
+    let t = motion.at(0.5);
+    let p = t.point(Vec3f::vec([1., 0., 0.]));
+    let e = Vec3f::vec([2., 0., 0.]) + Vec3f::vec([0.5f32.sqrt(), 0., -(0.5f32.sqrt())]) * 2.;
+    for i in 0..3 {
+        assert!((p[i] - e[i]).abs() < 1e-5);
+    }
+
+    // clamped outside keys
+    assert_eq!(motion.at(-1.).pos, Vec3f::vec([0.; 3]));
+    assert_eq!(motion.at(2.).pos, Vec3f::vec([4., 0., 0.]));
+    let fixed = MotionTransform::fixed(Transform::translate(y));
+    assert!(fixed.is_fixed());
+    assert_eq!(fixed.at(0.7).pos, y);
+}
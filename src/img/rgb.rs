@@ -10,6 +10,13 @@ impl PixelType for Rgb<u8> {
     }
 }
 
+/// linear radiance, not clamped
+impl PixelType for Rgb<f32> {
+    fn from(c: &[f32; 3]) -> Self {
+        Rgb(*c)
+    }
+}
+
 impl From<RgbImage> for RawImage<Rgb<u8>> {
     fn from(value: RgbImage) -> Self {
         let (w, h) = (value.width() as usize, value.height() as usize);
@@ -1,7 +1,11 @@
-use std::mem;
-
 use crate::{
-    core::{math::gamma, tensor::Vec3f, tsrmath::TensorMath},
+    core::{
+        math::gamma,
+        tensor::Vec3f,
+        transform::{MotionTransform, Transform},
+        tsrmath::TensorMath,
+        vec::Vector,
+    },
     raycast::*,
 };
 
@@ -25,6 +29,14 @@ impl Bounds3f {
         }
     }
 
+    /// contains nothing, identity of union
+    pub fn empty() -> Bounds3f {
+        Bounds3f {
+            min: Vec3f::vec([f32::INFINITY; 3]),
+            max: Vec3f::vec([f32::NEG_INFINITY; 3]),
+        }
+    }
+
     pub fn centroid(&self) -> Vec3f {
         (self.min + self.max) * 0.5
     }
@@ -36,6 +48,27 @@ impl Bounds3f {
         }
     }
 
+    /// overlapping part, empty if not overlapped
+    pub fn intersect(&self, other: &Bounds3f) -> Bounds3f {
+        Bounds3f {
+            min: self.min.max(other.min),
+            max: self.max.min(other.max),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        (0..3).any(|i| self.min[i] > self.max[i])
+    }
+
+    /// no NaN or infinite coordinate
+    pub fn is_finite(&self) -> bool {
+        (0..3).all(|i| self.min[i].is_finite() && self.max[i].is_finite())
+    }
+
+    pub fn contains(&self, p: Vec3f) -> bool {
+        (0..3).all(|i| p[i] >= self.min[i] && p[i] <= self.max[i])
+    }
+
     pub fn enlarge(&self, p: Vec3f) -> Bounds3f {
         Bounds3f {
             min: self.min.min(p),
@@ -71,6 +104,68 @@ impl Bounds3f {
         }
     }
 
+    /// distance from p to nearest point inside, 0 if p is inside
+    pub fn distance(&self, p: Vec3f) -> f32 {
+        (0..3)
+            .map(|i| {
+                let d = (self.min[i] - p[i]).max(p[i] - self.max[i]).max(0.);
+                d * d
+            })
+            .sum::<f32>()
+            .sqrt()
+    }
+
+    /// corner c of 8, bit i selects max on axis i
+    fn corner(&self, c: usize) -> Vec3f {
+        Vec3f::vec(std::array::from_fn(|i| {
+            if c & (1 << i) == 0 {
+                self.min[i]
+            } else {
+                self.max[i]
+            }
+        }))
+    }
+
+    /// world bounds of object bounds, encloses all 8 transformed corners
+    pub fn transform(&self, t: &Transform) -> Bounds3f {
+        (0..8).fold(Bounds3f::empty(), |acc, c| {
+            acc.enlarge(t.point(self.corner(c)))
+        })
+    }
+
+    /// world bounds over whole motion, transformed at steps between keys.
+    /// between steps a point strays from the chord by at most
+    /// its scaled radius times 2 sin(step angle / 4), added as padding.
+    /// steps are taken every 0.02 radian, so padding stays under 1% of radius
+    pub fn transform_motion(&self, motion: &MotionTransform) -> Bounds3f {
+        let keys = motion.keys();
+        let mut bounds = self.transform(&keys[0].1);
+
+        for w in keys.windows(2) {
+            let ((t0, a), (t1, b)) = (w[0], w[1]);
+            // without rotation corners move linearly
+            let angle = a.rot.angle(&b.rot);
+            let steps = ((angle / 0.02).ceil() as usize).max(1);
+            // scale is linear, so farthest corner is farthest at a key
+            let r = (0..8)
+                .flat_map(|c| {
+                    [
+                        a.vector(self.corner(c)).norm(),
+                        b.vector(self.corner(c)).norm(),
+                    ]
+                })
+                .fold(0f32, f32::max);
+            let pad = Vec3f::vec([r * 2. * (angle / steps as f32 / 4.).sin(); 3]);
+
+            for s in 0..=steps {
+                let time = t0 + (t1 - t0) * s as f32 / steps as f32;
+                let step = self.transform(&motion.at(time));
+                bounds = bounds.union(Bounds3f::new(step.min - pad, step.max + pad));
+            }
+        }
+        bounds
+    }
+
     pub fn area(&self) -> f32 {
         let d = self.diagonal();
         let x = d[0];
@@ -86,34 +181,31 @@ impl Default for Bounds3f {
     }
 }
 
-impl Raycast for Bounds3f {
-    fn raycast(&self, ray: &Ray) -> Option<Hit> {
-        //TODO: branch free testing
-        let (mut t0, mut t1) = (0f32, ray.t_max);
+impl Bounds3f {
+    /// branch free slab test, returns (entry t, exit t) within [0, t_max].
+    /// exit t is enlarged by 2 gamma(3) to cover rounding of slab distances,
+    /// so rays grazing a face are kept, pbrt 6.8.2
+    #[inline]
+    pub fn raycast_inv(&self, ray: &InvRay, t_max: f32) -> Option<(f32, f32)> {
+        let slabs = [self.min, self.max];
+        let robust = 1. + 2. * gamma(3);
+        let (mut t0, mut t1) = (0f32, t_max);
         for i in 0..3 {
-            let inv_dir = 1. / ray.dir[i];
-            // inside axis aligned plane x = x0, t = (x0-org_x)/dir_x
-            let mut tnear = (self.min[i] - ray.org[i]) * inv_dir;
-            let mut tfar = (self.max[i] - ray.org[i]) * inv_dir;
-            if tnear > tfar {
-                mem::swap(&mut tnear, &mut tfar);
-            }
-
-            // robust intersect
-            // TODO: explain
-            tfar *= 1. + 2. * gamma(3);
-
-            //NaN still works
-            t0 = if tnear > t0 { tnear } else { t0 };
-            t1 = if tfar < t1 { tfar } else { t1 };
-            if t0 > t1 {
-                return None;
-            }
+            let tnear = (slabs[ray.neg[i]][i] - ray.org[i]) * ray.inv_dir[i];
+            let tfar = (slabs[1 - ray.neg[i]][i] - ray.org[i]) * ray.inv_dir[i] * robust;
+            // 0 * inf of origin on slab gives NaN, f32::max and f32::min drop it
+            t0 = t0.max(tnear);
+            t1 = t1.min(tfar);
         }
+        (t0 <= t1).then_some((t0, t1))
+    }
+}
+
+impl Raycast for Bounds3f {
+    fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        let (t0, t1) = self.raycast_inv(&InvRay::new(ray), ray.t_max)?;
         // if org_x = x0, not intersect at x0
-        Some(Hit {
-            t: if t0 > 0. { t0 } else { t1 },
-        })
+        Some(Hit::new(if t0 > 0. { t0 } else { t1 }))
     }
 }
 
@@ -133,6 +225,75 @@ fn test_bounds() {
     assert_eq!(b.centroid()[0], 0.);
 }
 
+#[test]
+fn test_motion_bounds() {
+    use crate::core::quaternion::Quat;
+    use rand::Rng;
+
+    let b = Bounds3f::new(Vec3f::vec([-1., -0.5, -2.]), Vec3f::vec([2., 1., 0.5]));
+    let y = Vec3f::vec([0., 1., 0.]);
+    let motion = MotionTransform::new(vec![
+        (0., Transform::identity()),
+        (
+            0.5,
+            Transform::new(
+                Vec3f::vec([3., 0., 1.]),
+                Quat::angle_axis(170., y),
+                Vec3f::vec([1.; 3]),
+            ),
+        ),
+        (
+            1.,
+            Transform::new(
+                Vec3f::vec([6., 2., 1.]),
+                Quat::angle_axis(-60., Vec3f::vec([1., 1., 0.])),
+                Vec3f::vec([2.; 3]),
+            ),
+        ),
+    ]);
+    let mb = b.transform_motion(&motion);
+
+    // every point of box at any time inside
+    let mut rng = rand::rng();
+    for _ in 0..4096 {
+        let p = Vec3f::vec(std::array::from_fn(|i| {
+            rng.random_range(b.min[i]..=b.max[i])
+        }));
+        let t = motion.at(rng.random_range(0. ..1.));
+        assert!(mb.contains(t.point(p)));
+    }
+    for k in motion.keys() {
+        let kb = b.transform(&k.1);
+        assert!(mb.contains(kb.min) && mb.contains(kb.max));
+    }
+
+    // padding stays small against densely swept corners
+    let swept = (0..=4096).fold(Bounds3f::empty(), |acc, i| {
+        let t = motion.at(i as f32 / 4096.);
+        (0..8).fold(acc, |acc, c| acc.enlarge(t.point(b.corner(c))))
+    });
+    for i in 0..3 {
+        assert!(swept.min[i] - mb.min[i] < 0.1 && mb.max[i] - swept.max[i] < 0.1);
+    }
+    let fixed = b.transform_motion(&MotionTransform::fixed(Transform::translate(y)));
+    assert_eq!(fixed, b.transform(&Transform::translate(y)));
+}
+
+#[test]
+fn test_intersect_bounds() {
+    let b0 = Bounds3f::new(Vec3f::vec([-1.; 3]), Vec3f::vec([1.; 3]));
+    let b1 = Bounds3f::new(Vec3f::vec([0.; 3]), Vec3f::vec([2.; 3]));
+    let b = b0.intersect(&b1);
+    assert_eq!(b, Bounds3f::new(Vec3f::vec([0.; 3]), Vec3f::vec([1.; 3])));
+    assert!(b.contains(Vec3f::vec([0.5; 3])));
+    assert!(!b.contains(Vec3f::vec([1.5; 3])));
+
+    let b2 = Bounds3f::new(Vec3f::vec([3.; 3]), Vec3f::vec([4.; 3]));
+    assert!(b0.intersect(&b2).is_empty());
+    assert!(Bounds3f::empty().is_empty());
+    assert!(!b0.is_empty());
+}
+
 #[test]
 fn test_hit_bounds() {
     let e = 1e-4;
@@ -168,3 +329,85 @@ fn test_hit_bounds() {
     let h = b.raycast(&ray);
     assert!(h.is_none());
 }
+
+#[test]
+fn test_slab_perf() {
+    use rand::Rng;
+    use std::time::Instant;
+
+    // slab test before precomputing, 1/dir per axis and swap branch
+    fn raycast_swap(b: &Bounds3f, ray: &Ray) -> Option<(f32, f32)> {
+        let (mut t0, mut t1) = (0f32, ray.t_max);
+        for i in 0..3 {
+            let inv_dir = 1. / ray.dir[i];
+            let mut tnear = (b.min[i] - ray.org[i]) * inv_dir;
+            let mut tfar = (b.max[i] - ray.org[i]) * inv_dir;
+            if tnear > tfar {
+                std::mem::swap(&mut tnear, &mut tfar);
+            }
+            tfar *= 1. + 2. * gamma(3);
+            t0 = if tnear > t0 { tnear } else { t0 };
+            t1 = if tfar < t1 { tfar } else { t1 };
+            if t0 > t1 {
+                return None;
+            }
+        }
+        Some((t0, t1))
+    }
+
+    let mut rng = rand::rng();
+    let boxes: Vec<Bounds3f> = (0..4096)
+        .map(|_| {
+            let c = Vec3f::vec(std::array::from_fn(|_| rng.random_range(-32.0..32.)));
+            let r = Vec3f::vec(std::array::from_fn(|_| rng.random_range(0.0..4.)));
+            Bounds3f::new(c - r, c + r)
+        })
+        .collect();
+    let mut rays: Vec<Ray> = (0..256)
+        .map(|_| {
+            let org = Vec3f::vec(std::array::from_fn(|_| rng.random_range(-48.0..48.)));
+            let dir = Vec3f::vec(std::array::from_fn(|_| rng.random_range(-1.0..1.)));
+            Ray::new(org, dir)
+        })
+        .collect();
+    // axis parallel rays, with -0 and +0 components, and origin on slabs
+    rays.push(Ray::new(Vec3f::vec([0.; 3]), Vec3f::vec([-0., 0., -1.])));
+    rays.push(Ray::new(Vec3f::vec([0.; 3]), Vec3f::vec([1., 0., -0.])));
+    rays.push(Ray::new(boxes[0].min, Vec3f::vec([0., 1., 0.])));
+
+    for ray in rays.iter() {
+        let inv = InvRay::new(ray);
+        for b in boxes.iter() {
+            assert_eq!(b.raycast_inv(&inv, ray.t_max), raycast_swap(b, ray));
+        }
+    }
+
+    let sw = Instant::now();
+    let mut before = 0;
+    for ray in rays.iter() {
+        before += boxes
+            .iter()
+            .filter(|b| raycast_swap(b, ray).is_some())
+            .count();
+    }
+    let before_ms = sw.elapsed().as_millis();
+
+    let sw = Instant::now();
+    let mut after = 0;
+    for ray in rays.iter() {
+        let inv = InvRay::new(ray);
+        after += boxes
+            .iter()
+            .filter(|b| b.raycast_inv(&inv, ray.t_max).is_some())
+            .count();
+    }
+    let after_ms = sw.elapsed().as_millis();
+
+    assert_eq!(before, after);
+    println!(
+        "{} slab tests, swap {}ms, precomputed branch free {}ms",
+        rays.len() * boxes.len(),
+        before_ms,
+        after_ms
+    );
+}
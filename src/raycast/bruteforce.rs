@@ -0,0 +1,370 @@
+use crate::{
+    core::tensor::Vec3f,
+    raycast::{bounds::Bounds3f, frustum::Frustum, primitive::Primitive, *},
+};
+
+/// tests every primitive, same queries as BVH without building anything.
+/// reference for BVH results and fallback for small scenes
+pub struct BruteForce<T: Primitive> {
+    pub primitives: Vec<T>,
+}
+
+impl<T: Primitive> BruteForce<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            primitives: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn push(&mut self, prim: T) {
+        self.primitives.push(prim);
+    }
+
+    pub fn bounds(&self) -> Bounds3f {
+        self.primitives
+            .iter()
+            .fold(Bounds3f::empty(), |b, p| b.union(p.bounds()))
+    }
+
+    pub fn raycast_node(&self, ray: &Ray) -> Option<(Hit, usize)> {
+        self.any_raycast(ray, |_, _, _| false)
+    }
+
+    /// F (ray,hit, primitve index) -> if skip
+    /// return cloest hit in not skiiped hits
+    pub fn any_raycast<F>(&self, ray: &Ray, mut anyhit: F) -> Option<(Hit, usize)>
+    where
+        F: FnMut(&Ray, Hit, usize) -> bool,
+    {
+        let mut hit = None;
+        let ray = &mut ray.clone();
+        for (prim_i, prim) in self.primitives.iter().enumerate() {
+            if let Some(hit_p) = prim.raycast(ray)
+                && !anyhit(ray, hit_p, prim_i)
+            {
+                ray.t_max = hit_p.t;
+                hit = Some((hit_p, prim_i));
+            }
+        }
+        hit
+    }
+
+    /// any primitive hit within ray segment
+    pub fn occluded(&self, ray: &Ray) -> bool {
+        self.primitives.iter().any(|p| p.raycast(ray).is_some())
+    }
+
+    /// nearest primitive to p by Primitive::distance, returns (distance, primitive index)
+    pub fn closest_point(&self, p: Vec3f) -> Option<(f32, usize)> {
+        self.knn(p, 1, f32::INFINITY).first().copied()
+    }
+
+    /// up to k nearest primitives within max_dist by Primitive::distance,
+    /// returns (distance, primitive index) sorted by distance
+    pub fn knn(&self, p: Vec3f, k: usize, max_dist: f32) -> Vec<(f32, usize)> {
+        let mut found: Vec<(f32, usize)> = self
+            .primitives
+            .iter()
+            .enumerate()
+            .map(|(i, prim)| (prim.distance(p), i))
+            .filter(|f| f.0 <= max_dist)
+            .collect();
+        found.sort_by(|a, b| a.0.total_cmp(&b.0));
+        found.truncate(k);
+        found
+    }
+
+    /// primitives whose bounds intersect frustum, conservative as Frustum::intersects_bounds
+    pub fn query_frustum(&self, frustum: &Frustum) -> Vec<usize> {
+        self.query(|p| frustum.intersects_bounds(&p.bounds()))
+    }
+
+    /// primitives whose bounds overlap bounds
+    pub fn query_aabb(&self, bounds: &Bounds3f) -> Vec<usize> {
+        self.query(|p| !p.bounds().intersect(bounds).is_empty())
+    }
+
+    /// primitives within r of center by Primitive::distance
+    pub fn query_sphere(&self, center: Vec3f, r: f32) -> Vec<usize> {
+        self.query(|p| p.distance(center) <= r)
+    }
+
+    fn query<P: Fn(&T) -> bool>(&self, prim_test: P) -> Vec<usize> {
+        (0..self.primitives.len())
+            .filter(|&i| prim_test(&self.primitives[i]))
+            .collect()
+    }
+}
+
+impl<T: Primitive> Raycast for BruteForce<T> {
+    fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        self.raycast_node(ray).map(|(hit, _)| hit)
+    }
+}
+
+/// ray queries of an accelerator, lets tests check any of them against BruteForce
+#[cfg(test)]
+pub(crate) trait RayAccel {
+    fn raycast_node(&self, ray: &Ray) -> Option<(Hit, usize)>;
+    fn occluded(&self, ray: &Ray) -> bool;
+    fn any_raycast<F: FnMut(&Ray, Hit, usize) -> bool>(
+        &self,
+        ray: &Ray,
+        anyhit: F,
+    ) -> Option<(Hit, usize)>;
+}
+
+#[cfg(test)]
+impl<T: Primitive> RayAccel for crate::raycast::bvh::BVH<T> {
+    fn raycast_node(&self, ray: &Ray) -> Option<(Hit, usize)> {
+        self.raycast_node(ray)
+    }
+    fn occluded(&self, ray: &Ray) -> bool {
+        self.occluded(ray)
+    }
+    fn any_raycast<F: FnMut(&Ray, Hit, usize) -> bool>(
+        &self,
+        ray: &Ray,
+        anyhit: F,
+    ) -> Option<(Hit, usize)> {
+        self.any_raycast(ray, anyhit)
+    }
+}
+
+#[cfg(test)]
+impl<T: Primitive> RayAccel for crate::raycast::grid::UniformGrid<T> {
+    fn raycast_node(&self, ray: &Ray) -> Option<(Hit, usize)> {
+        self.raycast_node(ray)
+    }
+    fn occluded(&self, ray: &Ray) -> bool {
+        self.occluded(ray)
+    }
+    fn any_raycast<F: FnMut(&Ray, Hit, usize) -> bool>(
+        &self,
+        ray: &Ray,
+        anyhit: F,
+    ) -> Option<(Hit, usize)> {
+        self.any_raycast(ray, anyhit)
+    }
+}
+
+/// point with every coordinate in [lo,hi)
+#[cfg(test)]
+pub(crate) fn random_point(rng: &mut rand::rngs::ThreadRng, lo: f32, hi: f32) -> Vec3f {
+    use rand::Rng;
+    Vec3f::vec(std::array::from_fn(|_| rng.random_range(lo..hi)))
+}
+
+/// asserts accel answers rays as brute force over prims, given in accel primitive order.
+/// returns closest hits of accel
+#[cfg(test)]
+pub(crate) fn assert_matches_bruteforce<T: Primitive>(
+    accel: &impl RayAccel,
+    prims: &[T],
+    rays: &[Ray],
+    what: &str,
+) -> Vec<Option<(Hit, usize)>> {
+    let mut oracle = BruteForce::new(prims.len());
+    prims.iter().for_each(|p| oracle.push(p.clone()));
+
+    rays.iter()
+        .map(|ray| {
+            let hit = accel.raycast_node(ray);
+            let expected = oracle.raycast_node(ray);
+            let t = |h: Option<(Hit, usize)>| h.map(|(h, _)| h.t);
+            assert_eq!(t(hit), t(expected), "{what}");
+            // ties may report another primitive, it still has to be hit there
+            if let Some((h, i)) = hit {
+                assert_eq!(prims[i].raycast(ray).map(|p| p.t), Some(h.t), "{what}");
+            }
+            assert_eq!(accel.occluded(ray), expected.is_some(), "{what}");
+
+            // every hit reported once, nothing kept when all are skipped
+            let mut all = Vec::new();
+            let kept = accel.any_raycast(ray, |_, _, i| {
+                all.push(i);
+                true
+            });
+            assert!(kept.is_none(), "{what}");
+            all.sort_unstable();
+            let mut expected_all = Vec::new();
+            oracle.any_raycast(ray, |_, _, i| {
+                expected_all.push(i);
+                true
+            });
+            assert_eq!(all, expected_all, "{what}");
+            hit
+        })
+        .collect()
+}
+
+#[test]
+fn test_bvh_differential() {
+    use crate::core::vec::Vector;
+    use crate::raycast::{
+        bvh::BVH,
+        bvhbuild::{BVHBuilder, HLBVHBuilder, MedianBuilder, SAHBuilder},
+        sbvh::SBVHBuilder,
+        sphere::Sphere,
+        triangle::Triangle,
+        widebvh::BVHWidth,
+    };
+    use crate::render::camera::Camera;
+    use crate::splat::{gaussian::Gaussian, io::RawGaussian};
+    use rand::{Rng, rngs::ThreadRng};
+
+    // scenes fill [0,64]^3, queries come from around it.
+    // every builder and width against brute force over same primitive order
+    fn check<T: Primitive>(name: &str, prims: &[T], rng: &mut ThreadRng) {
+        // origins outside scene, some rays cut short
+        let rays: Vec<Ray> = (0..128)
+            .map(|i| {
+                let org = Vec3f::vec([32.; 3]) + random_point(rng, -1., 1.).normalize() * 80.;
+                let dir = random_point(rng, 0., 64.) - org;
+                let t_max = if i % 4 == 0 {
+                    rng.random_range(0.5..1.)
+                } else {
+                    f32::MAX
+                };
+                Ray::segment(org, dir, t_max)
+            })
+            .collect();
+        let points: Vec<Vec3f> = (0..64).map(|_| random_point(rng, -8., 72.)).collect();
+        let boxes: Vec<Bounds3f> = (0..16)
+            .map(|_| {
+                let (c, r) = (random_point(rng, 0., 64.), random_point(rng, 0., 8.));
+                Bounds3f::new(c - r, c + r)
+            })
+            .collect();
+        let cam = Camera::new(
+            Vec3f::vec([32., 32., 96.]),
+            Vec3f::vec([0., 0., -1.]),
+            30.,
+            1.,
+            128.,
+        );
+        let frustum = cam.frustum((16, 9));
+
+        fn sorted(mut v: Vec<usize>) -> Vec<usize> {
+            v.sort_unstable();
+            v.dedup();
+            v
+        }
+
+        fn run<T: Primitive, B: BVHBuilder>(
+            name: &str,
+            prims: &[T],
+            builder: &B,
+            rays: &[Ray],
+            points: &[Vec3f],
+            boxes: &[Bounds3f],
+            frustum: &Frustum,
+        ) {
+            let mut bvh = BVH::new(prims.len());
+            prims.iter().for_each(|p| bvh.push(p.clone()));
+            bvh.build_with(builder, 5);
+            // oracle over reordered primitives, so indices agree
+            let mut oracle = BruteForce::new(prims.len());
+            bvh.primitives.iter().for_each(|p| oracle.push(p.clone()));
+
+            for width in [BVHWidth::Binary, BVHWidth::Four, BVHWidth::Eight] {
+                bvh.collapse(width);
+                let what = format!("{name} {} {width:?}", builder.describe());
+
+                assert_matches_bruteforce(&bvh, &oracle.primitives, rays, &what);
+
+                for &p in points.iter() {
+                    let d =
+                        |found: Vec<(f32, usize)>| found.iter().map(|f| f.0).collect::<Vec<_>>();
+                    assert_eq!(
+                        bvh.closest_point(p).map(|c| c.0),
+                        oracle.closest_point(p).map(|c| c.0),
+                        "{what}"
+                    );
+                    assert_eq!(
+                        d(bvh.knn(p, 8, f32::INFINITY)),
+                        d(oracle.knn(p, 8, f32::INFINITY))
+                    );
+                    assert_eq!(d(bvh.knn(p, 32, 4.)), d(oracle.knn(p, 32, 4.)), "{what}");
+                    assert_eq!(sorted(bvh.query_sphere(p, 6.)), oracle.query_sphere(p, 6.));
+                }
+
+                // spatial splits bound clipped parts, so bounds overlapped only
+                // where the primitive is not may be left out
+                let subset = |found: &[usize], expected: &[usize]| {
+                    if bvh.split_refs {
+                        found.iter().all(|i| expected.contains(i))
+                    } else {
+                        found == expected
+                    }
+                };
+                for b in boxes.iter() {
+                    let found = sorted(bvh.query_aabb(b));
+                    let expected = oracle.query_aabb(b);
+                    assert!(subset(&found, &expected), "{what}");
+                    for i in expected {
+                        if !bvh.primitives[i].clip_bounds(b).is_empty() {
+                            assert!(found.contains(&i), "{what}");
+                        }
+                    }
+                }
+                let found = sorted(bvh.query_frustum(frustum));
+                assert!(subset(&found, &oracle.query_frustum(frustum)), "{what}");
+            }
+        }
+
+        // parallel builds match sequential ones, see test_bvh_deterministic
+        let args = (
+            rays.as_slice(),
+            points.as_slice(),
+            boxes.as_slice(),
+            &frustum,
+        );
+        let (r, p, b, f) = args;
+        let par_build = true;
+        run(name, prims, &HLBVHBuilder { par_build }, r, p, b, f);
+        run(name, prims, &SAHBuilder { par_build }, r, p, b, f);
+        run(name, prims, &MedianBuilder { par_build }, r, p, b, f);
+        run(name, prims, &SBVHBuilder::default(), r, p, b, f);
+    }
+
+    let n = 512;
+    let mut rng = rand::rng();
+
+    let spheres: Vec<Sphere> = (0..n)
+        .map(|_| Sphere::new(random_point(&mut rng, 0., 64.), rng.random_range(0.1..2.)))
+        .collect();
+    check("spheres", &spheres, &mut rng);
+
+    let gaussians: Vec<Gaussian> = (0..n)
+        .map(|_| {
+            let rot: [f32; 4] = std::array::from_fn(|_| rng.random_range(-1.0..1.));
+            let len = rot.iter().map(|x| x * x).sum::<f32>().sqrt();
+            let raw = RawGaussian {
+                pos: std::array::from_fn(|_| rng.random_range(0.0..64.)),
+                scale: std::array::from_fn(|_| rng.random_range(0.05f32..4.).ln()),
+                rot: rot.map(|x| x / len),
+                ..Default::default()
+            };
+            Gaussian::from_input(&raw)
+        })
+        .collect();
+    check("gaussians", &gaussians, &mut rng);
+
+    // large thin triangles, spatial splits cut them
+    let triangles: Vec<Triangle> = (0..n)
+        .map(|_| {
+            let p0 = random_point(&mut rng, 0., 64.);
+            let p1 = p0 + random_point(&mut rng, -16., 16.);
+            let p2 = p0 + random_point(&mut rng, -2., 2.);
+            Triangle::new(p0, p1, p2)
+        })
+        .collect();
+    check("triangles", &triangles, &mut rng);
+
+    let empty: BruteForce<Sphere> = BruteForce::new(0);
+    let ray = Ray::new(Vec3f::vec([0.; 3]), Vec3f::vec([1., 0., 0.]));
+    assert!(empty.raycast(&ray).is_none());
+    assert!(!empty.occluded(&ray));
+    assert!(empty.closest_point(ray.org).is_none());
+}
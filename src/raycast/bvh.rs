@@ -1,6 +1,9 @@
-use crate::raycast::{bounds::Bounds3f, primitive::Primitive, *};
+use crate::raycast::{
+    bounds::Bounds3f, bvhbuild::TRAVERSAL_COST, bvhstats::TraversalCounter, bvhupdate::UpdateState,
+    primitive::Primitive, stack::TraversalStack, widebvh::WideNodes, *,
+};
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct LinearBVHNode {
     pub bounds: Bounds3f,
     pub axis: usize,
@@ -19,6 +22,26 @@ pub struct BVH<T: Primitive> {
     pub node_prims_limit: usize, // max primitives a node can include
     pub primitives: Vec<T>,
     pub nodes: Vec<LinearBVHNode>,
+    /// primitive index of every leaf slot, empty if primitives are reordered instead
+    pub prim_refs: Vec<usize>,
+    /// index before build of every reordered primitive, empty if primitives were not reordered
+    pub prim_order: Vec<usize>,
+    /// BVHBuilder::describe of last build
+    pub builder: String,
+    /// leaves may reference same primitive, built with spatial splits
+    pub split_refs: bool,
+    /// sah cost after last full build
+    pub build_cost: f32,
+    /// root area after last full build, build_cost is relative to it
+    pub build_area: f32,
+    /// collapsed nodes, traversed instead of binary nodes if not empty
+    pub wide: WideNodes,
+    /// primitives with NaN or infinite bounds, left out of tree
+    pub invalid_prims: Vec<usize>,
+    /// invalid primitives that are Primitive::unbounded, traced next to the tree
+    pub unbounded_prims: Vec<usize>,
+    /// links kept by insert and remove, reset by builds
+    pub(crate) update: UpdateState,
 }
 
 impl<T: Primitive> BVH<T> {
@@ -27,6 +50,16 @@ impl<T: Primitive> BVH<T> {
             node_prims_limit: 65,
             primitives: Vec::with_capacity(capacity),
             nodes: Vec::new(),
+            prim_refs: Vec::new(),
+            prim_order: Vec::new(),
+            builder: String::new(),
+            split_refs: false,
+            build_cost: 0.,
+            build_area: 0.,
+            wide: WideNodes::None,
+            invalid_prims: Vec::new(),
+            unbounded_prims: Vec::new(),
+            update: UpdateState::default(),
         }
     }
 
@@ -42,113 +75,207 @@ impl<T: Primitive> BVH<T> {
         }
     }
 
+    /// primitive index of leaf slot
+    #[inline]
+    pub fn prim_index(&self, slot: usize) -> usize {
+        if self.prim_refs.is_empty() {
+            slot
+        } else {
+            self.prim_refs[slot]
+        }
+    }
+
+    /// a primitive referenced by several leaves is reported only on its first hit.
+    /// hit points of grazing rays may fall outside every leaf, so leaves can not own hits
+    #[inline]
+    pub(crate) fn first_report(&self, reported: &mut Vec<usize>, prim_i: usize) -> bool {
+        if !self.split_refs {
+            return true;
+        }
+        if reported.contains(&prim_i) {
+            return false;
+        }
+        reported.push(prim_i);
+        true
+    }
+
+    /// expected cost of tracing a ray through the tree by surface area heuristic,
+    /// relative to intersecting one primitive. lower is better
+    pub fn sah_cost(&self) -> f32 {
+        if self.nodes.is_empty() {
+            return 0.;
+        }
+
+        // nodes left unreachable by updates are skipped
+        let mut cost = 0.;
+        let mut nodes_to_visit = vec![0];
+        while let Some(i) = nodes_to_visit.pop() {
+            let node = &self.nodes[i];
+            let c = if node.is_leaf() {
+                node.nprimitives as f32
+            } else {
+                nodes_to_visit.push(node.offset);
+                nodes_to_visit.push(i + 1);
+                TRAVERSAL_COST
+            };
+            cost += c * node.bounds.area();
+        }
+
+        let root_area = self.nodes[0].bounds.area();
+        if root_area > 0. {
+            cost / root_area
+        } else {
+            cost
+        }
+    }
+
     pub fn raycast_node(&self, ray: &Ray) -> Option<(Hit, usize)> {
-        let mut hit: Option<(Hit, usize)> = None;
+        self.traverse(ray, false, false, |_, _, _| false, &mut ())
+    }
 
-        let mut cur_node_i = 0;
-        let mut to_visit_i = 0;
-        let mut nodes_to_visit = [0; 64];
+    /// F (ray,hit, primitve index) -> if skip
+    /// return cloest hit in not skiiped hits
+    pub fn any_raycast<F>(&self, ray: &Ray, anyhit: F) -> Option<(Hit, usize)>
+    where
+        F: FnMut(&Ray, Hit, usize) -> bool,
+    {
+        self.traverse(ray, true, false, anyhit, &mut ())
+    }
 
-        let ray = &mut ray.clone();
+    /// any primitive hit within ray segment, stops at first hit found
+    pub fn occluded(&self, ray: &Ray) -> bool {
+        self.traverse(ray, false, true, |_, _, _| false, &mut ())
+            .is_some()
+    }
 
-        loop {
-            let node = &self.nodes[cur_node_i];
-            if node.bounds.raycast(ray).is_some() {
-                if node.is_leaf() {
-                    // cast ray with primitives
-                    for i in 0..node.nprimitives {
-                        if let Some(hit_p) = self.primitives[node.offset + i].raycast(ray) {
-                            //update t_max to find nearest primitive
-                            ray.t_max = hit_p.t;
-                            hit = Some((hit_p, node.offset + i));
-                        }
-                    }
-                    if to_visit_i == 0 {
-                        break;
-                    }
+    /// every hit within ray segment in increasing t, F (hit, primitive index) -> if stop.
+    /// all hits are collected before the first is handed out
+    pub fn ordered_hits<F>(&self, ray: &Ray, mut f: F)
+    where
+        F: FnMut(Hit, usize) -> bool,
+    {
+        let mut hits = Vec::new();
+        self.any_raycast(ray, |_, hit, prim_i| {
+            hits.push((hit, prim_i));
+            true
+        });
+        hits.sort_by(|a, b| a.0.t.total_cmp(&b.0.t));
+        for (hit, prim_i) in hits {
+            if f(hit, prim_i) {
+                break;
+            }
+        }
+    }
 
-                    cur_node_i = nodes_to_visit[to_visit_i - 1];
-                    to_visit_i -= 1;
-                } else {
-                    // not leaf, put far BVH node on nodes_to_visit stack, advance to near node
-                    if ray.dir[node.axis] < 0. {
-                        // far node is left
-                        nodes_to_visit[to_visit_i] = cur_node_i + 1;
-                        cur_node_i = node.offset;
-                    } else {
-                        // far node is right
-                        nodes_to_visit[to_visit_i] = node.offset;
-                        cur_node_i += 1;
-                    }
-                    to_visit_i += 1;
-                }
-            } else {
-                // not hit
-                if to_visit_i == 0 {
-                    break;
+    /// unique: report referenced primitive once, see first_report.
+    /// first: return first accepted hit instead of nearest
+    pub(crate) fn traverse<F, C>(
+        &self,
+        ray: &Ray,
+        unique: bool,
+        first: bool,
+        mut anyhit: F,
+        counter: &mut C,
+    ) -> Option<(Hit, usize)>
+    where
+        F: FnMut(&Ray, Hit, usize) -> bool,
+        C: TraversalCounter,
+    {
+        // unbounded primitives first, their hit shortens the ray through the tree
+        let mut hit: Option<(Hit, usize)> = None;
+        let ray = &mut ray.clone();
+        for &prim_i in self.unbounded_prims.iter() {
+            counter.prim();
+            if let Some(hit_p) = self.primitives[prim_i].raycast(ray)
+                && !anyhit(ray, hit_p, prim_i)
+            {
+                ray.t_max = hit_p.t;
+                hit = Some((hit_p, prim_i));
+                if first {
+                    return hit;
                 }
-                cur_node_i = nodes_to_visit[to_visit_i - 1];
-                to_visit_i -= 1;
             }
         }
 
-        hit
+        let anyhit = &mut anyhit;
+        let tree_hit = match &self.wide {
+            WideNodes::Four(nodes) => self.raycast_wide(nodes, ray, unique, first, anyhit, counter),
+            WideNodes::Eight(nodes) => {
+                self.raycast_wide(nodes, ray, unique, first, anyhit, counter)
+            }
+            WideNodes::None => self.raycast_binary(ray, unique, first, anyhit, counter),
+        };
+        tree_hit.or(hit)
     }
 
-    /// F (ray,hit, primitve index) -> if skip
-    /// return cloest hit in not skiiped hits
-    pub fn any_raycast<F>(&self, ray: &Ray, mut anyhit: F) -> Option<(Hit, usize)>
+    fn raycast_binary<F, C>(
+        &self,
+        ray: &Ray,
+        unique: bool,
+        first: bool,
+        mut anyhit: F,
+        counter: &mut C,
+    ) -> Option<(Hit, usize)>
     where
         F: FnMut(&Ray, Hit, usize) -> bool,
+        C: TraversalCounter,
     {
         let mut hit: Option<(Hit, usize)> = None;
+        if self.nodes.is_empty() {
+            return hit;
+        }
 
         let mut cur_node_i = 0;
-        let mut to_visit_i = 0;
-        let mut nodes_to_visit = [0; 64];
+        let mut nodes_to_visit: TraversalStack<usize> = TraversalStack::new();
+        let mut reported = Vec::new();
 
         let ray = &mut ray.clone();
+        let inv = InvRay::new(ray);
 
         loop {
             let node = &self.nodes[cur_node_i];
-            if node.bounds.raycast(ray).is_some() {
+            counter.node();
+            if node.bounds.raycast_inv(&inv, ray.t_max).is_some() {
                 if node.is_leaf() {
                     // cast ray with primitives
                     for i in 0..node.nprimitives {
-                        if let Some(hit_p) = self.primitives[node.offset + i].raycast(ray) {
+                        let prim_i = self.prim_index(node.offset + i);
+                        counter.prim();
+                        if let Some(hit_p) = self.primitives[prim_i].raycast(ray)
+                            && (!unique || self.first_report(&mut reported, prim_i))
                             // if not skip use hit t limit tmax
-                            if !anyhit(ray, hit_p, node.offset + i) {
-                                ray.t_max = hit_p.t;
-                                hit = Some((hit_p, node.offset + i));
+                            && !anyhit(ray, hit_p, prim_i)
+                        {
+                            //update t_max to find nearest primitive
+                            ray.t_max = hit_p.t;
+                            hit = Some((hit_p, prim_i));
+                            if first {
+                                return hit;
                             }
                         }
                     }
-                    if to_visit_i == 0 {
+                    let Some(next) = nodes_to_visit.pop() else {
                         break;
-                    }
-
-                    cur_node_i = nodes_to_visit[to_visit_i - 1];
-                    to_visit_i -= 1;
+                    };
+                    cur_node_i = next;
                 } else {
                     // not leaf, put far BVH node on nodes_to_visit stack, advance to near node
-                    if ray.dir[node.axis] < 0. {
+                    if inv.neg[node.axis] == 1 {
                         // far node is left
-                        nodes_to_visit[to_visit_i] = cur_node_i + 1;
+                        nodes_to_visit.push(cur_node_i + 1);
                         cur_node_i = node.offset;
                     } else {
                         // far node is right
-                        nodes_to_visit[to_visit_i] = node.offset;
+                        nodes_to_visit.push(node.offset);
                         cur_node_i += 1;
                     }
-                    to_visit_i += 1;
                 }
             } else {
                 // not hit
-                if to_visit_i == 0 {
+                let Some(next) = nodes_to_visit.pop() else {
                     break;
-                }
-                cur_node_i = nodes_to_visit[to_visit_i - 1];
-                to_visit_i -= 1;
+                };
+                cur_node_i = next;
             }
         }
 
@@ -171,32 +298,32 @@ fn test_bvh_order() {
     use crate::raycast::sphere::Sphere;
     use rand::seq::SliceRandom;
     /*
-     if n > 1024, test might fail since more than one points can map to same code,
-    radix_sort use a 30bit 10bit-per axis setting, meaning only 1024 sperated points
-    for each axis.
+     30-bit morton codes only separate 1024 points per axis,
+     builds over more than 1024 primitives switch to 63-bit codes.
     */
-    let n = 1024;
-    let node_limit = 17;
+    for n in [1024, 8192] {
+        let node_limit = 17;
 
-    let mut bvh = BVH::new(n);
-    let mut arr: Vec<usize> = (0..n).collect();
-    let mut rng = rand::rng();
-    arr.shuffle(&mut rng);
-    for &i in arr.iter() {
-        let sph = Sphere::new(Vec3f::vec([i as f32 + 0.5; 3]), 0.5);
-        bvh.push(sph);
-    }
+        let mut bvh = BVH::new(n);
+        let mut arr: Vec<usize> = (0..n).collect();
+        let mut rng = rand::rng();
+        arr.shuffle(&mut rng);
+        for &i in arr.iter() {
+            let sph = Sphere::new(Vec3f::vec([i as f32 + 0.5; 3]), 0.5);
+            bvh.push(sph);
+        }
 
-    // sequential build , all primitives are sequentially ordered
-    bvh.build(node_limit, false);
+        // sequential build , all primitives are sequentially ordered
+        bvh.build(node_limit, false);
 
-    for i in 1..n {
-        let b1 = bvh.primitives[i].bounds();
-        let b0 = bvh.primitives[i - 1].bounds();
-        if b1.min[0] < b0.min[0] {
-            panic!("b1:{} < b0:{}", i, i - 1);
+        for i in 1..n {
+            let b1 = bvh.primitives[i].bounds();
+            let b0 = bvh.primitives[i - 1].bounds();
+            if b1.min[0] < b0.min[0] {
+                panic!("n {}, b1:{} < b0:{}", n, i, i - 1);
+            }
+            assert!(b1.min[0] >= b0.min[0])
         }
-        assert!(b1.min[0] >= b0.min[0])
     }
 }
 
@@ -223,7 +350,7 @@ fn test_bvh_nodes() {
     let mut start = 0;
     while start < bvh.nodes.len() - 1 {
         let c = &bvh.nodes[start];
-        let mut b = Bounds3f::default();
+        let mut b = Bounds3f::empty();
         if c.is_leaf() {
             for i in 0..c.nprimitives {
                 let cb = bvh.primitives[c.offset + i].bounds();
@@ -287,16 +414,73 @@ fn test_bvh_cast() {
 
     let diag_ray = Ray::new(Vec3f::vec([0.; 3]), Vec3f::vec([1.; 3]));
 
+    // accepted hits limit t_max, so only skipping every hit reports all spheres on diagonal
     let mut hits = 0;
-    bvh.any_raycast(&diag_ray, |_r, _hit, _i| {
+    let hit = bvh.any_raycast(&diag_ray, |_r, _hit, _i| {
         hits += 1;
-        if hits == 3 {
-            return true;
-        }
-        false
+        true
     });
 
-    assert_eq!(hits, 3);
+    assert!(hit.is_none());
+    assert_eq!(hits, n);
+
+    let (hit, i) = bvh.any_raycast(&diag_ray, |_, _, _| false).unwrap();
+    assert_eq!(bvh.primitives[i].cnt[0], 0.5);
+    assert_eq!(Some(hit.t), bvh.raycast(&diag_ray).map(|h| h.t));
+}
+
+#[test]
+fn test_bvh_deep_traversal() {
+    use crate::raycast::{sphere::Sphere, widebvh::BVHWidth};
+
+    // caterpillar tree, every interior node has a leaf and a deeper interior child.
+    // ray along -x pushes every leaf, far deeper than inline stack
+    let n = 300;
+    let mut bvh = BVH::new(n);
+    for i in 0..n {
+        bvh.push(Sphere::new(Vec3f::vec([i as f32, 0., 0.]), 0.25));
+    }
+
+    for k in 0..n {
+        let bounds = (k..n).fold(Bounds3f::empty(), |acc, i| {
+            acc.union(bvh.primitives[i].bounds())
+        });
+        let leaf = LinearBVHNode {
+            bounds: bvh.primitives[k].bounds(),
+            axis: 0,
+            nprimitives: 1,
+            offset: k,
+        };
+        if k == n - 1 {
+            bvh.nodes.push(leaf);
+        } else {
+            bvh.nodes.push(LinearBVHNode {
+                bounds,
+                axis: 0,
+                nprimitives: 0,
+                offset: 2 * k + 2,
+            });
+            bvh.nodes.push(leaf);
+        }
+    }
+
+    let ray = Ray::new(
+        Vec3f::vec([n as f32 + 1., 0., 0.]),
+        Vec3f::vec([-1., 0., 0.]),
+    );
+    for width in [BVHWidth::Binary, BVHWidth::Four, BVHWidth::Eight] {
+        bvh.collapse(width);
+        let (hit, i) = bvh.raycast_node(&ray).unwrap();
+        assert_eq!(i, n - 1);
+        assert_eq!(hit.t, 1.75);
+
+        let mut hits = 0;
+        bvh.any_raycast(&ray, |_, _, _| {
+            hits += 1;
+            true
+        });
+        assert_eq!(hits, n);
+    }
 }
 
 #[test]
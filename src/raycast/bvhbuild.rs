@@ -1,23 +1,28 @@
 use crate::raycast::bvh::{BVH, LinearBVHNode};
+use crate::raycast::bvhupdate::UpdateState;
+use crate::raycast::widebvh::WideNodes;
 use crate::{
-    core::math::split_index,
+    core::{math::split_index, tensor::Vec3f},
     raycast::{
         bounds::Bounds3f,
-        morton::{MortonCode, encode_morton3, radix_sort},
+        morton::{MortonCode, encode_morton3, morton_bits, radix_sort},
         primitive::Primitive,
     },
 };
 use rayon::prelude::*;
-use std::sync::{
-    Arc, Mutex,
-    atomic::{AtomicUsize, Ordering::Relaxed},
-};
+use std::sync::{Arc, Mutex};
 
 ///how many splits in sah building, efficient setting
-const N_BUCKETS: usize = 12;
+pub(crate) const N_BUCKETS: usize = 12;
+
+/// cost of traversing a node relative to intersecting a primitive
+pub(crate) const TRAVERSAL_COST: f32 = 0.125;
+
+/// subtrees larger than this are built on separate threads
+pub(crate) const PAR_BUILD_SIZE: usize = 4096;
 
 #[derive(Default)]
-struct BVHBuildNode {
+pub struct BVHBuildNode {
     bounds: Bounds3f,
     axis: usize,
     prim_offset: usize,
@@ -46,16 +51,132 @@ impl BVHBuildNode {
         self.c0 = Some(c0);
         self.c1 = Some(c1);
     }
+
+    /// number leaves of a not shared tree in depth first order,
+    /// for builders not knowing offsets until subtrees are done
+    pub(crate) fn assign_offsets(node: &mut Arc<BVHBuildNode>, next: &mut usize) {
+        let node = Arc::get_mut(node).expect("build node is shared");
+        if node.nprimitives > 0 {
+            node.prim_offset = *next;
+            *next += node.nprimitives;
+            return;
+        }
+
+        if let Some(c0) = &mut node.c0 {
+            Self::assign_offsets(c0, next);
+        }
+        if let Some(c1) = &mut node.c1 {
+            Self::assign_offsets(c1, next);
+        }
+    }
+}
+
+/// binary tree created by a builder, flattened by BVH
+pub struct BuildTree {
+    pub root: Arc<BVHBuildNode>,
+    pub total_nodes: usize,
+    /// original primitive index at each ordered position, leaf offsets index this order
+    pub prim_order: Vec<usize>,
+    /// prim_order may repeat primitives, kept as references instead of reordering primitives
+    pub references: bool,
+}
+
+/// strategy building binary tree over primitives
+pub trait BVHBuilder {
+    /// primitives are not empty and have finite bounds, node_prims_limit is at least 2.
+    /// leaves contain less than node_prims_limit primitives unless they can not be split
+    fn build_tree<T: Primitive>(&self, primitives: &[T], node_prims_limit: usize) -> BuildTree;
+
+    /// builder and parameters shaping the tree, cached trees are reused only if equal
+    fn describe(&self) -> String;
+}
+
+/// LBVH treelets on morton codes, SAH over treelet roots. fastest build
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HLBVHBuilder {
+    pub par_build: bool,
+}
+
+/// top-down binned SAH over all primitives. slower build, best trace quality
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SAHBuilder {
+    pub par_build: bool,
+}
+
+/// split at object median of largest centroid extent
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MedianBuilder {
+    pub par_build: bool,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct BVHSplitBucket {
+    pub count: usize,
+    pub bounds: Bounds3f,
+}
+
+impl BVHSplitBucket {
+    pub fn empty() -> Self {
+        BVHSplitBucket {
+            count: 0,
+            bounds: Bounds3f::empty(),
+        }
+    }
+}
+
+/// returns bucket to split after and SAH cost of that split
+pub(crate) fn min_split_cost(
+    buckets: &[BVHSplitBucket; N_BUCKETS],
+    bounds: &Bounds3f,
+) -> (usize, f32) {
+    // sweep from both sides, area of empty side not counted
+    let mut cost = [0f32; N_BUCKETS - 1];
+    let (mut b0, mut c0) = (Bounds3f::empty(), 0);
+    for i in 0..N_BUCKETS - 1 {
+        b0 = b0.union(buckets[i].bounds);
+        c0 += buckets[i].count;
+        if c0 > 0 {
+            cost[i] += c0 as f32 * b0.area();
+        }
+    }
+
+    let (mut b1, mut c1) = (Bounds3f::empty(), 0);
+    for i in (1..N_BUCKETS).rev() {
+        b1 = b1.union(buckets[i].bounds);
+        c1 += buckets[i].count;
+        if c1 > 0 {
+            cost[i - 1] += c1 as f32 * b1.area();
+        }
+    }
+
+    let (min_cost_index, min_cost) = cost.iter().enumerate().fold(
+        (0, f32::INFINITY),
+        |(im, m), (i, &c)| {
+            if c < m { (i, c) } else { (im, m) }
+        },
+    );
+
+    let area = bounds.area();
+    let min_cost = if area > 0. { min_cost / area } else { 0. };
+    (min_cost_index, TRAVERSAL_COST + min_cost)
+}
+
+/// primitive bounds cached for top-down builders, a clipped reference in spatial splits
+#[derive(Clone, Copy)]
+pub(crate) struct BuildPrim {
+    pub index: usize,
+    pub bounds: Bounds3f,
+    pub centroid: Vec3f,
 }
 
 #[derive(Default, Clone)]
 struct MortonPrim {
-    morton_code: usize,
+    morton_code: u64,
     prim_index: usize,
 }
 
 impl MortonCode for MortonPrim {
-    fn morton_code(&self) -> usize {
+    fn morton_code(&self) -> u64 {
         self.morton_code
     }
 }
@@ -68,26 +189,179 @@ struct Treelet {
     nodes: Vec<Arc<BVHBuildNode>>, // root node of treelet
 }
 
-// imple linear bvh build
-impl<T:Primitive> BVH<T> {
+impl<T: Primitive> BVH<T> {
+    /// build with HLBVHBuilder
     pub fn build(&mut self, node_prims_limit: usize, par_build: bool) {
+        self.build_with(&HLBVHBuilder { par_build }, node_prims_limit);
+    }
+
+    /// primitives with NaN or infinite bounds are reported and left out of tree,
+    /// unbounded ones are traced next to it
+    pub fn build_with<B: BVHBuilder>(&mut self, builder: &B, node_prims_limit: usize) {
+        // leaves hold less than limit primitives, so one primitive leaves need 2
+        let node_prims_limit = node_prims_limit.max(2);
         self.node_prims_limit = node_prims_limit;
+        self.builder = builder.describe();
+        // binary nodes changed, collapse again if needed
+        self.wide = WideNodes::None;
+        self.update = UpdateState::default();
+
+        let partition = self.partition_invalid();
+        let nvalid = self.primitives.len() - self.invalid_prims.len();
+        if nvalid == 0 {
+            self.nodes.clear();
+            self.prim_refs.clear();
+            self.split_refs = false;
+            self.prim_order = chain_order(&self.prim_order, partition);
+            self.build_cost = 0.;
+            self.build_area = 0.;
+            return;
+        }
+
+        let tree = builder.build_tree(&self.primitives[..nvalid], node_prims_limit);
+        self.nodes = tree.flatten();
+        self.split_refs = tree.references;
+
+        let order = if tree.references {
+            self.prim_refs = tree.prim_order;
+            Vec::new()
+        } else {
+            // reorder primitives so every leaf references a continuous range
+            let order: Vec<usize> = tree
+                .prim_order
+                .into_iter()
+                .chain(nvalid..self.primitives.len())
+                .collect();
+            let ordered_prims: Vec<T> = order.iter().map(|&i| self.primitives[i].clone()).collect();
+            let _ = std::mem::replace(&mut self.primitives, ordered_prims);
+            self.prim_refs.clear();
+            order
+        };
+
+        // primitives may be reordered by previous build
+        let order = chain_order(&partition, order);
+        self.prim_order = chain_order(&self.prim_order, order);
+        self.build_cost = self.sah_cost();
+        self.build_area = self.nodes[0].bounds.area();
+    }
+
+    /// move primitives with NaN or infinite bounds behind others, recorded in invalid_prims.
+    /// returns index before partition of every primitive, empty if none moved
+    fn partition_invalid(&mut self) -> Vec<usize> {
+        let (valid, invalid): (Vec<usize>, Vec<usize>) =
+            (0..self.primitives.len()).partition(|&i| self.primitives[i].bounds().is_finite());
+        self.invalid_prims = (valid.len()..self.primitives.len()).collect();
+        self.unbounded_prims.clear();
+        if invalid.is_empty() {
+            return Vec::new();
+        }
+
+        let unbounded = invalid
+            .iter()
+            .filter(|&&i| self.primitives[i].unbounded())
+            .count();
+        if unbounded < invalid.len() {
+            eprintln!(
+                "warn: {} primitives with NaN or infinite bounds left out of bvh",
+                invalid.len() - unbounded
+            );
+        }
+
+        let order = if invalid[0] == valid.len() {
+            Vec::new()
+        } else {
+            let order: Vec<usize> = valid.into_iter().chain(invalid).collect();
+            self.primitives = order.iter().map(|&i| self.primitives[i].clone()).collect();
+            order
+        };
+        self.unbounded_prims = self.unbounded_of_invalid();
+        order
+    }
+
+    /// invalid primitives traced next to tree
+    pub(crate) fn unbounded_of_invalid(&self) -> Vec<usize> {
+        self.invalid_prims
+            .iter()
+            .copied()
+            .filter(|&i| self.primitives[i].unbounded())
+            .collect()
+    }
+}
+
+/// order of primitives ordered by before then by order, empty order keeps positions
+fn chain_order(before: &[usize], order: Vec<usize>) -> Vec<usize> {
+    if order.is_empty() {
+        before.to_vec()
+    } else if before.is_empty() {
+        order
+    } else {
+        order.iter().map(|&i| before[i]).collect()
+    }
+}
+
+impl BuildTree {
+    /// linear nodes in depth first order
+    pub fn flatten(&self) -> Vec<LinearBVHNode> {
+        let mut nodes = vec![LinearBVHNode::default(); self.total_nodes];
+        flatten_bvh(&mut nodes, &self.root, &mut 0);
+        nodes
+    }
+}
+
+//compact memory
+fn flatten_bvh(nodes: &mut [LinearBVHNode], root: &BVHBuildNode, offset: &mut usize) -> usize {
+    let node_offset = *offset;
+    *offset += 1;
+    let lnode = &mut nodes[node_offset];
+    lnode.bounds = root.bounds;
+    // leaf
+    if root.nprimitives > 0 {
+        lnode.offset = root.prim_offset;
+        lnode.nprimitives = root.nprimitives;
+    } else {
+        // interior
+        lnode.axis = root.axis;
+        lnode.nprimitives = 0;
+
+        if let Some(c0) = &root.c0 {
+            flatten_bvh(nodes, c0, offset);
+        }
+
+        if let Some(c1) = &root.c1 {
+            let i = flatten_bvh(nodes, c1, offset);
+            // put there since borrow checker
+            let lnode = &mut nodes[node_offset];
+            lnode.offset = i;
+        }
+    }
+
+    node_offset
+}
+
+// imple linear bvh build
+impl BVHBuilder for HLBVHBuilder {
+    fn build_tree<T: Primitive>(&self, primitives: &[T], node_prims_limit: usize) -> BuildTree {
         // bounds of whole bvh
-        let bounds = self
-            .primitives
+        let bounds = primitives
             .iter()
-            .fold(Bounds3f::zero(), |acc, b| acc.union(b.bounds()));
+            .fold(Bounds3f::empty(), |acc, b| acc.union(b.bounds()));
 
-        let mut morton_prims: Vec<MortonPrim> = vec![MortonPrim::default(); self.primitives.len()];
+        // 63-bit codes for large inputs, 30-bit ones sort faster
+        let morton_bits = morton_bits(primitives.len());
+        let code_bits = 3 * morton_bits as i32;
+
+        let mut morton_prims: Vec<MortonPrim> = vec![MortonPrim::default(); primitives.len()];
         morton_prims
             .par_iter_mut()
             .enumerate()
             .for_each(|(i, morton_prim)| {
-                let morton_bits: usize = 10;
-                let morton_scale = 1 << morton_bits;
+                let morton_max = ((1 << morton_bits) - 1) as f32;
                 morton_prim.prim_index = i;
-                let cnt_offset = bounds.offset(self.primitives[i].bounds().centroid());
-                let offset = cnt_offset * morton_scale as f32;
+                let cnt_offset = bounds.offset(primitives[i].bounds().centroid());
+                // centroid on upper bound maps to last cell
+                let offset = Vec3f::vec(std::array::from_fn(|k| {
+                    (cnt_offset[k] * (morton_max + 1.)).min(morton_max)
+                }));
                 morton_prim.morton_code = encode_morton3(offset);
             });
 
@@ -103,7 +377,7 @@ impl<T:Primitive> BVH<T> {
             let prims_size = morton_prims.len();
             while end <= prims_size {
                 // use hight 12 bits to cluster treelets, clustering inside 16x16x16 grid
-                let mask = 0b00111111111111000000000000000000;
+                let mask = ((1 << 12) - 1) << (code_bits - 12);
                 if (end == prims_size)
                     || (morton_prims[start].morton_code & mask)
                         != (morton_prims[end].morton_code & mask)
@@ -128,28 +402,23 @@ impl<T:Primitive> BVH<T> {
             }
         };
 
-        let mut p: Vec<T> = Vec::with_capacity(self.primitives.len());
-        let ordered_prims = {
-            unsafe {
-                p.set_len(self.primitives.len());
-            };
-            Arc::new(p)
-        };
-        let ordered_prims_offset = Arc::new(AtomicUsize::new(0));
+        let ordered_prims = Arc::new(vec![0usize; primitives.len()]);
         let total_nodes = Arc::new(Mutex::new(0usize));
 
         let build_task = |tr: &mut Treelet| {
             // i-th treelet
-            // 30 bits morton code , 12 bits used for building treelet clusters
-            let first_bit_index: i32 = 29 - 12;
+            // high 12 bits of morton code used for building treelet clusters
+            let first_bit_index: i32 = code_bits - 1 - 12;
+            // treelet owns slots of its morton range, order never depends on scheduling
             let ordered_prims = ordered_prims.clone();
-            let ordered_prims_offset = ordered_prims_offset.clone();
             let (root, nodes_created) = self.emit_lbvh(
+                primitives,
+                node_prims_limit,
                 &mut tr.nodes[0..],
                 &morton_prims[tr.start_index..tr.start_index + tr.nprimitives],
                 tr.nprimitives,
                 ordered_prims,
-                ordered_prims_offset,
+                tr.start_index,
                 first_bit_index,
             );
 
@@ -158,7 +427,7 @@ impl<T:Primitive> BVH<T> {
             *guard += nodes_created;
         };
 
-        if par_build {
+        if self.par_build {
             treelets.par_iter_mut().for_each(build_task);
         } else {
             treelets.iter_mut().for_each(build_task);
@@ -171,12 +440,11 @@ impl<T:Primitive> BVH<T> {
             }
         });
 
-        let (root, sah_created_nodes) = self.build_sah(&treelet_roots);
+        let (root, sah_created_nodes) = build_sah(&treelet_roots);
         assert!(sah_created_nodes < treelet_roots.len() * 2);
         let mut total_nodes = total_nodes.lock().unwrap();
         *total_nodes += sah_created_nodes;
 
-
         // swap ordered primitives and original primitives
         let ordered_prims = match Arc::try_unwrap(ordered_prims) {
             Ok(p_vec) => p_vec,
@@ -188,37 +456,47 @@ impl<T:Primitive> BVH<T> {
             }
         };
 
-        let _ = std::mem::replace(&mut self.primitives, ordered_prims);
-        self.nodes.resize_with(*total_nodes, LinearBVHNode::default);
-        let mut offset = Box::new(0);
-        self.flatten_bvh(&root, &mut offset);
+        BuildTree {
+            root,
+            total_nodes: *total_nodes,
+            prim_order: ordered_prims,
+            references: false,
+        }
+    }
+
+    fn describe(&self) -> String {
+        "hlbvh".to_string()
     }
+}
 
-    /// returns root node of sub tree and created nodes num
-    fn emit_lbvh(
+impl HLBVHBuilder {
+    /// returns root node of sub tree and created nodes num.
+    /// leaves take slots from first_prim_offset in morton order
+    #[allow(clippy::too_many_arguments)]
+    fn emit_lbvh<T: Primitive>(
         &self,
+        primitives: &[T],
+        node_prims_limit: usize,
         build_nodes: &mut [Arc<BVHBuildNode>],
         morton_prims: &[MortonPrim],
         nprimitives: usize,
-        ordered_prims: Arc<Vec<T>>,
-        ordered_prims_offset: Arc<AtomicUsize>,
+        ordered_prims: Arc<Vec<usize>>,
+        first_prim_offset: usize,
         bit_index: i32,
     ) -> (Arc<BVHBuildNode>, usize) {
-        if bit_index == -1 || nprimitives < self.node_prims_limit {
-            let first_prim_offset = ordered_prims_offset.fetch_add(nprimitives, Relaxed);
+        if nprimitives < node_prims_limit {
             let node = build_nodes[0].clone();
-            let mut bounds = Bounds3f::zero();
+            let mut bounds = Bounds3f::empty();
 
             unsafe {
-                let vec_ptr = Arc::as_ptr(&ordered_prims) as *mut Vec<T>;
+                let vec_ptr = Arc::as_ptr(&ordered_prims) as *mut Vec<usize>;
                 let buffer_ptr = (*vec_ptr).as_mut_ptr();
 
                 for (i, morton_prim) in morton_prims.iter().take(nprimitives).enumerate() {
                     let org_prim_index = morton_prim.prim_index;
-                    let prim = self.primitives[org_prim_index].clone();
-                    bounds = bounds.union(prim.bounds());
+                    bounds = bounds.union(primitives[org_prim_index].bounds());
                     let cur_prim_index = first_prim_offset + i;
-                    std::ptr::write(buffer_ptr.add(cur_prim_index), prim);
+                    std::ptr::write(buffer_ptr.add(cur_prim_index), org_prim_index);
                 }
 
                 let node_ptr = Arc::as_ptr(&node) as *mut BVHBuildNode;
@@ -226,17 +504,50 @@ impl<T:Primitive> BVH<T> {
             }
 
             (node, 1)
+        } else if bit_index == -1 {
+            // all codes equal, e.g. coincident centroids. split evenly
+            let mid = nprimitives / 2;
+            let (c0, c0_created_nodes) = self.emit_lbvh(
+                primitives,
+                node_prims_limit,
+                &mut build_nodes[1..],
+                morton_prims,
+                mid,
+                Arc::clone(&ordered_prims),
+                first_prim_offset,
+                -1,
+            );
+            let (c1, c1_created_nodes) = self.emit_lbvh(
+                primitives,
+                node_prims_limit,
+                &mut build_nodes[1 + c0_created_nodes..],
+                &morton_prims[mid..],
+                nprimitives - mid,
+                ordered_prims,
+                first_prim_offset + mid,
+                -1,
+            );
+
+            let node = build_nodes[0].clone();
+            unsafe {
+                let node_ptr = Arc::as_ptr(&node) as *mut BVHBuildNode;
+                (*node_ptr).init_interior(0, c0, c1);
+            }
+
+            (node, c0_created_nodes + c1_created_nodes + 1)
         } else {
             let mask = 1 << bit_index;
             let first_morton = morton_prims[0].morton_code;
             // advance to next subtree level if there is no LBVH split for this bit
             if (first_morton & mask) == (morton_prims[nprimitives - 1].morton_code & mask) {
                 return self.emit_lbvh(
+                    primitives,
+                    node_prims_limit,
                     build_nodes,
                     morton_prims,
                     nprimitives,
                     ordered_prims,
-                    ordered_prims_offset,
+                    first_prim_offset,
                     bit_index - 1,
                 );
             }
@@ -254,20 +565,24 @@ impl<T:Primitive> BVH<T> {
             );
 
             let (c0, c0_created_nodes) = self.emit_lbvh(
+                primitives,
+                node_prims_limit,
                 &mut build_nodes[1..], // [0] as current node
                 morton_prims,
                 split_offset,
                 Arc::clone(&ordered_prims),
-                Arc::clone(&ordered_prims_offset),
+                first_prim_offset,
                 bit_index - 1,
             );
 
             let (c1, c1_created_nodes) = self.emit_lbvh(
+                primitives,
+                node_prims_limit,
                 &mut build_nodes[1 + c0_created_nodes..],
                 &morton_prims[split_offset..],
                 nprimitives - split_offset,
                 ordered_prims,
-                ordered_prims_offset,
+                first_prim_offset + split_offset,
                 bit_index - 1,
             );
 
@@ -281,149 +596,505 @@ impl<T:Primitive> BVH<T> {
             (node, c0_created_nodes + c1_created_nodes + 1)
         }
     }
+}
 
-    /// build treelets node use Surface Area Heuristic
-    #[allow(clippy::only_used_in_recursion)]
-    fn build_sah(&self, treelet_roots: &[Arc<BVHBuildNode>]) -> (Arc<BVHBuildNode>, usize) {
-        if treelet_roots.len() == 1 {
-            return (treelet_roots[0].clone(), 0);
-        }
+/// build treelets node use Surface Area Heuristic
+fn build_sah(treelet_roots: &[Arc<BVHBuildNode>]) -> (Arc<BVHBuildNode>, usize) {
+    if treelet_roots.len() == 1 {
+        return (treelet_roots[0].clone(), 0);
+    }
 
-        let centroid_bounds = treelet_roots.iter().fold(Bounds3f::zero(), |acc, node| {
-            acc.enlarge(node.bounds().centroid())
-        });
-        let dim = centroid_bounds.max_dim();
-        // need handle when this hits
-        assert_ne!(centroid_bounds.min[dim], centroid_bounds.max[dim]);
-
-        #[derive(Default, Clone, Copy)]
-        struct BVHSplitBucket {
-            count: usize,
-            bounds: Bounds3f,
+    let centroid_bounds = treelet_roots.iter().fold(Bounds3f::empty(), |acc, node| {
+        acc.enlarge(node.bounds().centroid())
+    });
+    let dim = centroid_bounds.max_dim();
+
+    let bounds = treelet_roots
+        .iter()
+        .fold(Bounds3f::empty(), |acc, node| node.bounds().union(acc));
+
+    // all centroids at same place, split evenly
+    if centroid_bounds.min[dim] >= centroid_bounds.max[dim] {
+        let mid = treelet_roots.len() / 2;
+        let (c0, c0_created_nodes) = build_sah(&treelet_roots[..mid]);
+        let (c1, c1_created_nodes) = build_sah(&treelet_roots[mid..]);
+        let mut node = BVHBuildNode::default();
+        node.init_interior(dim, c0, c1);
+        return (Arc::new(node), c0_created_nodes + c1_created_nodes + 1);
+    }
+
+    let mut buckets = [BVHSplitBucket::empty(); N_BUCKETS];
+
+    // init partition buckets alone max dimension
+    treelet_roots.iter().for_each(|node| {
+        let centroid = node.bounds().centroid()[dim];
+        let centroid_offset = (centroid - centroid_bounds.min[dim])
+            / (centroid_bounds.max[dim] - centroid_bounds.min[dim]);
+        let mut b = ((centroid_offset) * N_BUCKETS as f32) as usize;
+        if b == N_BUCKETS {
+            b = N_BUCKETS - 1;
         }
 
-        let mut buckets = [BVHSplitBucket::default(); N_BUCKETS];
+        buckets[b].count += 1;
+        buckets[b].bounds = buckets[b].bounds.union(node.bounds());
+    });
 
-        // init partition buckets alone max dimension
-        treelet_roots.iter().for_each(|node| {
-            let centroid = node.bounds().centroid()[dim];
-            let centroid_offset = (centroid - centroid_bounds.min[dim])
-                / (centroid_bounds.max[dim] - centroid_bounds.min[dim]);
-            let mut b = ((centroid_offset) * N_BUCKETS as f32) as usize;
-            if b == N_BUCKETS {
-                b = N_BUCKETS - 1;
-            }
+    // find bucket to split at that minimizes SAH metric
+    let (min_cost_index, _) = min_split_cost(&buckets, &bounds);
 
-            buckets[b].count += 1;
-            buckets[b].bounds = buckets[b].bounds.union(node.bounds());
-        });
+    // return how many elements satisfy the predicate
+    let (start, end): (Vec<_>, Vec<_>) = treelet_roots.iter().partition(|node| {
+        let centroid = node.bounds().centroid()[dim];
+        let centroid_offset = (centroid - centroid_bounds.min[dim])
+            / (centroid_bounds.max[dim] - centroid_bounds.min[dim]);
+
+        let mut b = ((centroid_offset) * N_BUCKETS as f32) as usize;
+        if b == N_BUCKETS {
+            b = N_BUCKETS - 1;
+        }
+
+        b <= min_cost_index
+    });
 
-        let bounds = treelet_roots
+    // handle corner cases, eg. all centroids located same place
+    // forcing split by fisrt element
+    let (left, right) = if start.is_empty() {
+        let left = vec![end[0].clone()];
+        let right = end[1..].iter().map(|&x| x.clone()).collect();
+        (left, right)
+    } else if end.is_empty() {
+        let left = start[..start.len() - 1]
             .iter()
-            .fold(Bounds3f::zero(), |acc, node| node.bounds().union(acc));
+            .map(|&x| x.clone())
+            .collect();
+        let right = vec![start[start.len() - 1].clone()];
+        (left, right)
+    } else {
+        (
+            start.iter().map(|&x| x.clone()).collect(),
+            end.iter().map(|&x| x.clone()).collect(),
+        )
+    };
 
-        // compute costs for splitting after each bucket
-        let mut cost = [0.; N_BUCKETS - 1];
-        cost.iter_mut().enumerate().for_each(|(i, c)| {
-            let (b0, c0) = buckets
-                .iter()
-                .take(i + 1)
-                .fold((Bounds3f::zero(), 0), |(b, c), bk| {
-                    (b.union(bk.bounds), c + bk.count)
-                });
+    let (c0, c0_created_nodes) = build_sah(&left);
+    let (c1, c1_created_nodes) = build_sah(&right);
 
-            let (b1, c1) = buckets
-                .iter()
-                .take(N_BUCKETS)
-                .skip(i + 1)
-                .fold((Bounds3f::zero(), 0), |(b, c), bk| {
-                    (b.union(bk.bounds), c + bk.count)
-                });
+    let node = Arc::new(BVHBuildNode::default());
+    unsafe {
+        let node_ptr = Arc::as_ptr(&node) as *mut BVHBuildNode;
+        (*node_ptr).init_interior(dim, c0, c1);
+    }
 
-            *c = 0.125 + (c0 as f32 * b0.area() + c1 as f32 * b1.area()) / bounds.area();
-        });
+    (node, c0_created_nodes + c1_created_nodes + 1)
+}
 
-        // find bucket to split at that minimizes SAH metric
-        let (min_cost_index, _) = cost.iter().enumerate().take(N_BUCKETS - 1).skip(1).fold(
-            (0, cost[0]),
-            |(im, m), (i, &cost)| {
-                if cost < m { (i, cost) } else { (im, m) }
-            },
-        );
+impl BVHBuilder for SAHBuilder {
+    fn build_tree<T: Primitive>(&self, primitives: &[T], node_prims_limit: usize) -> BuildTree {
+        build_top_down(primitives, node_prims_limit, self.par_build, &sah_split)
+    }
+
+    fn describe(&self) -> String {
+        "sah".to_string()
+    }
+}
+
+impl BVHBuilder for MedianBuilder {
+    fn build_tree<T: Primitive>(&self, primitives: &[T], node_prims_limit: usize) -> BuildTree {
+        build_top_down(primitives, node_prims_limit, self.par_build, &median_split)
+    }
 
-        // return how many elements satisfy the predicate
-        let (start, end): (Vec<_>, Vec<_>) = treelet_roots.iter().partition(|node| {
-            let centroid = node.bounds().centroid()[dim];
-            let centroid_offset = (centroid - centroid_bounds.min[dim])
-                / (centroid_bounds.max[dim] - centroid_bounds.min[dim]);
+    fn describe(&self) -> String {
+        "median".to_string()
+    }
+}
 
-            let mut b = ((centroid_offset) * N_BUCKETS as f32) as usize;
-            if b == N_BUCKETS {
-                b = N_BUCKETS - 1;
+/// split: (primitives, bounds, node_prims_limit) -> partition point and axis, None for leaf
+fn build_top_down<T, F>(
+    primitives: &[T],
+    node_prims_limit: usize,
+    par_build: bool,
+    split: &F,
+) -> BuildTree
+where
+    T: Primitive,
+    F: Fn(&mut [BuildPrim], &Bounds3f, usize) -> Option<(usize, usize)> + Sync,
+{
+    let mut build_prims: Vec<BuildPrim> = primitives
+        .par_iter()
+        .enumerate()
+        .map(|(index, p)| {
+            let bounds = p.bounds();
+            BuildPrim {
+                index,
+                bounds,
+                centroid: bounds.centroid(),
             }
+        })
+        .collect();
 
-            b <= min_cost_index
-        });
+    let (root, total_nodes) =
+        build_recursive(&mut build_prims, 0, node_prims_limit, par_build, split);
 
-        // handle corner cases, eg. all centroids located same place
-        // forcing split by fisrt element
-        let (left, right) = if start.is_empty() {
-            let left = vec![end[0].clone()];
-            let right = end[1..].iter().map(|&x| x.clone()).collect();
-            (left, right)
-        } else if end.is_empty() {
-            let left = start[..start.len() - 1]
-                .iter()
-                .map(|&x| x.clone())
-                .collect();
-            let right = vec![start[start.len() - 1].clone()];
-            (left, right)
-        } else {
-            (
-                start.iter().map(|&x| x.clone()).collect(),
-                end.iter().map(|&x| x.clone()).collect(),
-            )
-        };
+    // leaves index continuous ranges of partitioned build_prims
+    BuildTree {
+        root,
+        total_nodes,
+        prim_order: build_prims.iter().map(|p| p.index).collect(),
+        references: false,
+    }
+}
 
-        let (c0, c0_created_nodes) = self.build_sah(&left);
-        let (c1, c1_created_nodes) = self.build_sah(&right);
+/// first: offset of prims in ordered primitives
+/// returns root node of sub tree and created nodes num
+fn build_recursive<F>(
+    prims: &mut [BuildPrim],
+    first: usize,
+    node_prims_limit: usize,
+    par_build: bool,
+    split: &F,
+) -> (Arc<BVHBuildNode>, usize)
+where
+    F: Fn(&mut [BuildPrim], &Bounds3f, usize) -> Option<(usize, usize)> + Sync,
+{
+    let n = prims.len();
+    let bounds = prims
+        .iter()
+        .fold(Bounds3f::empty(), |acc, p| acc.union(p.bounds));
 
-        let node = Arc::new(BVHBuildNode::default());
-        unsafe {
-            let node_ptr = Arc::as_ptr(&node) as *mut BVHBuildNode;
-            (*node_ptr).init_interior(dim, c0, c1);
+    let mut node = BVHBuildNode::default();
+    let split_at = if n > 1 {
+        split(prims, &bounds, node_prims_limit)
+    } else {
+        None
+    };
+
+    match split_at {
+        None => {
+            node.init_leaf(first, n, bounds);
+            (Arc::new(node), 1)
         }
+        Some((mid, axis)) => {
+            let (left, right) = prims.split_at_mut(mid);
+            let ((c0, c0_created_nodes), (c1, c1_created_nodes)) =
+                if par_build && n > PAR_BUILD_SIZE {
+                    rayon::join(
+                        || build_recursive(left, first, node_prims_limit, par_build, split),
+                        || build_recursive(right, first + mid, node_prims_limit, par_build, split),
+                    )
+                } else {
+                    (
+                        build_recursive(left, first, node_prims_limit, par_build, split),
+                        build_recursive(right, first + mid, node_prims_limit, par_build, split),
+                    )
+                };
 
-        (node, c0_created_nodes + c1_created_nodes + 1)
+            node.init_interior(axis, c0, c1);
+            (Arc::new(node), c0_created_nodes + c1_created_nodes + 1)
+        }
     }
+}
 
-    //compact memory
-    fn flatten_bvh(&mut self, root: &BVHBuildNode, offset: &mut Box<usize>) -> usize {
-        let node_offset = **offset;
-        **offset += 1;
-        let lnode = &mut self.nodes[node_offset];
-        lnode.bounds = root.bounds;
-        // leaf
-        if root.nprimitives > 0 {
-            lnode.offset = root.prim_offset;
-            lnode.nprimitives = root.nprimitives;
-        } else {
-            // interior
-            lnode.axis = root.axis;
-            lnode.nprimitives = 0;
+pub(crate) fn centroid_bounds(prims: &[BuildPrim]) -> Bounds3f {
+    prims
+        .iter()
+        .fold(Bounds3f::empty(), |acc, p| acc.enlarge(p.centroid))
+}
 
-            if let Some(c0) = &root.c0 {
-                self.flatten_bvh(c0, offset);
-            }
+/// put smaller half of centroids on dim before returned index
+pub(crate) fn split_equal_counts(prims: &mut [BuildPrim], dim: usize) -> usize {
+    let mid = prims.len() / 2;
+    prims.select_nth_unstable_by(mid, |a, b| a.centroid[dim].total_cmp(&b.centroid[dim]));
+    mid
+}
+
+fn median_split(
+    prims: &mut [BuildPrim],
+    _bounds: &Bounds3f,
+    node_prims_limit: usize,
+) -> Option<(usize, usize)> {
+    if prims.len() < node_prims_limit {
+        return None;
+    }
 
-            if let Some(c1) = &root.c1 {
-                let i = self.flatten_bvh(c1, offset);
-                // put there since borrow checker
-                let lnode = &mut self.nodes[node_offset];
-                lnode.offset = i;
+    let dim = centroid_bounds(prims).max_dim();
+    Some((split_equal_counts(prims, dim), dim))
+}
+
+fn sah_split(
+    prims: &mut [BuildPrim],
+    bounds: &Bounds3f,
+    node_prims_limit: usize,
+) -> Option<(usize, usize)> {
+    let n = prims.len();
+    let centroid_bounds = centroid_bounds(prims);
+    let dim = centroid_bounds.max_dim();
+    let (cmin, cmax) = (centroid_bounds.min[dim], centroid_bounds.max[dim]);
+
+    // all centroids at same place, buckets can not separate them
+    if cmax <= cmin {
+        if n < node_prims_limit {
+            return None;
+        }
+        return Some((split_equal_counts(prims, dim), dim));
+    }
+
+    let bucket_of = |c: f32| (((c - cmin) / (cmax - cmin)) * N_BUCKETS as f32) as usize;
+
+    let mut buckets = [BVHSplitBucket::empty(); N_BUCKETS];
+
+    prims.iter().for_each(|p| {
+        let b = bucket_of(p.centroid[dim]).min(N_BUCKETS - 1);
+        buckets[b].count += 1;
+        buckets[b].bounds = buckets[b].bounds.union(p.bounds);
+    });
+
+    let (min_cost_index, split_cost) = min_split_cost(&buckets, bounds);
+
+    let leaf_cost = n as f32;
+    if n < node_prims_limit && leaf_cost <= split_cost {
+        return None;
+    }
+
+    let mid = itertools::partition(prims.iter_mut(), |p| {
+        bucket_of(p.centroid[dim]).min(N_BUCKETS - 1) <= min_cost_index
+    });
+    Some((mid, dim))
+}
+
+#[test]
+fn test_bvh_builders() {
+    use crate::raycast::{Ray, Raycast, sphere::Sphere};
+    use rand::Rng;
+    use std::time::Instant;
+
+    let (n, node_limit) = (4096, 9);
+    let mut rng = rand::rng();
+    let spheres: Vec<Sphere> = (0..n)
+        .map(|_| {
+            let cnt = Vec3f::vec([
+                rng.random_range(0.0..64.),
+                rng.random_range(0.0..8.),
+                rng.random_range(0.0..64.),
+            ]);
+            Sphere::new(cnt, rng.random_range(0.1..2.))
+        })
+        .collect();
+
+    let rays: Vec<Ray> = (0..256)
+        .map(|_| {
+            let org = Vec3f::vec([rng.random_range(0.0..64.), 32., rng.random_range(0.0..64.)]);
+            let dir = Vec3f::vec([rng.random_range(-1.0..1.), -1., rng.random_range(-1.0..1.)]);
+            Ray::new(org, dir)
+        })
+        .collect();
+
+    let check = |name: &str, builder: &dyn Fn(&mut BVH<Sphere>)| {
+        let mut bvh = BVH::new(spheres.len());
+        spheres.iter().for_each(|s| bvh.push(s.clone()));
+
+        let sw = Instant::now();
+        builder(&mut bvh);
+        println!(
+            "{name} build {}ms, sah cost {}",
+            sw.elapsed().as_millis(),
+            bvh.sah_cost()
+        );
+
+        assert_eq!(bvh.primitives.len(), spheres.len());
+        let mut nprims = 0;
+        for node in bvh.nodes.iter().filter(|node| node.is_leaf()) {
+            assert!(node.nprimitives < node_limit);
+            nprims += node.nprimitives;
+        }
+        assert_eq!(nprims, spheres.len());
+
+        for ray in rays.iter() {
+            let expected = spheres
+                .iter()
+                .filter_map(|s| s.raycast(ray))
+                .map(|h| h.t)
+                .reduce(f32::min);
+            assert_eq!(bvh.raycast(ray).map(|h| h.t), expected);
+        }
+    };
+
+    check("hlbvh", &|bvh| {
+        bvh.build_with(&HLBVHBuilder { par_build: true }, node_limit)
+    });
+    check("sah", &|bvh| {
+        bvh.build_with(&SAHBuilder { par_build: true }, node_limit)
+    });
+    check("median", &|bvh| {
+        bvh.build_with(&MedianBuilder { par_build: false }, node_limit)
+    });
+}
+
+#[test]
+fn test_bvh_deterministic() {
+    use crate::raycast::sbvh::SBVHBuilder;
+    use crate::raycast::sphere::Sphere;
+    use rand::Rng;
+
+    let n = 3 * PAR_BUILD_SIZE;
+    let mut rng = rand::rng();
+    let spheres: Vec<Sphere> = (0..n)
+        .map(|_| {
+            let cnt = Vec3f::vec([
+                rng.random_range(0.0..64.),
+                rng.random_range(0.0..64.),
+                rng.random_range(0.0..64.),
+            ]);
+            Sphere::new(cnt, rng.random_range(0.1..1.))
+        })
+        .collect();
+
+    // parallel build has to match sequential one, primitive ids stay stable
+    fn check<B: BVHBuilder>(spheres: &[Sphere], seq: &B, par: &B) {
+        let build = |builder: &B| {
+            let mut bvh = BVH::new(spheres.len());
+            spheres.iter().for_each(|s| bvh.push(s.clone()));
+            bvh.build_with(builder, 9);
+            bvh
+        };
+
+        let expected = build(seq);
+        for _ in 0..4 {
+            let bvh = build(par);
+            assert_eq!(bvh.nodes, expected.nodes, "{}", par.describe());
+            assert_eq!(bvh.prim_order, expected.prim_order);
+            assert_eq!(bvh.prim_refs, expected.prim_refs);
+        }
+    }
+
+    check(
+        &spheres,
+        &HLBVHBuilder { par_build: false },
+        &HLBVHBuilder { par_build: true },
+    );
+    check(
+        &spheres,
+        &SAHBuilder { par_build: false },
+        &SAHBuilder { par_build: true },
+    );
+    check(
+        &spheres,
+        &MedianBuilder { par_build: false },
+        &MedianBuilder { par_build: true },
+    );
+    check(
+        &spheres,
+        &SBVHBuilder {
+            par_build: false,
+            ..Default::default()
+        },
+        &SBVHBuilder {
+            par_build: true,
+            ..Default::default()
+        },
+    );
+}
+
+#[test]
+fn test_bvh_degenerate() {
+    use crate::raycast::{Ray, Raycast, sbvh::SBVHBuilder, sphere::Sphere, widebvh::BVHWidth};
+
+    fn check<B: BVHBuilder>(builder: &B, spheres: &[Sphere], node_limit: usize) {
+        let mut bvh = BVH::new(spheres.len());
+        spheres.iter().for_each(|s| bvh.push(s.clone()));
+        bvh.build_with(builder, node_limit);
+
+        let valid = spheres.iter().filter(|s| s.bounds().is_finite()).count();
+        assert_eq!(bvh.primitives.len(), spheres.len());
+        assert_eq!(bvh.invalid_prims.len(), spheres.len() - valid);
+        for &i in bvh.invalid_prims.iter() {
+            assert!(!bvh.primitives[i].bounds().is_finite());
+        }
+        // prim_order still maps every primitive back
+        if !bvh.prim_order.is_empty() {
+            let mut order = bvh.prim_order.clone();
+            order.sort_unstable();
+            assert!(order.into_iter().eq(0..spheres.len()));
+        }
+
+        let mut slots = 0;
+        for node in bvh.nodes.iter().filter(|node| node.is_leaf()) {
+            assert!(node.nprimitives < node_limit.max(2));
+            slots += node.nprimitives;
+        }
+        assert!(slots >= valid);
+        assert!(bvh.sah_cost().is_finite());
+
+        let rays = [
+            Ray::new(Vec3f::vec([-4., 0.1, 0.2]), Vec3f::vec([1., 0., 0.])),
+            Ray::new(Vec3f::vec([0.3, 5., -0.1]), Vec3f::vec([0., -1., 0.])),
+            Ray::new(Vec3f::vec([8., 8., 8.]), Vec3f::vec([1., 1., 1.])),
+        ];
+        for width in [BVHWidth::Binary, BVHWidth::Four] {
+            bvh.collapse(width);
+            for ray in rays.iter() {
+                let expected = bvh
+                    .primitives
+                    .iter()
+                    .filter(|s| s.bounds().is_finite())
+                    .filter_map(|s| s.raycast(ray))
+                    .map(|h| h.t)
+                    .reduce(f32::min);
+                assert_eq!(
+                    bvh.raycast(ray).map(|h| h.t),
+                    expected,
+                    "{}",
+                    builder.describe()
+                );
             }
         }
+    }
 
-        node_offset
+    let origin = Vec3f::vec([0.; 3]);
+    // identical centroids, radii differ
+    let coincident: Vec<Sphere> = (0..5000)
+        .map(|i| Sphere::new(origin, 0.5 + i as f32 * 1e-4))
+        .collect();
+    let mut invalid: Vec<Sphere> = (0..64)
+        .map(|i| Sphere::new(Vec3f::vec([i as f32, 0., 0.]), 0.25))
+        .collect();
+    invalid.insert(3, Sphere::new(Vec3f::vec([f32::NAN, 0., 0.]), 1.));
+    invalid.insert(10, Sphere::new(origin, f32::INFINITY));
+    invalid.push(Sphere::new(Vec3f::vec([0., f32::NEG_INFINITY, 0.]), 1.));
+    let all_invalid = vec![Sphere::new(Vec3f::vec([f32::NAN; 3]), 1.); 3];
+
+    let inputs: [(&[Sphere], usize); 6] = [
+        (&[], 9),
+        (&[Sphere::new(origin, 1.)], 9),
+        (&[Sphere::new(origin, 1.)], 1),
+        (&coincident, 9),
+        (&invalid, 4),
+        (&all_invalid, 4),
+    ];
+    for (spheres, node_limit) in inputs {
+        check(&HLBVHBuilder { par_build: true }, spheres, node_limit);
+        check(&SAHBuilder { par_build: true }, spheres, node_limit);
+        check(&MedianBuilder { par_build: false }, spheres, node_limit);
+        check(&SBVHBuilder::default(), spheres, node_limit);
     }
+
+    // insert and remove around invalid primitives
+    let mut bvh: BVH<Sphere> = BVH::new(0);
+    bvh.build(4, false);
+    assert!(
+        bvh.raycast_node(&Ray::new(origin, Vec3f::vec([1., 0., 0.])))
+            .is_none()
+    );
+    let nan = bvh.insert(Sphere::new(Vec3f::vec([f32::NAN; 3]), 1.));
+    let a = bvh.insert(Sphere::new(Vec3f::vec([4., 0., 0.]), 1.));
+    let b = bvh.insert(Sphere::new(Vec3f::vec([8., 0., 0.]), 1.));
+    assert_eq!(bvh.invalid_prims, vec![nan]);
+    assert_eq!(bvh.compacted().unwrap().1, vec![a, b]);
+
+    bvh.remove(nan);
+    assert!(bvh.invalid_prims.is_empty());
+    let (hit, i) = bvh
+        .raycast_node(&Ray::new(origin, Vec3f::vec([1., 0., 0.])))
+        .unwrap();
+    assert_eq!(hit.t, 3.);
+    assert_eq!(bvh.primitives[i].cnt[0], 4.);
 }
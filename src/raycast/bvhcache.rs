@@ -0,0 +1,464 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+};
+
+use anyhow::{Result, anyhow};
+
+use crate::{
+    core::tensor::Vec3f,
+    raycast::{
+        bounds::Bounds3f,
+        bvh::{BVH, LinearBVHNode},
+        bvhbuild::BVHBuilder,
+        bvhupdate::UpdateState,
+        primitive::Primitive,
+        widebvh::BVHWidth,
+    },
+};
+
+const BVH_CACHE_MAGIC: &[u8; 4] = b"IBVH";
+/// bump when layout changes, older files are rejected
+const BVH_CACHE_VERSION: u32 = 3;
+
+/// primitives are not stored, only hash of their bounds in order before build
+struct CacheHeader {
+    builder: String,
+    node_prims_limit: usize,
+    nprimitives: usize,
+    content_hash: u64,
+}
+
+impl<T: Primitive> BVH<T> {
+    /// load cached tree matching primitives and builder, otherwise build and write cache.
+    /// returns true if loaded from cache. fails if cache can not be written, tree is built anyway.
+    /// loaded tree is collapsed to width it was saved with, built tree stays binary
+    pub fn build_cached<B: BVHBuilder>(
+        &mut self,
+        builder: &B,
+        node_prims_limit: usize,
+        path: &str,
+    ) -> Result<bool> {
+        if self.load_cache(path, builder, node_prims_limit).is_ok() {
+            return Ok(true);
+        }
+
+        self.build_with(builder, node_prims_limit);
+        self.save_cache(path)
+            .map_err(|e| anyhow!("err: can not write bvh cache {path}, {e}"))?;
+        Ok(false)
+    }
+
+    /// write nodes, width, primitive order, build parameters and primitives hash
+    pub fn save_cache(&self, path: &str) -> Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+
+        w.write_all(BVH_CACHE_MAGIC)?;
+        write_u32(&mut w, BVH_CACHE_VERSION)?;
+        write_u64(&mut w, self.builder.len() as u64)?;
+        w.write_all(self.builder.as_bytes())?;
+        write_u64(&mut w, self.node_prims_limit as u64)?;
+        write_u64(&mut w, self.primitives.len() as u64)?;
+        write_u64(&mut w, self.content_hash())?;
+
+        w.write_all(&[self.split_refs as u8])?;
+        let width = match self.wide.width() {
+            BVHWidth::Binary => 2u8,
+            BVHWidth::Four => 4,
+            BVHWidth::Eight => 8,
+        };
+        w.write_all(&[width])?;
+        write_f32(&mut w, self.build_cost)?;
+        write_f32(&mut w, self.build_area)?;
+
+        // holes left by updates are not written
+        let compacted = self.compacted();
+        let (nodes, prim_refs) = match &compacted {
+            Some((nodes, refs)) => (nodes, refs),
+            None => (&self.nodes, &self.prim_refs),
+        };
+        write_u64(&mut w, nodes.len() as u64)?;
+        for node in nodes.iter() {
+            for a in 0..3 {
+                write_f32(&mut w, node.bounds.min[a])?;
+            }
+            for a in 0..3 {
+                write_f32(&mut w, node.bounds.max[a])?;
+            }
+            write_u32(&mut w, node.axis as u32)?;
+            write_u64(&mut w, node.nprimitives as u64)?;
+            write_u64(&mut w, node.offset as u64)?;
+        }
+
+        write_indices(&mut w, &self.prim_order)?;
+        write_indices(&mut w, prim_refs)?;
+        w.flush()?;
+        Ok(())
+    }
+
+    /// load tree built for current primitives, which must be in order before build.
+    /// nodes are collapsed again to saved width.
+    /// fails if file is of other version, primitives or build parameters
+    pub fn load_cache<B: BVHBuilder>(
+        &mut self,
+        path: &str,
+        builder: &B,
+        node_prims_limit: usize,
+    ) -> Result<()> {
+        let mut r = BufReader::new(File::open(path)?);
+
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != BVH_CACHE_MAGIC {
+            return Err(anyhow!("err: not a bvh cache"));
+        }
+        let version = read_u32(&mut r)?;
+        if version != BVH_CACHE_VERSION {
+            return Err(anyhow!(
+                "err: bvh cache version {version}, need {BVH_CACHE_VERSION}"
+            ));
+        }
+
+        let header = read_header(&mut r)?;
+        // build_with raises limit to at least 2
+        let node_prims_limit = node_prims_limit.max(2);
+        if header.builder != builder.describe() || header.node_prims_limit != node_prims_limit {
+            return Err(anyhow!("err: bvh cache built with other parameters"));
+        }
+        if header.nprimitives != self.primitives.len() || header.content_hash != self.content_hash()
+        {
+            return Err(anyhow!("err: bvh cache built for other primitives"));
+        }
+
+        let mut flag = [0u8; 1];
+        r.read_exact(&mut flag)?;
+        let split_refs = flag[0] != 0;
+        r.read_exact(&mut flag)?;
+        let width = match flag[0] {
+            2 => BVHWidth::Binary,
+            4 => BVHWidth::Four,
+            8 => BVHWidth::Eight,
+            _ => return Err(anyhow!("err: bvh cache width corrupted")),
+        };
+        let build_cost = read_f32(&mut r)?;
+        let build_area = read_f32(&mut r)?;
+
+        let nnodes = read_u64(&mut r)? as usize;
+        let mut nodes = Vec::with_capacity(nnodes.min(self.primitives.len() * 4 + 1));
+        for _ in 0..nnodes {
+            let min = Vec3f::vec([read_f32(&mut r)?, read_f32(&mut r)?, read_f32(&mut r)?]);
+            let max = Vec3f::vec([read_f32(&mut r)?, read_f32(&mut r)?, read_f32(&mut r)?]);
+            nodes.push(LinearBVHNode {
+                bounds: Bounds3f::new(min, max),
+                axis: read_u32(&mut r)? as usize,
+                nprimitives: read_u64(&mut r)? as usize,
+                offset: read_u64(&mut r)? as usize,
+            });
+        }
+
+        let n = self.primitives.len();
+        let prim_order = read_indices(&mut r, n)?;
+        let prim_refs = read_indices(&mut r, n)?;
+        // order before build must name every primitive once
+        let mut seen = vec![false; n];
+        let repeated = prim_order
+            .iter()
+            .any(|&i| std::mem::replace(&mut seen[i], true));
+        if !prim_order.is_empty() && (prim_order.len() != n || repeated) {
+            return Err(anyhow!("err: bvh cache primitive order corrupted"));
+        }
+
+        // leaves index slots, interior nodes index nodes after themselves
+        let nslots = if prim_refs.is_empty() {
+            n
+        } else {
+            prim_refs.len()
+        };
+        let valid = nodes.iter().enumerate().all(|(i, node)| {
+            if node.is_leaf() {
+                node.offset
+                    .checked_add(node.nprimitives)
+                    .is_some_and(|end| end <= nslots)
+            } else {
+                node.offset > i + 1 && node.offset < nnodes && node.axis < 3
+            }
+        });
+        if !valid {
+            return Err(anyhow!("err: bvh cache nodes corrupted"));
+        }
+
+        // back to order before build, if built already
+        if !self.prim_order.is_empty() {
+            let mut original = self.primitives.clone();
+            self.prim_order
+                .iter()
+                .zip(self.primitives.iter())
+                .for_each(|(&i, p)| original[i] = p.clone());
+            self.primitives = original;
+        }
+
+        if !prim_order.is_empty() {
+            self.primitives = prim_order
+                .iter()
+                .map(|&i| self.primitives[i].clone())
+                .collect();
+        }
+
+        self.builder = header.builder;
+        self.node_prims_limit = node_prims_limit;
+        self.nodes = nodes;
+        self.prim_order = prim_order;
+        self.prim_refs = prim_refs;
+        self.split_refs = split_refs;
+        self.build_cost = build_cost;
+        self.build_area = build_area;
+        self.update = UpdateState::default();
+        self.collapse(width);
+        self.invalid_prims = (0..n)
+            .filter(|&i| !self.primitives[i].bounds().is_finite())
+            .collect();
+        self.unbounded_prims = self.unbounded_of_invalid();
+        Ok(())
+    }
+
+    /// FNV-1a of primitive bounds in order before build
+    pub fn content_hash(&self) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut feed = |b: &Bounds3f| {
+            for a in 0..3 {
+                for v in [b.min[a], b.max[a]] {
+                    for byte in v.to_bits().to_le_bytes() {
+                        hash ^= byte as u64;
+                        hash = hash.wrapping_mul(0x100000001b3);
+                    }
+                }
+            }
+        };
+
+        if self.prim_order.is_empty() {
+            self.primitives.iter().for_each(|p| feed(&p.bounds()));
+        } else {
+            let mut slots = vec![0; self.prim_order.len()];
+            self.prim_order
+                .iter()
+                .enumerate()
+                .for_each(|(slot, &i)| slots[i] = slot);
+            slots
+                .iter()
+                .for_each(|&slot| feed(&self.primitives[slot].bounds()));
+        }
+
+        hash
+    }
+}
+
+fn read_header(r: &mut impl Read) -> Result<CacheHeader> {
+    let len = read_u64(r)? as usize;
+    let mut builder = Vec::new();
+    r.take(len as u64).read_to_end(&mut builder)?;
+    if builder.len() != len {
+        return Err(anyhow!("err: bvh cache truncated"));
+    }
+
+    Ok(CacheHeader {
+        builder: String::from_utf8(builder)?,
+        node_prims_limit: read_u64(r)? as usize,
+        nprimitives: read_u64(r)? as usize,
+        content_hash: read_u64(r)?,
+    })
+}
+
+fn write_u32(w: &mut impl Write, v: u32) -> Result<()> {
+    w.write_all(&v.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_u64(w: &mut impl Write, v: u64) -> Result<()> {
+    w.write_all(&v.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_f32(w: &mut impl Write, v: f32) -> Result<()> {
+    w.write_all(&v.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_indices(w: &mut impl Write, indices: &[usize]) -> Result<()> {
+    write_u64(w, indices.len() as u64)?;
+    for &i in indices.iter() {
+        write_u64(w, i as u64)?;
+    }
+    Ok(())
+}
+
+fn read_u32(r: &mut impl Read) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(r: &mut impl Read) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_f32(r: &mut impl Read) -> Result<f32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(f32::from_le_bytes(buf))
+}
+
+/// every index must be less than n
+fn read_indices(r: &mut impl Read, n: usize) -> Result<Vec<usize>> {
+    let len = read_u64(r)? as usize;
+    let mut indices = Vec::with_capacity(len.min(n * 4));
+    for _ in 0..len {
+        let i = read_u64(r)? as usize;
+        if i >= n {
+            return Err(anyhow!("err: bvh cache index out of range"));
+        }
+        indices.push(i);
+    }
+    Ok(indices)
+}
+
+#[test]
+fn test_bvh_cache() -> Result<()> {
+    use crate::raycast::{
+        Ray, Raycast,
+        bvhbuild::{MedianBuilder, SAHBuilder},
+        sbvh::SBVHBuilder,
+        sphere::Sphere,
+    };
+    use rand::Rng;
+
+    let n = 1024;
+    let mut rng = rand::rng();
+    let spheres: Vec<Sphere> = (0..n)
+        .map(|_| {
+            let cnt = Vec3f::vec([
+                rng.random_range(0.0..32.),
+                rng.random_range(0.0..32.),
+                rng.random_range(0.0..32.),
+            ]);
+            Sphere::new(cnt, rng.random_range(0.1..2.))
+        })
+        .collect();
+    let new_bvh = || {
+        let mut bvh = BVH::new(n);
+        spheres.iter().for_each(|s| bvh.push(s.clone()));
+        bvh
+    };
+
+    let path = std::env::temp_dir().join(format!("test_bvh_cache_{}.bvh", std::process::id()));
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+
+    let builder = SAHBuilder { par_build: true };
+    let mut built = new_bvh();
+    assert!(!built.build_cached(&builder, 9, path)?);
+    // twice reordered, cache must keep order before first build
+    built.build_with(&builder, 9);
+    built.save_cache(path)?;
+
+    // loading into a built tree
+    let mut loaded = new_bvh();
+    loaded.build_with(&MedianBuilder::default(), 9);
+    assert!(loaded.build_cached(&builder, 9, path)?);
+    assert_eq!(loaded.nodes, built.nodes);
+    assert_eq!(loaded.prim_order, built.prim_order);
+    assert_eq!(loaded.content_hash(), built.content_hash());
+    for i in 0..n {
+        assert_eq!(loaded.primitives[i].cnt, built.primitives[i].cnt);
+    }
+
+    for _ in 0..256 {
+        let org = Vec3f::vec([-8., rng.random_range(0.0..32.), rng.random_range(0.0..32.)]);
+        let target = Vec3f::vec([40., rng.random_range(0.0..32.), rng.random_range(0.0..32.)]);
+        let ray = Ray::new(org, target - org);
+        assert_eq!(
+            loaded.raycast(&ray).map(|h| h.t),
+            built.raycast(&ray).map(|h| h.t)
+        );
+    }
+
+    // collapsed width is kept
+    built.collapse(BVHWidth::Eight);
+    built.save_cache(path)?;
+    let mut wide = new_bvh();
+    assert!(wide.build_cached(&builder, 9, path)?);
+    assert_eq!(wide.wide.width(), BVHWidth::Eight);
+    for _ in 0..64 {
+        let org = Vec3f::vec([-8., rng.random_range(0.0..32.), rng.random_range(0.0..32.)]);
+        let ray = Ray::new(org, Vec3f::vec([1., 0., 0.]));
+        assert_eq!(
+            wide.raycast(&ray).map(|h| h.t),
+            built.raycast(&ray).map(|h| h.t)
+        );
+    }
+    built.collapse(BVHWidth::Binary);
+    built.save_cache(path)?;
+
+    // other builder, node limit or primitives
+    assert!(
+        new_bvh()
+            .load_cache(path, &MedianBuilder::default(), 9)
+            .is_err()
+    );
+    assert!(new_bvh().load_cache(path, &builder, 17).is_err());
+    let mut moved = new_bvh();
+    moved.primitives[7].cnt = moved.primitives[7].cnt + 1.;
+    assert!(moved.load_cache(path, &builder, 9).is_err());
+    let mut shuffled = new_bvh();
+    shuffled.primitives.swap(0, 1);
+    assert!(shuffled.load_cache(path, &builder, 9).is_err());
+
+    // corrupted nodes or order are rejected, not trusted
+    let bytes = std::fs::read(path)?;
+    let nodes_at = 4 + 4 + 8 + built.builder.len() + 8 + 8 + 8 + 1 + 1 + 4 + 4 + 8;
+    let node_size = 6 * 4 + 4 + 8 + 8;
+    let order_at = nodes_at + built.nodes.len() * node_size + 8;
+    let corrupted = |at: usize, value: u64| -> Result<bool> {
+        let mut bytes = bytes.clone();
+        bytes[at..at + 8].copy_from_slice(&value.to_le_bytes());
+        std::fs::write(path, &bytes)?;
+        Ok(new_bvh().load_cache(path, &builder, 9).is_err())
+    };
+    let leaf = built.nodes.iter().position(|n| n.is_leaf()).unwrap();
+    assert!(corrupted(nodes_at + leaf * node_size + 36, u64::MAX)?);
+    let first = u64::from_le_bytes(bytes[order_at..order_at + 8].try_into()?);
+    assert!(corrupted(order_at + 8, first)?);
+    assert!(!corrupted(order_at, first)?);
+
+    // other version
+    let mut bytes = std::fs::read(path)?;
+    bytes[4] = bytes[4].wrapping_add(1);
+    std::fs::write(path, &bytes)?;
+    assert!(new_bvh().load_cache(path, &builder, 9).is_err());
+
+    // references
+    let sbvh = SBVHBuilder::default();
+    let mut built = new_bvh();
+    assert!(!built.build_cached(&sbvh, 5, path)?);
+    let mut loaded = new_bvh();
+    assert!(loaded.build_cached(&sbvh, 5, path)?);
+    assert_eq!(loaded.nodes, built.nodes);
+    assert_eq!(loaded.prim_refs, built.prim_refs);
+    assert!(loaded.split_refs);
+
+    // unwritable cache is reported, tree is still built
+    let missing = std::env::temp_dir().join(format!("missing_{}/t.bvh", std::process::id()));
+    let mut unwritten = new_bvh();
+    assert!(
+        unwritten
+            .build_cached(&builder, 9, missing.to_str().unwrap())
+            .is_err()
+    );
+    let mut expected = new_bvh();
+    expected.build_with(&builder, 9);
+    assert_eq!(unwritten.nodes, expected.nodes);
+
+    std::fs::remove_file(path)?;
+    Ok(())
+}
@@ -0,0 +1,325 @@
+use crate::{
+    core::tensor::Vec3f,
+    raycast::{
+        bounds::Bounds3f, bvh::BVH, frustum::Frustum, primitive::Primitive, stack::TraversalStack,
+    },
+};
+
+impl<T: Primitive> BVH<T> {
+    /// nearest primitive to p by Primitive::distance, returns (distance, primitive index)
+    pub fn closest_point(&self, p: Vec3f) -> Option<(f32, usize)> {
+        self.knn(p, 1, f32::INFINITY).first().copied()
+    }
+
+    /// up to k nearest primitives within max_dist by Primitive::distance,
+    /// returns (distance, primitive index) sorted by distance
+    pub fn knn(&self, p: Vec3f, k: usize, max_dist: f32) -> Vec<(f32, usize)> {
+        let mut found: Vec<(f32, usize)> = Vec::with_capacity(k + 1);
+        if self.nodes.is_empty() || k == 0 {
+            return found;
+        }
+
+        // farthest distance still accepted
+        let bound = |found: &Vec<(f32, usize)>| {
+            if found.len() < k {
+                max_dist
+            } else {
+                found[k - 1].0
+            }
+        };
+
+        // (distance to node bounds, node) to visit
+        let mut nodes_to_visit: TraversalStack<(f32, usize)> = TraversalStack::new();
+        nodes_to_visit.push((self.nodes[0].bounds.distance(p), 0));
+
+        while let Some((d, node_i)) = nodes_to_visit.pop() {
+            // nearer primitives found after this entry was pushed
+            if d > bound(&found) {
+                continue;
+            }
+
+            let node = &self.nodes[node_i];
+            if node.is_leaf() {
+                for slot in node.offset..node.offset + node.nprimitives {
+                    let prim_i = self.prim_index(slot);
+                    let dist = self.primitives[prim_i].distance(p);
+                    // primitives referenced by several leaves are counted once
+                    if dist > bound(&found)
+                        || (self.split_refs && found.iter().any(|f| f.1 == prim_i))
+                    {
+                        continue;
+                    }
+
+                    let pos = found.partition_point(|f| f.0 <= dist);
+                    found.insert(pos, (dist, prim_i));
+                    found.truncate(k);
+                }
+            } else {
+                // push far child first so near child is visited next
+                let (c0, c1) = (node_i + 1, node.offset);
+                let (d0, d1) = (
+                    self.nodes[c0].bounds.distance(p),
+                    self.nodes[c1].bounds.distance(p),
+                );
+                let bound = bound(&found);
+                let (near, far) = if d0 <= d1 {
+                    ((d0, c0), (d1, c1))
+                } else {
+                    ((d1, c1), (d0, c0))
+                };
+                if far.0 <= bound {
+                    nodes_to_visit.push(far);
+                }
+                if near.0 <= bound {
+                    nodes_to_visit.push(near);
+                }
+            }
+        }
+
+        found
+    }
+
+    /// primitives whose bounds intersect frustum, conservative as Frustum::intersects_bounds.
+    /// with spatial splits nodes only bound the clipped parts of their primitives, so a
+    /// primitive is found only if a clipped part passes: the result is a subset of the
+    /// brute force one, same for query_aabb
+    pub fn query_frustum(&self, frustum: &Frustum) -> Vec<usize> {
+        self.query(
+            |b| frustum.intersects_bounds(b),
+            |p| frustum.intersects_bounds(&p.bounds()),
+        )
+    }
+
+    /// primitives whose bounds overlap bounds
+    pub fn query_aabb(&self, bounds: &Bounds3f) -> Vec<usize> {
+        let overlaps = |b: &Bounds3f| !b.intersect(bounds).is_empty();
+        self.query(overlaps, |p| overlaps(&p.bounds()))
+    }
+
+    /// primitives within r of center by Primitive::distance
+    pub fn query_sphere(&self, center: Vec3f, r: f32) -> Vec<usize> {
+        self.query(|b| b.distance(center) <= r, |p| p.distance(center) <= r)
+    }
+
+    /// indices of primitives passing prim_test under nodes passing node_test.
+    /// node_test must pass bounds of every node holding a primitive passing prim_test
+    fn query<N, P>(&self, node_test: N, prim_test: P) -> Vec<usize>
+    where
+        N: Fn(&Bounds3f) -> bool,
+        P: Fn(&T) -> bool,
+    {
+        let mut found = Vec::new();
+        if self.nodes.is_empty() {
+            return found;
+        }
+
+        let mut nodes_to_visit: TraversalStack<usize> = TraversalStack::new();
+        nodes_to_visit.push(0);
+        while let Some(node_i) = nodes_to_visit.pop() {
+            let node = &self.nodes[node_i];
+            if !node_test(&node.bounds) {
+                continue;
+            }
+
+            if node.is_leaf() {
+                for slot in node.offset..node.offset + node.nprimitives {
+                    let prim_i = self.prim_index(slot);
+                    if prim_test(&self.primitives[prim_i]) {
+                        found.push(prim_i);
+                    }
+                }
+            } else {
+                nodes_to_visit.push(node.offset);
+                nodes_to_visit.push(node_i + 1);
+            }
+        }
+
+        // primitives referenced by several leaves are reported once
+        if self.split_refs {
+            found.sort_unstable();
+            found.dedup();
+        }
+        found
+    }
+}
+
+#[test]
+fn test_bvh_point_queries() {
+    use crate::raycast::{bvhbuild::SAHBuilder, sbvh::SBVHBuilder, sphere::Sphere};
+    use rand::Rng;
+
+    let n = 2048;
+    let mut rng = rand::rng();
+    let spheres: Vec<Sphere> = (0..n)
+        .map(|_| {
+            let cnt = Vec3f::vec([
+                rng.random_range(0.0..64.),
+                rng.random_range(0.0..64.),
+                rng.random_range(0.0..16.),
+            ]);
+            Sphere::new(cnt, rng.random_range(0.05..1.))
+        })
+        .collect();
+
+    let queries: Vec<Vec3f> = (0..256)
+        .map(|_| {
+            Vec3f::vec([
+                rng.random_range(-8.0..72.),
+                rng.random_range(-8.0..72.),
+                rng.random_range(-8.0..24.),
+            ])
+        })
+        .collect();
+
+    fn check(bvh: &BVH<Sphere>, queries: &[Vec3f]) {
+        for &p in queries.iter() {
+            let mut expected: Vec<(f32, usize)> = bvh
+                .primitives
+                .iter()
+                .enumerate()
+                .map(|(i, s)| (s.distance(p), i))
+                .collect();
+            expected.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            assert_eq!(bvh.closest_point(p).map(|c| c.0), Some(expected[0].0));
+
+            let knn = bvh.knn(p, 16, f32::INFINITY);
+            assert_eq!(knn.len(), 16);
+            for (c, e) in knn.iter().zip(expected.iter()) {
+                assert_eq!(c.0, e.0);
+                assert_eq!(c.0, bvh.primitives[c.1].distance(p));
+            }
+            let mut ids: Vec<usize> = knn.iter().map(|c| c.1).collect();
+            ids.sort_unstable();
+            ids.dedup();
+            assert_eq!(ids.len(), knn.len());
+
+            let max_dist = expected[8].0;
+            let within = expected.iter().filter(|e| e.0 <= max_dist).count();
+            let knn = bvh.knn(p, 64, max_dist);
+            assert_eq!(knn.len(), within.min(64));
+            assert!(knn.iter().all(|c| c.0 <= max_dist));
+        }
+    }
+
+    let build = |f: &dyn Fn(&mut BVH<Sphere>)| {
+        let mut bvh = BVH::new(n);
+        spheres.iter().for_each(|s| bvh.push(s.clone()));
+        f(&mut bvh);
+        bvh
+    };
+
+    check(&build(&|bvh| bvh.build(9, true)), &queries);
+    check(
+        &build(&|bvh| bvh.build_with(&SAHBuilder { par_build: true }, 5)),
+        &queries,
+    );
+    // referenced primitives are reported once
+    check(
+        &build(&|bvh| bvh.build_with(&SBVHBuilder::default(), 5)),
+        &queries,
+    );
+
+    let empty: BVH<Sphere> = BVH::new(0);
+    assert!(empty.closest_point(queries[0]).is_none());
+    assert!(empty.knn(queries[0], 4, f32::INFINITY).is_empty());
+    assert!(
+        build(&|bvh| bvh.build(9, true))
+            .knn(queries[0], 0, 1.)
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_bvh_range_queries() {
+    use crate::raycast::{sbvh::SBVHBuilder, sphere::Sphere};
+    use crate::render::camera::Camera;
+    use rand::Rng;
+
+    let n = 4096;
+    let mut rng = rand::rng();
+    let mut bvh = BVH::new(n);
+    for _ in 0..n {
+        let cnt = Vec3f::vec([
+            rng.random_range(-32.0..32.),
+            rng.random_range(-32.0..32.),
+            rng.random_range(-32.0..32.),
+        ]);
+        bvh.push(Sphere::new(cnt, rng.random_range(0.05..1.)));
+    }
+
+    let cam = Camera::new(Vec3f::vec([0.; 3]), Vec3f::vec([0., 0., -1.]), 60., 1., 24.);
+    let frustum = cam.frustum((16, 9));
+    let boxes: Vec<Bounds3f> = (0..32)
+        .map(|_| {
+            let c = Vec3f::vec([
+                rng.random_range(-32.0..32.),
+                rng.random_range(-32.0..32.),
+                rng.random_range(-32.0..32.),
+            ]);
+            let r = Vec3f::vec([
+                rng.random_range(0.0..8.),
+                rng.random_range(0.0..8.),
+                rng.random_range(0.0..8.),
+            ]);
+            Bounds3f::new(c - r, c + r)
+        })
+        .collect();
+
+    fn sorted(mut v: Vec<usize>) -> Vec<usize> {
+        v.sort_unstable();
+        v
+    }
+    fn brute(bvh: &BVH<Sphere>, f: impl Fn(&Sphere) -> bool) -> Vec<usize> {
+        (0..bvh.primitives.len())
+            .filter(|&i| f(&bvh.primitives[i]))
+            .collect()
+    }
+
+    // spatial splits may drop primitives whose clipped parts all miss the query
+    fn check(refs: bool, found: &[usize], brute: &[usize]) {
+        if refs {
+            assert!(found.iter().all(|i| brute.binary_search(i).is_ok()));
+        } else {
+            assert_eq!(found, brute);
+        }
+    }
+
+    for refs in [false, true] {
+        if refs {
+            bvh.build_with(&SBVHBuilder::default(), 5);
+        } else {
+            bvh.build(9, true);
+        }
+
+        let visible = sorted(bvh.query_frustum(&frustum));
+        assert!(!visible.is_empty());
+        check(
+            refs,
+            &visible,
+            &brute(&bvh, |s| frustum.intersects_bounds(&s.bounds())),
+        );
+        // spheres with center in view are never culled
+        for s in bvh.primitives.iter().filter(|s| frustum.contains(s.cnt)) {
+            assert!(visible.iter().any(|&i| bvh.primitives[i].cnt == s.cnt));
+        }
+
+        for b in boxes.iter() {
+            check(
+                refs,
+                &sorted(bvh.query_aabb(b)),
+                &brute(&bvh, |s| !s.bounds().intersect(b).is_empty()),
+            );
+
+            let (c, r) = (b.centroid(), b.diagonal()[0]);
+            // clipped parts still bound the geometry distance is measured to
+            assert_eq!(
+                sorted(bvh.query_sphere(c, r)),
+                brute(&bvh, |s| s.distance(c) <= r)
+            );
+        }
+    }
+
+    let empty: BVH<Sphere> = BVH::new(0);
+    assert!(empty.query_frustum(&frustum).is_empty());
+}
@@ -0,0 +1,253 @@
+use std::{fmt::Display, mem::size_of};
+
+use crate::raycast::{
+    Hit, Ray,
+    bvh::{BVH, LinearBVHNode},
+    primitive::Primitive,
+    widebvh::{WideBVHNode, WideNodes},
+};
+
+/// tree shape and quality of a built bvh
+#[derive(Debug, Default, Clone)]
+pub struct BVHStats {
+    pub nodes: usize,
+    pub interior_nodes: usize,
+    pub leaves: usize,
+    pub primitives: usize,
+    /// leaf slots, more than primitives if spatial splits referenced them twice
+    pub references: usize,
+    pub max_depth: usize,
+    /// leaves at each depth, root at depth 0
+    pub depth_histogram: Vec<usize>,
+    /// leaves holding each number of primitives
+    pub leaf_size_histogram: Vec<usize>,
+    pub sah_cost: f32,
+    /// area shared by sibling nodes relative to their parent area, mean and max over interior nodes
+    pub mean_sibling_overlap: f32,
+    pub max_sibling_overlap: f32,
+    /// bytes of nodes and index arrays, primitives excluded
+    pub memory_bytes: usize,
+}
+
+/// node visits and primitive tests of one traversal
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TraversalCounters {
+    pub nodes: usize,
+    pub prims: usize,
+}
+
+/// hooks of instrumented traversal, () counts nothing
+pub(crate) trait TraversalCounter {
+    fn node(&mut self) {}
+    fn prim(&mut self) {}
+}
+
+impl TraversalCounter for () {}
+
+impl TraversalCounter for TraversalCounters {
+    #[inline]
+    fn node(&mut self) {
+        self.nodes += 1;
+    }
+
+    #[inline]
+    fn prim(&mut self) {
+        self.prims += 1;
+    }
+}
+
+impl<T: Primitive> BVH<T> {
+    pub fn stats(&self) -> BVHStats {
+        let mut stats = BVHStats {
+            nodes: self.nodes.len(),
+            primitives: self.primitives.len(),
+            sah_cost: self.sah_cost(),
+            ..Default::default()
+        };
+
+        let wide_bytes = match &self.wide {
+            WideNodes::None => 0,
+            WideNodes::Four(nodes) => nodes.len() * size_of::<WideBVHNode<4>>(),
+            WideNodes::Eight(nodes) => nodes.len() * size_of::<WideBVHNode<8>>(),
+        };
+        stats.memory_bytes = self.nodes.len() * size_of::<LinearBVHNode>()
+            + (self.prim_refs.len()
+                + self.prim_order.len()
+                + self.invalid_prims.len()
+                + self.unbounded_prims.len())
+                * size_of::<usize>()
+            + wide_bytes;
+
+        if self.nodes.is_empty() {
+            return stats;
+        }
+
+        let mut overlap_sum = 0.;
+        let mut nodes_to_visit = vec![(0, 0)];
+        while let Some((node_i, depth)) = nodes_to_visit.pop() {
+            let node = &self.nodes[node_i];
+            if node.is_leaf() {
+                stats.leaves += 1;
+                stats.references += node.nprimitives;
+                stats.max_depth = stats.max_depth.max(depth);
+
+                if stats.depth_histogram.len() <= depth {
+                    stats.depth_histogram.resize(depth + 1, 0);
+                }
+                stats.depth_histogram[depth] += 1;
+                if stats.leaf_size_histogram.len() <= node.nprimitives {
+                    stats.leaf_size_histogram.resize(node.nprimitives + 1, 0);
+                }
+                stats.leaf_size_histogram[node.nprimitives] += 1;
+            } else {
+                stats.interior_nodes += 1;
+                let (c0, c1) = (&self.nodes[node_i + 1], &self.nodes[node.offset]);
+                let overlap = c0.bounds.intersect(&c1.bounds);
+                let area = node.bounds.area();
+                let ratio = if !overlap.is_empty() && area > 0. {
+                    overlap.area() / area
+                } else {
+                    0.
+                };
+                overlap_sum += ratio;
+                stats.max_sibling_overlap = stats.max_sibling_overlap.max(ratio);
+
+                nodes_to_visit.push((node.offset, depth + 1));
+                nodes_to_visit.push((node_i + 1, depth + 1));
+            }
+        }
+
+        // nodes left unreachable by updates are not counted
+        stats.nodes = stats.interior_nodes + stats.leaves;
+        if stats.interior_nodes > 0 {
+            stats.mean_sibling_overlap = overlap_sum / stats.interior_nodes as f32;
+        }
+        stats
+    }
+
+    /// nearest hit with node visits and primitive tests spent on it
+    pub fn raycast_counted(&self, ray: &Ray) -> (Option<(Hit, usize)>, TraversalCounters) {
+        let mut counters = TraversalCounters::default();
+        let hit = self.traverse(ray, false, false, |_, _, _| false, &mut counters);
+        (hit, counters)
+    }
+}
+
+impl Display for BVHStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "nodes {} (interior {}, leaves {}), primitives {}, references {}",
+            self.nodes, self.interior_nodes, self.leaves, self.primitives, self.references
+        )?;
+        writeln!(
+            f,
+            "sah cost {:.3}, sibling overlap mean {:.3} max {:.3}, memory {} KiB",
+            self.sah_cost,
+            self.mean_sibling_overlap,
+            self.max_sibling_overlap,
+            self.memory_bytes / 1024
+        )?;
+        writeln!(f, "max depth {}, leaves per depth:", self.max_depth)?;
+        for (depth, &n) in self.depth_histogram.iter().enumerate() {
+            if n > 0 {
+                writeln!(f, "  {depth:>3}: {n}")?;
+            }
+        }
+        writeln!(f, "leaves per size:")?;
+        for (size, &n) in self.leaf_size_histogram.iter().enumerate() {
+            if n > 0 {
+                writeln!(f, "  {size:>3}: {n}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_bvh_stats() {
+    use crate::core::tensor::Vec3f;
+    use crate::raycast::{bounds::Bounds3f, sphere::Sphere, widebvh::BVHWidth};
+    use rand::Rng;
+
+    // two overlapping unit spheres under one root
+    let mut bvh = BVH::new(2);
+    bvh.push(Sphere::new(Vec3f::vec([0., 0., 0.]), 1.));
+    bvh.push(Sphere::new(Vec3f::vec([1., 0., 0.]), 1.));
+    let leaf = |i: usize| LinearBVHNode {
+        bounds: bvh.primitives[i].bounds(),
+        axis: 0,
+        nprimitives: 1,
+        offset: i,
+    };
+    bvh.nodes = vec![
+        LinearBVHNode {
+            bounds: Bounds3f::new(Vec3f::vec([-1.; 3]), Vec3f::vec([2., 1., 1.])),
+            axis: 0,
+            nprimitives: 0,
+            offset: 2,
+        },
+        leaf(0),
+        leaf(1),
+    ];
+
+    let stats = bvh.stats();
+    assert_eq!((stats.nodes, stats.interior_nodes, stats.leaves), (3, 1, 2));
+    assert_eq!(stats.depth_histogram, vec![0, 2]);
+    assert_eq!(stats.leaf_size_histogram, vec![0, 2]);
+    // overlap 1x2x2 box, area 16 of parent 3x2x2 box, area 32
+    assert_eq!(stats.mean_sibling_overlap, 0.5);
+    assert_eq!(stats.max_sibling_overlap, 0.5);
+    assert_eq!(stats.memory_bytes, 3 * size_of::<LinearBVHNode>());
+
+    let n = 2048;
+    let mut rng = rand::rng();
+    let mut bvh = BVH::new(n);
+    for _ in 0..n {
+        let cnt = Vec3f::vec([
+            rng.random_range(0.0..64.),
+            rng.random_range(0.0..64.),
+            rng.random_range(0.0..64.),
+        ]);
+        bvh.push(Sphere::new(cnt, rng.random_range(0.1..1.)));
+    }
+    bvh.build(9, true);
+
+    let stats = bvh.stats();
+    println!("{stats}");
+    assert_eq!(stats.nodes, stats.interior_nodes + stats.leaves);
+    assert_eq!(stats.interior_nodes + 1, stats.leaves);
+    assert_eq!(stats.references, n);
+    assert_eq!(stats.depth_histogram.iter().sum::<usize>(), stats.leaves);
+    assert_eq!(stats.depth_histogram.len(), stats.max_depth + 1);
+    let (mut leaves, mut refs) = (0, 0);
+    for (size, &count) in stats.leaf_size_histogram.iter().enumerate() {
+        leaves += count;
+        refs += size * count;
+    }
+    assert_eq!((leaves, refs), (stats.leaves, n));
+    assert!(stats.leaf_size_histogram.len() <= 9);
+    assert_eq!(stats.sah_cost, bvh.sah_cost());
+    assert!(stats.mean_sibling_overlap <= stats.max_sibling_overlap);
+
+    let miss = Ray::new(Vec3f::vec([-1.; 3]), Vec3f::vec([-1., 0., 0.]));
+    let hit_ray = Ray::new(Vec3f::vec([32., 32., -8.]), Vec3f::vec([0., 0., 1.]));
+    for width in [BVHWidth::Binary, BVHWidth::Four, BVHWidth::Eight] {
+        bvh.collapse(width);
+        let (hit, counters) = bvh.raycast_counted(&miss);
+        assert!(hit.is_none());
+        assert_eq!(counters, TraversalCounters { nodes: 1, prims: 0 });
+
+        for _ in 0..64 {
+            let org = Vec3f::vec([rng.random_range(0.0..64.), rng.random_range(0.0..64.), -8.]);
+            let ray = Ray::new(org, hit_ray.dir);
+            let (hit, counters) = bvh.raycast_counted(&ray);
+            assert_eq!(hit.map(|h| h.0.t), bvh.raycast_node(&ray).map(|h| h.0.t));
+            assert!(counters.nodes >= 1);
+            if hit.is_some() {
+                assert!(counters.prims >= 1);
+            }
+        }
+    }
+    assert!(bvh.stats().memory_bytes > stats.memory_bytes);
+}
@@ -0,0 +1,852 @@
+use crate::raycast::{
+    bounds::Bounds3f,
+    bvh::{BVH, LinearBVHNode},
+    bvhbuild::{BVHBuilder, SAHBuilder, TRAVERSAL_COST},
+    primitive::Primitive,
+    widebvh::{BVHWidth, NO_WIDE, WideBVHNode, WideNodes},
+};
+
+const NO_PARENT: usize = usize::MAX;
+/// parent of nodes no longer reachable from root
+const DEAD: usize = usize::MAX - 1;
+/// unreachable nodes and slots allowed beyond reachable ones before compaction
+const GARBAGE_SLACK: usize = 64;
+
+/// links kept up to date by insert and remove, so they only walk one path of the tree.
+/// moved and replaced nodes leave holes, compacted once they outnumber reachable nodes
+#[derive(Debug, Default, Clone)]
+pub(crate) struct UpdateState {
+    /// links are set up by first update after build, refit or load
+    active: bool,
+    /// parent of every node, NO_PARENT for root
+    parents: Vec<usize>,
+    /// leaf of every slot, NO_PARENT for unused slots
+    slot_leaf: Vec<usize>,
+    /// first slot of every primitive, slots of spatial splits are chained by next_slot
+    prim_slot: Vec<usize>,
+    next_slot: Vec<usize>,
+    live_nodes: usize,
+    live_slots: usize,
+    /// sum of cost times area of reachable nodes, sah cost before division by root area
+    area_cost: f64,
+    /// wide node rooted at every binary node, NO_WIDE for changed nodes
+    pub(crate) wide_root: Vec<usize>,
+}
+
+fn node_cost(node: &LinearBVHNode) -> f64 {
+    let c = if node.is_leaf() {
+        node.nprimitives as f32
+    } else {
+        TRAVERSAL_COST
+    };
+    (c * node.bounds.area()) as f64
+}
+
+fn wide_len(wide: &WideNodes) -> usize {
+    match wide {
+        WideNodes::None => 0,
+        WideNodes::Four(nodes) => nodes.len(),
+        WideNodes::Eight(nodes) => nodes.len(),
+    }
+}
+
+impl<T: Primitive> BVH<T> {
+    /// update node bounds bottom-up after primitives moved, tree topology is kept.
+    /// leaves built with spatial splits grow to whole primitives
+    pub fn refit(&mut self) {
+        // children must follow parent, holes left by updates are compacted
+        if let Some((nodes, refs)) = self.compacted() {
+            self.nodes = nodes;
+            self.prim_refs = refs;
+            self.update = UpdateState::default();
+        }
+        for i in (0..self.nodes.len()).rev() {
+            self.nodes[i].bounds = self.fit_node(i);
+        }
+        self.recollapse();
+    }
+
+    /// sah cost relative to cost after last full build or rebuild of root, 1 for fresh build.
+    /// grows with refits, insertions and removals, full rebuild is usually due above 1.5
+    pub fn degradation(&self) -> f32 {
+        if self.build_cost <= 0. || self.nodes.is_empty() {
+            return 1.;
+        }
+        // both costs relative to root area of build, a grown root costs more
+        let root_area = self.nodes[0].bounds.area();
+        if self.build_area > 0. && root_area > 0. {
+            self.current_cost() * root_area / (self.build_cost * self.build_area)
+        } else {
+            self.current_cost() / self.build_cost
+        }
+    }
+
+    /// add primitive into leaf growing least in area, the leaf is rebuilt locally.
+    /// returns index of inserted primitive
+    pub fn insert(&mut self, prim: T) -> usize {
+        self.begin_update();
+        // primitives changed, current order counts as order before build
+        self.prim_order.clear();
+        let bounds = prim.bounds();
+        self.primitives.push(prim);
+        self.update
+            .prim_slot
+            .resize(self.primitives.len(), NO_PARENT);
+        let prim_i = self.primitives.len() - 1;
+
+        if !bounds.is_finite() {
+            if self.primitives[prim_i].unbounded() {
+                self.unbounded_prims.push(prim_i);
+            } else {
+                eprintln!("warn: primitive {prim_i} with NaN or infinite bounds left out of bvh");
+            }
+            self.invalid_prims.push(prim_i);
+            return prim_i;
+        }
+
+        let (target, refs) = if self.nodes.is_empty() {
+            // primitives pushed without build join the first leaf
+            let refs = (0..self.primitives.len())
+                .filter(|i| !self.invalid_prims.contains(i))
+                .collect();
+            (0, refs)
+        } else {
+            let mut node_i = 0;
+            while !self.nodes[node_i].is_leaf() {
+                let (c0, c1) = (node_i + 1, self.nodes[node_i].offset);
+                let growth = |c: usize| {
+                    let b = self.nodes[c].bounds;
+                    b.union(bounds).area() - b.area()
+                };
+                node_i = if growth(c1) < growth(c0) { c1 } else { c0 };
+            }
+            let mut refs = self.subtree_refs(node_i);
+            refs.push(prim_i);
+            (node_i, refs)
+        };
+
+        // sah decides if grown leaf is split, also keeps leaves under node_prims_limit
+        let (sub, refs) = self.build_over(refs);
+        self.place(target, sub, refs);
+
+        self.finish_update();
+        prim_i
+    }
+
+    /// remove primitive and every leaf slot referencing it, emptied leaves are merged into parent.
+    /// last primitive takes index of removed one, as Vec::swap_remove
+    pub fn remove(&mut self, prim_i: usize) -> T {
+        self.begin_update();
+        self.prim_order.clear();
+
+        while self.update.prim_slot[prim_i] != NO_PARENT {
+            self.remove_slot(self.update.prim_slot[prim_i]);
+        }
+
+        let last = self.primitives.len() - 1;
+        let prim = self.primitives.swap_remove(prim_i);
+        self.update.prim_slot.swap_remove(prim_i);
+        self.invalid_prims.retain(|&i| i != prim_i);
+        self.unbounded_prims.retain(|&i| i != prim_i);
+        self.invalid_prims
+            .iter_mut()
+            .chain(self.unbounded_prims.iter_mut())
+            .for_each(|r| {
+                if *r == last {
+                    *r = prim_i;
+                }
+            });
+        // slots of moved primitive are renamed through its slot chain
+        let mut slot = self
+            .update
+            .prim_slot
+            .get(prim_i)
+            .copied()
+            .unwrap_or(NO_PARENT);
+        while slot != NO_PARENT {
+            self.prim_refs[slot] = prim_i;
+            slot = self.update.next_slot[slot];
+        }
+
+        self.finish_update();
+        prim
+    }
+
+    /// rebuild subtree under node with binned SAH, e.g. after many insertions into it.
+    /// rebuilding root is a full build for degradation
+    pub fn rebuild_subtree(&mut self, node_i: usize) {
+        self.begin_update();
+        let refs = self.subtree_refs(node_i);
+        let (sub, refs) = self.build_over(refs);
+        self.place(node_i, sub, refs);
+        self.finish_update();
+        if node_i == 0 && !self.nodes.is_empty() {
+            self.build_cost = self.current_cost();
+            self.build_area = self.nodes[0].bounds.area();
+        }
+    }
+
+    /// nodes and slots reachable from root stored depth first, as after a build.
+    /// None if nodes are unchanged since last build
+    pub(crate) fn compacted(&self) -> Option<(Vec<LinearBVHNode>, Vec<usize>)> {
+        if !self.update.active {
+            return None;
+        }
+        let mut nodes: Vec<LinearBVHNode> = Vec::with_capacity(self.update.live_nodes);
+        let mut refs = Vec::with_capacity(self.update.live_slots);
+        if self.nodes.is_empty() {
+            return Some((nodes, refs));
+        }
+
+        // (node, copied parent of second child)
+        let mut stack = vec![(0, NO_PARENT)];
+        while let Some((i, parent)) = stack.pop() {
+            if parent != NO_PARENT {
+                nodes[parent].offset = nodes.len();
+            }
+            let mut node = self.nodes[i].clone();
+            if node.is_leaf() {
+                let start = refs.len();
+                refs.extend(
+                    (node.offset..node.offset + node.nprimitives).map(|s| self.prim_index(s)),
+                );
+                node.offset = start;
+            } else {
+                stack.push((node.offset, nodes.len()));
+                stack.push((i + 1, NO_PARENT));
+            }
+            nodes.push(node);
+        }
+        Some((nodes, refs))
+    }
+
+    /// leaf slots become primitive indices, primitives are not moved afterwards
+    fn use_refs(&mut self) {
+        // invalid primitives follow tree primitives after a build
+        if self.prim_refs.is_empty() && !self.nodes.is_empty() {
+            self.prim_refs = (0..self.primitives.len() - self.invalid_prims.len()).collect();
+        }
+    }
+
+    /// collapsed nodes follow binary nodes
+    fn recollapse(&mut self) {
+        let width = self.wide.width();
+        self.collapse(width);
+    }
+
+    fn fit_node(&self, i: usize) -> Bounds3f {
+        let node = &self.nodes[i];
+        if node.is_leaf() {
+            (node.offset..node.offset + node.nprimitives).fold(Bounds3f::empty(), |acc, s| {
+                acc.union(self.primitives[self.prim_index(s)].bounds())
+            })
+        } else {
+            self.nodes[i + 1]
+                .bounds
+                .union(self.nodes[node.offset].bounds)
+        }
+    }
+
+    /// sah cost kept by updates, walks the tree only without them
+    fn current_cost(&self) -> f32 {
+        if !self.update.active {
+            return self.sah_cost();
+        }
+        if self.nodes.is_empty() {
+            return 0.;
+        }
+        let cost = self.update.area_cost as f32;
+        let root_area = self.nodes[0].bounds.area();
+        if root_area > 0. {
+            cost / root_area
+        } else {
+            cost
+        }
+    }
+
+    /// link nodes to parents and slots to leaves, once after build
+    fn begin_update(&mut self) {
+        self.use_refs();
+        if self.update.active {
+            return;
+        }
+        self.update = UpdateState {
+            active: true,
+            parents: vec![DEAD; self.nodes.len()],
+            slot_leaf: vec![NO_PARENT; self.prim_refs.len()],
+            prim_slot: vec![NO_PARENT; self.primitives.len()],
+            next_slot: vec![NO_PARENT; self.prim_refs.len()],
+            ..Default::default()
+        };
+
+        let mut stack = if self.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![(0, NO_PARENT)]
+        };
+        while let Some((i, parent)) = stack.pop() {
+            let node = self.nodes[i].clone();
+            self.update.parents[i] = parent;
+            self.update.live_nodes += 1;
+            self.update.area_cost += node_cost(&node);
+            if node.is_leaf() {
+                (node.offset..node.offset + node.nprimitives).for_each(|s| self.attach_slot(s, i));
+            } else {
+                stack.push((node.offset, i));
+                stack.push((i + 1, i));
+            }
+        }
+        self.collapse_recording();
+    }
+
+    /// compact once holes outnumber reachable nodes, else collapse changed wide nodes
+    fn finish_update(&mut self) {
+        let u = &self.update;
+        if self.nodes.len() > 2 * u.live_nodes + GARBAGE_SLACK
+            || self.prim_refs.len() > 2 * u.live_slots + GARBAGE_SLACK
+        {
+            let (nodes, refs) = self.compacted().unwrap();
+            self.nodes = nodes;
+            self.prim_refs = refs;
+            self.update = UpdateState::default();
+            self.begin_update();
+        } else {
+            self.recollapse_changed();
+        }
+    }
+
+    /// collapse from scratch, recording wide node of every binary node
+    fn collapse_recording(&mut self) {
+        let mut roots = vec![NO_WIDE; self.nodes.len()];
+        self.wide = match self.wide.width() {
+            _ if self.nodes.is_empty() => WideNodes::None,
+            BVHWidth::Binary => WideNodes::None,
+            BVHWidth::Four => WideNodes::Four(self.collapse_nodes(&mut roots)),
+            BVHWidth::Eight => WideNodes::Eight(self.collapse_nodes(&mut roots)),
+        };
+        self.update.wide_root = match self.wide {
+            WideNodes::None => Vec::new(),
+            _ => roots,
+        };
+    }
+
+    /// collapse again wide nodes over changed binary nodes, others are reused
+    fn recollapse_changed(&mut self) {
+        if self.nodes.is_empty() {
+            self.wide = WideNodes::None;
+            return;
+        }
+        if self.wide.width() == BVHWidth::Binary {
+            return;
+        }
+        // replaced wide nodes are left behind like binary ones
+        if self.update.wide_root.len() != self.nodes.len()
+            || wide_len(&self.wide) > self.update.live_nodes + GARBAGE_SLACK
+        {
+            self.collapse_recording();
+            return;
+        }
+
+        let mut wide = std::mem::take(&mut self.wide);
+        let mut roots = std::mem::take(&mut self.update.wide_root);
+        match &mut wide {
+            WideNodes::None => {}
+            WideNodes::Four(nodes) => self.collapse_root(nodes, &mut roots),
+            WideNodes::Eight(nodes) => self.collapse_root(nodes, &mut roots),
+        }
+        self.wide = wide;
+        self.update.wide_root = roots;
+    }
+
+    /// traversal starts at wide node 0
+    fn collapse_root<const W: usize>(&self, wide: &mut Vec<WideBVHNode<W>>, roots: &mut [usize]) {
+        match roots[0] {
+            0 => {}
+            NO_WIDE => {
+                self.collapse_node(wide, 0, Some(0), roots);
+            }
+            w => {
+                wide[0] = wide[w];
+                roots[0] = 0;
+            }
+        }
+    }
+
+    /// primitives under node, in slot order
+    fn subtree_refs(&self, node_i: usize) -> Vec<usize> {
+        let mut refs = Vec::new();
+        let mut stack = vec![node_i];
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i];
+            if node.is_leaf() {
+                refs.extend(&self.prim_refs[node.offset..node.offset + node.nprimitives]);
+            } else {
+                stack.push(node.offset);
+                stack.push(i + 1);
+            }
+        }
+        refs
+    }
+
+    /// binned SAH subtree over primitives, nodes with offsets relative to subtree
+    /// and primitive index of its slots
+    fn build_over(&self, mut indices: Vec<usize>) -> (Vec<LinearBVHNode>, Vec<usize>) {
+        // references of spatial splits are merged back into whole primitives
+        indices.sort_unstable();
+        indices.dedup();
+
+        let prims: Vec<T> = indices
+            .iter()
+            .map(|&i| self.primitives[i].clone())
+            .collect();
+        let tree = SAHBuilder { par_build: false }.build_tree(&prims, self.node_prims_limit);
+        let refs = tree.prim_order.iter().map(|&i| indices[i]).collect();
+        (tree.flatten(), refs)
+    }
+
+    fn attach_slot(&mut self, slot: usize, leaf_i: usize) {
+        let u = &mut self.update;
+        let prim_i = self.prim_refs[slot];
+        u.slot_leaf[slot] = leaf_i;
+        u.next_slot[slot] = u.prim_slot[prim_i];
+        u.prim_slot[prim_i] = slot;
+        u.live_slots += 1;
+    }
+
+    fn detach_slot(&mut self, slot: usize) {
+        let u = &mut self.update;
+        let prim_i = self.prim_refs[slot];
+        if u.prim_slot[prim_i] == slot {
+            u.prim_slot[prim_i] = u.next_slot[slot];
+        } else {
+            let mut prev = u.prim_slot[prim_i];
+            while u.next_slot[prev] != slot {
+                prev = u.next_slot[prev];
+            }
+            u.next_slot[prev] = u.next_slot[slot];
+        }
+        u.slot_leaf[slot] = NO_PARENT;
+        u.next_slot[slot] = NO_PARENT;
+        u.live_slots -= 1;
+    }
+
+    /// wide node over binary node is collapsed again by next update
+    fn touch(&mut self, i: usize) {
+        if let Some(root) = self.update.wide_root.get_mut(i) {
+            *root = NO_WIDE;
+        }
+    }
+
+    /// unused node at end
+    fn push_node(&mut self) -> usize {
+        self.nodes.push(LinearBVHNode::default());
+        self.update.parents.push(DEAD);
+        if !self.update.wide_root.is_empty() {
+            self.update.wide_root.push(NO_WIDE);
+        }
+        self.nodes.len() - 1
+    }
+
+    fn write_node(&mut self, i: usize, node: LinearBVHNode, parent: usize) {
+        self.update.area_cost += node_cost(&node);
+        self.update.live_nodes += 1;
+        self.update.parents[i] = parent;
+        self.nodes[i] = node;
+        self.touch(i);
+    }
+
+    fn kill_node(&mut self, i: usize) {
+        self.update.area_cost -= node_cost(&self.nodes[i]);
+        self.update.live_nodes -= 1;
+        self.update.parents[i] = DEAD;
+        self.touch(i);
+    }
+
+    fn set_node(&mut self, i: usize, node: LinearBVHNode) {
+        self.update.area_cost += node_cost(&node) - node_cost(&self.nodes[i]);
+        self.nodes[i] = node;
+        self.touch(i);
+    }
+
+    /// fit bounds from node up to root
+    fn refit_path(&mut self, mut i: usize) {
+        while i != NO_PARENT {
+            let bounds = self.fit_node(i);
+            self.set_node(
+                i,
+                LinearBVHNode {
+                    bounds,
+                    ..self.nodes[i].clone()
+                },
+            );
+            i = self.update.parents[i];
+        }
+    }
+
+    /// nodes reached from node through first children, ending with a leaf
+    fn spine_len(&self, node_i: usize) -> usize {
+        let mut i = node_i;
+        while !self.nodes[i].is_leaf() {
+            i += 1;
+        }
+        i - node_i + 1
+    }
+
+    /// move spine to dst.., which must be unused or hold the spine itself behind dst.
+    /// subtrees hanging off the spine stay, their wide nodes are kept
+    fn move_spine(&mut self, src: usize, dst: usize, parent: usize) {
+        for j in 0..self.spine_len(src) {
+            let (from, to) = (src + j, dst + j);
+            if to == self.nodes.len() {
+                self.push_node();
+            }
+            let node = self.nodes[from].clone();
+            let root = self.update.wide_root.get(from).copied();
+            self.kill_node(from);
+            self.write_node(to, node.clone(), if j == 0 { parent } else { to - 1 });
+            if let Some(root) = root {
+                self.update.wide_root[to] = root;
+            }
+            if node.is_leaf() {
+                (node.offset..node.offset + node.nprimitives)
+                    .for_each(|s| self.update.slot_leaf[s] = to);
+            } else {
+                self.update.parents[node.offset] = to;
+            }
+        }
+    }
+
+    /// make position unused, a spine starting there moves to end.
+    /// returns new place of moved spine
+    fn free_position(&mut self, i: usize) -> Option<usize> {
+        if i == self.nodes.len() {
+            self.push_node();
+            return None;
+        }
+        if self.update.parents[i] == DEAD {
+            return None;
+        }
+        // node before is a leaf or unused, so node is a second child
+        let parent = self.update.parents[i];
+        let end = self.nodes.len();
+        self.move_spine(i, end, parent);
+        self.nodes[parent].offset = end;
+        Some(end)
+    }
+
+    /// replace subtree at node_i, or empty tree, with subtree of build_over.
+    /// its spine takes the place of the old one, other nodes and slots go to end
+    fn place(&mut self, node_i: usize, sub: Vec<LinearBVHNode>, refs: Vec<usize>) {
+        if self.nodes.is_empty() {
+            self.push_node();
+            self.update.parents[0] = NO_PARENT;
+        } else {
+            let parent = self.update.parents[node_i];
+            self.kill_subtree(node_i);
+            // position stays reserved, parent is followed if it moves
+            self.update.parents[node_i] = parent;
+        }
+
+        let spine = sub.iter().position(|node| node.is_leaf()).unwrap() + 1;
+        for j in 1..spine {
+            self.free_position(node_i + j);
+        }
+        let parent = self.update.parents[node_i];
+        let base = self.nodes.len();
+        let index = |j: usize| {
+            if j < spine {
+                node_i + j
+            } else {
+                base + j - spine
+            }
+        };
+        (spine..sub.len()).for_each(|_| {
+            self.push_node();
+        });
+
+        let slot_base = self.prim_refs.len();
+        self.prim_refs.extend(&refs);
+        self.update
+            .slot_leaf
+            .resize(self.prim_refs.len(), NO_PARENT);
+        self.update
+            .next_slot
+            .resize(self.prim_refs.len(), NO_PARENT);
+
+        let mut parents = vec![parent; sub.len()];
+        for (j, mut node) in sub.into_iter().enumerate() {
+            let i = index(j);
+            if node.is_leaf() {
+                node.offset += slot_base;
+                (node.offset..node.offset + node.nprimitives).for_each(|s| self.attach_slot(s, i));
+            } else {
+                parents[j + 1] = i;
+                parents[node.offset] = i;
+                node.offset = index(node.offset);
+            }
+            self.write_node(i, node, parents[j]);
+        }
+        self.refit_path(parent);
+    }
+
+    fn kill_subtree(&mut self, node_i: usize) {
+        let mut stack = vec![node_i];
+        while let Some(i) = stack.pop() {
+            let node = self.nodes[i].clone();
+            if node.is_leaf() {
+                (node.offset..node.offset + node.nprimitives).for_each(|s| self.detach_slot(s));
+            } else {
+                stack.push(i + 1);
+                stack.push(node.offset);
+            }
+            self.kill_node(i);
+        }
+    }
+
+    fn remove_slot(&mut self, slot: usize) {
+        let leaf_i = self.update.slot_leaf[slot];
+        let leaf = self.nodes[leaf_i].clone();
+        self.detach_slot(slot);
+
+        if leaf.nprimitives > 1 {
+            // last slot of leaf fills the gap
+            let last = leaf.offset + leaf.nprimitives - 1;
+            if slot != last {
+                self.detach_slot(last);
+                self.prim_refs[slot] = self.prim_refs[last];
+                self.attach_slot(slot, leaf_i);
+            }
+            let nprimitives = leaf.nprimitives - 1;
+            self.set_node(
+                leaf_i,
+                LinearBVHNode {
+                    nprimitives,
+                    ..leaf
+                },
+            );
+            self.refit_path(leaf_i);
+            return;
+        }
+
+        // last primitive of root leaf, tree is empty
+        if leaf_i == 0 {
+            self.nodes.clear();
+            self.prim_refs.clear();
+            self.update = UpdateState {
+                active: true,
+                prim_slot: vec![NO_PARENT; self.primitives.len()],
+                ..Default::default()
+            };
+            return;
+        }
+
+        // sibling takes place of parent
+        let parent_i = self.update.parents[leaf_i];
+        let sibling_i = if leaf_i == parent_i + 1 {
+            self.nodes[parent_i].offset
+        } else {
+            parent_i + 1
+        };
+        let grand_i = self.update.parents[parent_i];
+        self.kill_node(leaf_i);
+        self.kill_node(parent_i);
+
+        if grand_i != NO_PARENT && self.nodes[grand_i].offset == parent_i {
+            self.nodes[grand_i].offset = sibling_i;
+            self.update.parents[sibling_i] = grand_i;
+        } else {
+            // parent is a first child or root, sibling spine moves into its place
+            let mut sibling_i = sibling_i;
+            for j in 1..self.spine_len(sibling_i) {
+                let moved = self.free_position(parent_i + j);
+                if parent_i + j == sibling_i {
+                    sibling_i = moved.unwrap();
+                }
+            }
+            self.move_spine(sibling_i, parent_i, grand_i);
+        }
+        self.refit_path(grand_i);
+    }
+}
+
+#[test]
+fn test_bvh_update() {
+    use crate::core::tensor::Vec3f;
+    use crate::raycast::{Ray, Raycast, sphere::Sphere, widebvh::BVHWidth};
+    use rand::Rng;
+
+    // every primitive referenced once, every reachable node fits its children.
+    // kept cost matches a walk and holes stay bounded
+    fn check_tree(bvh: &BVH<Sphere>) {
+        let mut referenced = vec![0; bvh.primitives.len()];
+        let mut reachable = 0;
+        let mut stack = if bvh.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![0]
+        };
+        while let Some(i) = stack.pop() {
+            reachable += 1;
+            let node = &bvh.nodes[i];
+            assert_eq!(node.bounds, bvh.fit_node(i));
+            if node.is_leaf() {
+                assert!(node.nprimitives < bvh.node_prims_limit);
+                (node.offset..node.offset + node.nprimitives)
+                    .for_each(|s| referenced[bvh.prim_index(s)] += 1);
+            } else {
+                stack.push(node.offset);
+                stack.push(i + 1);
+            }
+        }
+        assert!(referenced.iter().all(|&c| c == 1));
+        assert!(bvh.nodes.len() <= 2 * reachable + GARBAGE_SLACK);
+
+        // reused and collapsed again wide nodes reach every slot once
+        fn wide_slots<const W: usize>(wide: &[WideBVHNode<W>], slots: &mut Vec<usize>) {
+            let mut stack = vec![0];
+            while let Some(w) = stack.pop() {
+                let node = &wide[w];
+                for k in 0..node.nchildren {
+                    if node.nprimitives[k] > 0 {
+                        slots.extend(node.offset[k]..node.offset[k] + node.nprimitives[k]);
+                    } else {
+                        stack.push(node.offset[k]);
+                    }
+                }
+            }
+        }
+        let mut slots = Vec::new();
+        match &bvh.wide {
+            WideNodes::None => {}
+            WideNodes::Four(wide) => wide_slots(wide, &mut slots),
+            WideNodes::Eight(wide) => wide_slots(wide, &mut slots),
+        }
+        if !slots.is_empty() {
+            let mut prims: Vec<usize> = slots.iter().map(|&s| bvh.prim_index(s)).collect();
+            prims.sort_unstable();
+            assert_eq!(prims, (0..bvh.primitives.len()).collect::<Vec<_>>());
+        }
+        let (kept, walked) = (bvh.current_cost(), bvh.sah_cost());
+        assert!((kept - walked).abs() <= 1e-3 * walked, "{kept} {walked}");
+    }
+
+    fn check_raycast(bvh: &BVH<Sphere>) {
+        let mut rng = rand::rng();
+        for _ in 0..128 {
+            let org = Vec3f::vec([-8., rng.random_range(0.0..32.), rng.random_range(0.0..32.)]);
+            let target = Vec3f::vec([40., rng.random_range(0.0..32.), rng.random_range(0.0..32.)]);
+            let ray = Ray::new(org, target - org);
+            let expected = bvh
+                .primitives
+                .iter()
+                .filter_map(|p| p.raycast(&ray).map(|h| h.t))
+                .reduce(f32::min);
+            assert_eq!(bvh.raycast(&ray).map(|h| h.t), expected);
+        }
+    }
+
+    let mut rng = rand::rng();
+    let mut sphere = || {
+        let cnt = Vec3f::vec([
+            rng.random_range(0.0..32.),
+            rng.random_range(0.0..32.),
+            rng.random_range(0.0..32.),
+        ]);
+        Sphere::new(cnt, rng.random_range(0.1..0.5))
+    };
+
+    // refit animated primitives
+    let mut bvh = BVH::new(1024);
+    (0..1024).for_each(|_| bvh.push(sphere()));
+    bvh.build_with(&SAHBuilder { par_build: false }, 9);
+    assert_eq!(bvh.degradation(), 1.);
+    for frame in 0..4 {
+        bvh.primitives.iter_mut().for_each(|s| {
+            let v = Vec3f::vec([
+                rand::rng().random_range(-1.0..1.),
+                rand::rng().random_range(-1.0..1.),
+                rand::rng().random_range(-1.0..1.),
+            ]);
+            s.cnt = s.cnt + v;
+        });
+        bvh.refit();
+        check_raycast(&bvh);
+        println!("refit frame {}, degradation {}", frame, bvh.degradation());
+    }
+    assert!(bvh.degradation() > 1.);
+
+    // empty tree grows from a single leaf
+    let mut bvh = BVH::new(512);
+    bvh.node_prims_limit = 9;
+    for i in 0..256 {
+        assert_eq!(bvh.insert(sphere()), i);
+    }
+    check_tree(&bvh);
+    check_raycast(&bvh);
+
+    bvh.build_with(&SAHBuilder { par_build: false }, 9);
+    bvh.collapse(BVHWidth::Four);
+    for _ in 0..256 {
+        bvh.insert(sphere());
+    }
+    check_tree(&bvh);
+    check_raycast(&bvh);
+    println!("inserted 256, degradation {}", bvh.degradation());
+
+    for _ in 0..384 {
+        let i = rand::rng().random_range(0..bvh.primitives.len());
+        let last = bvh.primitives[bvh.primitives.len() - 1].cnt;
+        bvh.remove(i);
+        if i < bvh.primitives.len() {
+            assert_eq!(bvh.primitives[i].cnt, last);
+        }
+    }
+    assert_eq!(bvh.wide.width(), BVHWidth::Four);
+    check_tree(&bvh);
+    check_raycast(&bvh);
+    println!("removed 384, degradation {}", bvh.degradation());
+
+    bvh.rebuild_subtree(0);
+    check_tree(&bvh);
+    check_raycast(&bvh);
+    assert_eq!(bvh.degradation(), 1.);
+
+    // cluster far from tree stretches root and nodes on its way, rebuild restores
+    let mut far = BVH::new(1024);
+    (0..1024).for_each(|_| far.push(sphere()));
+    far.build_with(&SAHBuilder { par_build: false }, 9);
+    for _ in 0..64 {
+        let s = sphere();
+        far.insert(Sphere::new(s.cnt * (1. / 32.) + Vec3f::vec([256.; 3]), s.r));
+    }
+    check_tree(&far);
+    check_raycast(&far);
+    println!("inserted far cluster, degradation {}", far.degradation());
+    assert!(far.degradation() > 1.);
+    far.rebuild_subtree(0);
+    assert_eq!(far.degradation(), 1.);
+
+    // mixed updates move spines, fill holes and compact
+    bvh.collapse(BVHWidth::Eight);
+    for round in 0..8 {
+        for _ in 0..256 {
+            if rand::rng().random_bool(0.6) {
+                bvh.insert(sphere());
+            } else {
+                bvh.remove(rand::rng().random_range(0..bvh.primitives.len()));
+            }
+        }
+        check_tree(&bvh);
+        check_raycast(&bvh);
+        println!("round {round}, degradation {}", bvh.degradation());
+    }
+    assert_eq!(bvh.wide.width(), BVHWidth::Eight);
+    bvh.refit();
+    check_tree(&bvh);
+    check_raycast(&bvh);
+
+    while !bvh.primitives.is_empty() {
+        bvh.remove(0);
+    }
+    assert!(bvh.nodes.is_empty());
+}
@@ -0,0 +1,158 @@
+use std::fmt::Debug;
+
+use crate::{
+    core::{
+        math::quadratic, quaternion::Quat, spherical::azimuth_u, tensor::Vec3f,
+        transform::Transform, vec::Vector,
+    },
+    raycast::{bounds::Bounds3f, primitive::Primitive, *},
+};
+
+/// points within r of local y axis segment from -half_h to half_h, placed by pos and rot
+#[derive(Clone)]
+pub struct Capsule {
+    pub r: f32,
+    pub half_h: f32,
+    transform: Transform,
+}
+
+impl Capsule {
+    pub fn new(pos: Vec3f, rot: Quat, r: f32, half_h: f32) -> Capsule {
+        Capsule {
+            r,
+            half_h,
+            transform: Transform::rigid(pos, rot),
+        }
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    /// nearest point on axis segment, object space
+    fn axis_point(&self, lp: Vec3f) -> Vec3f {
+        Vec3f::vec([0., lp[1].clamp(-self.half_h, self.half_h), 0.])
+    }
+}
+
+impl Raycast for Capsule {
+    fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        let Ray { org: o, dir: d, .. } = ray.to_object(&self.transform);
+        let (r, h) = (self.r, self.half_h);
+        let mut nearest: Option<f32> = None;
+        let mut accept = |t: f32| {
+            if t >= 0. && t <= ray.t_max && nearest.is_none_or(|n| t < n) {
+                nearest = Some(t);
+            }
+        };
+
+        // side, x^2 + z^2 = r^2 between segment ends
+        if let Some((t0, t1)) = quadratic(
+            d[0] * d[0] + d[2] * d[2],
+            2. * (o[0] * d[0] + o[2] * d[2]),
+            o[0] * o[0] + o[2] * o[2] - r * r,
+        ) {
+            for t in [t0, t1] {
+                if (o[1] + d[1] * t).abs() <= h {
+                    accept(t);
+                }
+            }
+        }
+
+        // hemispheres at segment ends, halves inside side are skipped
+        for end in [-h, h] {
+            let oc = o - Vec3f::vec([0., end, 0.]);
+            if let Some((t0, t1)) = quadratic(d.dot(d), 2. * oc.dot(d), oc.dot(oc) - r * r) {
+                for t in [t0, t1] {
+                    if (oc[1] + d[1] * t) * end >= 0. {
+                        accept(t);
+                    }
+                }
+            }
+        }
+
+        nearest.map(Hit::new)
+    }
+}
+
+impl Debug for Capsule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.transform.pos, self.r, self.half_h)
+    }
+}
+
+impl Primitive for Capsule {
+    fn bounds(&self) -> Bounds3f {
+        let e = Vec3f::vec([self.r, self.half_h + self.r, self.r]);
+        Bounds3f::new(e * -1., e).transform(&self.transform)
+    }
+
+    fn distance(&self, p: Vec3f) -> f32 {
+        let lp = self.transform.inv_point(p);
+        ((lp - self.axis_point(lp)).norm() - self.r).max(0.)
+    }
+
+    /// uv is azimuth and height along whole capsule in [0,1]
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Option<Surface> {
+        let p = hit.position(ray);
+        let lp = self.transform.inv_point(p);
+        let n = (lp - self.axis_point(lp)).normalize();
+        let v = (lp[1] + self.half_h + self.r) / (2. * (self.half_h + self.r));
+        Some(Surface {
+            p,
+            n: self.transform.normal(n).normalize(),
+            uv: [azimuth_u(lp), v.clamp(0., 1.)],
+            bary: None,
+        })
+    }
+}
+
+#[test]
+fn test_capsule() {
+    let cap = Capsule::new(Vec3f::vec([0.; 3]), Quat::identity(), 1., 1.);
+
+    // tangent rays at top and bottom left out
+    for i in 1..10 {
+        let y = i as f32 / 10. * 4. - 2.;
+        let ray = Ray::new(Vec3f::vec([0., y, 4.]), Vec3f::vec([0., 0., -1.]));
+        let hit = cap.raycast(&ray).unwrap();
+        let s = cap.surface(&ray, &hit).unwrap();
+
+        // side, or sphere around nearer segment end
+        let end = y.clamp(-1., 1.);
+        let z = (1. - (y - end) * (y - end)).max(0.).sqrt();
+        assert!((hit.t - (4. - z)).abs() < 1e-3, "{y}");
+        let n = Vec3f::vec([0., y - end, z]);
+        assert!((s.n - n).norm() < 1e-3);
+        assert!((s.uv[1] - i as f32 / 10.).abs() < 1e-5);
+    }
+
+    // along axis hits cap top, inside leaves through bottom
+    let down = Vec3f::vec([0., -1., 0.]);
+    let ray = Ray::new(Vec3f::vec([0., 5., 0.]), down);
+    assert_eq!(cap.raycast(&ray).map(|h| h.t), Some(3.));
+    let ray = Ray::new(Vec3f::vec([0., 1.5, 0.]), down);
+    let hit = cap.raycast(&ray).unwrap();
+    assert_eq!(hit.t, 3.5);
+    assert_eq!(cap.surface(&ray, &hit).unwrap().n, down);
+    // ray through lower half of top sphere hits side, not inner hemisphere
+    let ray = Ray::new(Vec3f::vec([0., 0.5, 0.]), Vec3f::vec([1., 0., 0.]));
+    let hit = cap.raycast(&ray).unwrap();
+    assert!((hit.position(&ray)[0] - 1.).abs() < 1e-5);
+    assert!(
+        cap.raycast(&Ray::segment(Vec3f::vec([0., 5., 0.]), down, 2.))
+            .is_none()
+    );
+    assert!(
+        cap.raycast(&Ray::new(Vec3f::vec([1.5, 5., 0.]), down))
+            .is_none()
+    );
+
+    let rot = Quat::angle_axis(90., Vec3f::vec([1., 0., 0.]));
+    let cap = Capsule::new(Vec3f::vec([0.; 3]), rot, 0.5, 2.);
+    let b = cap.bounds();
+    assert!((b.max[2] - 2.5).abs() < 1e-5 && (b.max[1] - 0.5).abs() < 1e-5);
+    assert!((cap.distance(Vec3f::vec([0., 0., 4.])) - 1.5).abs() < 1e-5);
+    assert!((cap.distance(Vec3f::vec([3., 0., 1.])) - 2.5).abs() < 1e-5);
+    assert_eq!(cap.distance(Vec3f::vec([0., 0.2, -2.])), 0.);
+}
@@ -0,0 +1,194 @@
+use std::fmt::Debug;
+
+use crate::{
+    core::{
+        math::quadratic, quaternion::Quat, spherical::azimuth_u, tensor::Vec3f,
+        transform::Transform, vec::Vector,
+    },
+    raycast::{bounds::Bounds3f, primitive::Primitive, *},
+};
+
+/// closed cone with base of radius r at local y = 0 and apex at y = h, placed by pos and rot
+#[derive(Clone)]
+pub struct Cone {
+    pub r: f32,
+    pub h: f32,
+    transform: Transform,
+}
+
+impl Cone {
+    pub fn new(pos: Vec3f, rot: Quat, r: f32, h: f32) -> Cone {
+        Cone {
+            r,
+            h,
+            transform: Transform::rigid(pos, rot),
+        }
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+}
+
+/// distance between 2d point p and segment ab
+fn segment_distance(p: [f32; 2], a: [f32; 2], b: [f32; 2]) -> f32 {
+    let ab = [b[0] - a[0], b[1] - a[1]];
+    let ap = [p[0] - a[0], p[1] - a[1]];
+    let s = ((ap[0] * ab[0] + ap[1] * ab[1]) / (ab[0] * ab[0] + ab[1] * ab[1])).clamp(0., 1.);
+    let d = [ap[0] - ab[0] * s, ap[1] - ab[1] * s];
+    (d[0] * d[0] + d[1] * d[1]).sqrt()
+}
+
+impl Raycast for Cone {
+    fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        let Ray { org: o, dir: d, .. } = ray.to_object(&self.transform);
+        let (r, h) = (self.r, self.h);
+        let mut nearest: Option<f32> = None;
+        let mut accept = |t: f32| {
+            if t >= 0. && t <= ray.t_max && nearest.is_none_or(|n| t < n) {
+                nearest = Some(t);
+            }
+        };
+
+        // side, x^2 + z^2 = (k (h - y))^2 between base and apex
+        let k2 = (r / h) * (r / h);
+        let w = h - o[1];
+        if let Some((t0, t1)) = quadratic(
+            d[0] * d[0] + d[2] * d[2] - k2 * d[1] * d[1],
+            2. * (o[0] * d[0] + o[2] * d[2] + k2 * w * d[1]),
+            o[0] * o[0] + o[2] * o[2] - k2 * w * w,
+        ) {
+            for t in [t0, t1] {
+                // other nappe of double cone lies above apex
+                let y = o[1] + d[1] * t;
+                if (0. ..=h).contains(&y) {
+                    accept(t);
+                }
+            }
+        }
+
+        // base, y = 0 within radius
+        if d[1] != 0. {
+            let t = -o[1] / d[1];
+            let (x, z) = (o[0] + d[0] * t, o[2] + d[2] * t);
+            if x * x + z * z <= r * r {
+                accept(t);
+            }
+        }
+
+        nearest.map(Hit::new)
+    }
+}
+
+impl Debug for Cone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.transform.pos, self.r, self.h)
+    }
+}
+
+impl Primitive for Cone {
+    fn bounds(&self) -> Bounds3f {
+        Bounds3f::new(
+            Vec3f::vec([-self.r, 0., -self.r]),
+            Vec3f::vec([self.r, self.h, self.r]),
+        )
+        .transform(&self.transform)
+    }
+
+    /// distance in plane of axis to triangle of base, apex and base rim
+    fn distance(&self, p: Vec3f) -> f32 {
+        let lp = self.transform.inv_point(p);
+        let (rho, y) = ((lp[0] * lp[0] + lp[2] * lp[2]).sqrt(), lp[1]);
+        if y >= 0. && rho <= self.r * (1. - y / self.h) {
+            return 0.;
+        }
+        let base = segment_distance([rho, y], [0., 0.], [self.r, 0.]);
+        let side = segment_distance([rho, y], [self.r, 0.], [0., self.h]);
+        base.min(side)
+    }
+
+    /// uv of side is azimuth and height in [0,1], of base azimuth and radius relative to r
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Option<Surface> {
+        let p = hit.position(ray);
+        let lp = self.transform.inv_point(p);
+        let rho = (lp[0] * lp[0] + lp[2] * lp[2]).sqrt();
+        let u = azimuth_u(lp);
+
+        // part nearest to hit point
+        let slant = (self.r * self.r + self.h * self.h).sqrt();
+        let side_dist = ((rho - self.r) * self.h + lp[1] * self.r).abs() / slant;
+        let (n, uv) = if lp[1].abs() < side_dist {
+            (Vec3f::vec([0., -1., 0.]), [u, rho / self.r])
+        } else if rho > 0. {
+            let (cx, cz) = (lp[0] / rho, lp[2] / rho);
+            let n = Vec3f::vec([cx * self.h, self.r, cz * self.h]) * (1. / slant);
+            (n, [u, lp[1] / self.h])
+        } else {
+            // apex
+            (Vec3f::vec([0., 1., 0.]), [u, 1.])
+        };
+        Some(Surface {
+            p,
+            n: self.transform.normal(n).normalize(),
+            uv,
+            bary: None,
+        })
+    }
+}
+
+#[test]
+fn test_cone() {
+    // 45 degree side
+    let cone = Cone::new(Vec3f::vec([0.; 3]), Quat::identity(), 1., 1.);
+
+    for i in 0..10 {
+        let y = i as f32 / 10.;
+        let ray = Ray::new(Vec3f::vec([0., y, 4.]), Vec3f::vec([0., 0., -1.]));
+        let hit = cone.raycast(&ray).unwrap();
+        assert!((hit.t - (3. + y)).abs() < 1e-5);
+
+        let s = cone.surface(&ray, &hit).unwrap();
+        assert!((s.p[1] - y).abs() < 1e-6);
+        if y > 0.1 {
+            let n = Vec3f::vec([0., 1., 1.]).normalize();
+            assert!((s.n - n).norm() < 1e-5);
+        }
+        assert!((s.uv[1] - y).abs() < 1e-5);
+    }
+
+    // base from below, side from above, above apex misses other nappe
+    let up = Vec3f::vec([0., 1., 0.]);
+    let ray = Ray::new(Vec3f::vec([0.5, -2., 0.]), up);
+    let hit = cone.raycast(&ray).unwrap();
+    assert_eq!(hit.t, 2.);
+    let s = cone.surface(&ray, &hit).unwrap();
+    assert_eq!(s.n, up * -1.);
+    assert_eq!(s.uv, [0.25, 0.5]);
+
+    let ray = Ray::new(Vec3f::vec([0.5, 3., 0.]), up * -1.);
+    assert_eq!(cone.raycast(&ray).map(|h| h.t), Some(2.5));
+    let ray = Ray::new(Vec3f::vec([-3., 2., 0.]), Vec3f::vec([1., 0., 0.]));
+    assert!(cone.raycast(&ray).is_none());
+    let ray = Ray::new(Vec3f::vec([1.1, -1., 0.]), up);
+    assert!(cone.raycast(&ray).is_none());
+
+    // inside hits side on the way out
+    let ray = Ray::new(Vec3f::vec([0., 0.5, 0.]), Vec3f::vec([1., 0., 0.]));
+    assert!((cone.raycast(&ray).unwrap().t - 0.5).abs() < 1e-5);
+
+    // upside down, apex at origin
+    let rot = Quat::angle_axis(180., Vec3f::vec([1., 0., 0.]));
+    let cone = Cone::new(Vec3f::vec([0., 2., 0.]), rot, 1., 2.);
+    let b = cone.bounds();
+    assert!(b.min[1].abs() < 1e-5 && (b.max[1] - 2.).abs() < 1e-5);
+    let ray = Ray::new(Vec3f::vec([0., 3., 0.]), up * -1.);
+    let hit = cone.raycast(&ray).unwrap();
+    assert!((hit.t - 1.).abs() < 1e-5);
+    assert!((cone.surface(&ray, &hit).unwrap().n - up).norm() < 1e-5);
+
+    assert!((cone.distance(Vec3f::vec([0., -1., 0.])) - 1.).abs() < 1e-5);
+    assert!((cone.distance(Vec3f::vec([0., 3., 0.])) - 1.).abs() < 1e-5);
+    assert_eq!(cone.distance(Vec3f::vec([0., 1., 0.])), 0.);
+    // nearest to slanted side
+    assert!((cone.distance(Vec3f::vec([2., 1., 0.])) - 1.8f32.sqrt()).abs() < 1e-5);
+}
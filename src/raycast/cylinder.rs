@@ -0,0 +1,172 @@
+use std::fmt::Debug;
+
+use crate::{
+    core::{
+        math::quadratic, quaternion::Quat, spherical::azimuth_u, tensor::Vec3f,
+        transform::Transform, vec::Vector,
+    },
+    raycast::{bounds::Bounds3f, primitive::Primitive, *},
+};
+
+/// closed cylinder around local y axis, from -half_h to half_h, placed by pos and rot
+#[derive(Clone)]
+pub struct Cylinder {
+    pub r: f32,
+    pub half_h: f32,
+    transform: Transform,
+}
+
+impl Cylinder {
+    pub fn new(pos: Vec3f, rot: Quat, r: f32, half_h: f32) -> Cylinder {
+        Cylinder {
+            r,
+            half_h,
+            transform: Transform::rigid(pos, rot),
+        }
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+}
+
+impl Raycast for Cylinder {
+    fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        let Ray { org: o, dir: d, .. } = ray.to_object(&self.transform);
+        let (r, h) = (self.r, self.half_h);
+        let mut nearest: Option<f32> = None;
+        let mut accept = |t: f32| {
+            if t >= 0. && t <= ray.t_max && nearest.is_none_or(|n| t < n) {
+                nearest = Some(t);
+            }
+        };
+
+        // side, x^2 + z^2 = r^2 within caps
+        if let Some((t0, t1)) = quadratic(
+            d[0] * d[0] + d[2] * d[2],
+            2. * (o[0] * d[0] + o[2] * d[2]),
+            o[0] * o[0] + o[2] * o[2] - r * r,
+        ) {
+            for t in [t0, t1] {
+                if (o[1] + d[1] * t).abs() <= h {
+                    accept(t);
+                }
+            }
+        }
+
+        // caps, y = -h and y = h within radius
+        if d[1] != 0. {
+            for y in [-h, h] {
+                let t = (y - o[1]) / d[1];
+                let (x, z) = (o[0] + d[0] * t, o[2] + d[2] * t);
+                if x * x + z * z <= r * r {
+                    accept(t);
+                }
+            }
+        }
+
+        nearest.map(Hit::new)
+    }
+}
+
+impl Debug for Cylinder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.transform.pos, self.r, self.half_h)
+    }
+}
+
+impl Primitive for Cylinder {
+    fn bounds(&self) -> Bounds3f {
+        let e = Vec3f::vec([self.r, self.half_h, self.r]);
+        Bounds3f::new(e * -1., e).transform(&self.transform)
+    }
+
+    fn distance(&self, p: Vec3f) -> f32 {
+        let lp = self.transform.inv_point(p);
+        let rho = (lp[0] * lp[0] + lp[2] * lp[2]).sqrt();
+        let dr = (rho - self.r).max(0.);
+        let dy = (lp[1].abs() - self.half_h).max(0.);
+        (dr * dr + dy * dy).sqrt()
+    }
+
+    /// uv of side is azimuth and height in [0,1], of caps azimuth and radius relative to r
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Option<Surface> {
+        let p = hit.position(ray);
+        let lp = self.transform.inv_point(p);
+        let rho = (lp[0] * lp[0] + lp[2] * lp[2]).sqrt();
+        let u = azimuth_u(lp);
+
+        // part nearest to hit point
+        let (n, uv) = if (lp[1].abs() - self.half_h).abs() < (rho - self.r).abs() {
+            let n = Vec3f::vec([0., 1f32.copysign(lp[1]), 0.]);
+            (n, [u, rho / self.r])
+        } else {
+            let n = Vec3f::vec([lp[0], 0., lp[2]]) * (1. / rho);
+            (n, [u, (lp[1] / self.half_h + 1.) * 0.5])
+        };
+        Some(Surface {
+            p,
+            n: self.transform.normal(n).normalize(),
+            uv,
+            bary: None,
+        })
+    }
+}
+
+#[test]
+fn test_cylinder() {
+    let cyl = Cylinder::new(Vec3f::vec([0.; 3]), Quat::identity(), 1., 2.);
+
+    for i in 0..11 {
+        let y = i as f32 / 10. * 4. - 2.;
+        let ray = Ray::new(Vec3f::vec([0., y, 4.]), Vec3f::vec([0., 0., -1.]));
+        let hit = cyl.raycast(&ray).unwrap();
+        assert_eq!(hit.t, 3.);
+
+        let s = cyl.surface(&ray, &hit).unwrap();
+        assert_eq!(s.p[1], y);
+        if y.abs() < 1.5 {
+            assert_eq!(s.n, Vec3f::vec([0., 0., 1.]));
+        }
+        assert!((s.uv[1] - i as f32 / 10.).abs() < 1e-6);
+    }
+
+    // cap from above, inside hits side, misses
+    let down = Vec3f::vec([0., -1., 0.]);
+    let ray = Ray::new(Vec3f::vec([0.5, 5., 0.]), down);
+    let hit = cyl.raycast(&ray).unwrap();
+    assert_eq!(hit.t, 3.);
+    let s = cyl.surface(&ray, &hit).unwrap();
+    assert_eq!(s.n, Vec3f::vec([0., 1., 0.]));
+    assert_eq!(s.uv, [0.25, 0.5]);
+
+    let ray = Ray::new(Vec3f::vec([0.; 3]), Vec3f::vec([1., 0., 0.]));
+    assert_eq!(cyl.raycast(&ray).map(|h| h.t), Some(1.));
+    assert!(
+        cyl.raycast(&Ray::new(Vec3f::vec([1.1, 5., 0.]), down))
+            .is_none()
+    );
+    assert!(
+        cyl.raycast(&Ray::segment(Vec3f::vec([0.5, 5., 0.]), down, 2.9))
+            .is_none()
+    );
+
+    // lying along x
+    let rot = Quat::angle_axis(90., Vec3f::vec([0., 0., 1.]));
+    let cyl = Cylinder::new(Vec3f::vec([0., 0., -3.]), rot, 1., 2.);
+    let b = cyl.bounds();
+    assert!((b.max[0] - 2.).abs() < 1e-5 && (b.max[1] - 1.).abs() < 1e-5);
+    let ray = Ray::new(Vec3f::vec([1.5, 0., 0.]), Vec3f::vec([0., 0., -1.]));
+    let hit = cyl.raycast(&ray).unwrap();
+    assert!((hit.t - 2.).abs() < 1e-5);
+    let s = cyl.surface(&ray, &hit).unwrap();
+    assert!((s.n - Vec3f::vec([0., 0., 1.])).norm() < 1e-5);
+    assert!(
+        cyl.raycast(&Ray::new(Vec3f::vec([2.5, 0., 0.]), ray.dir))
+            .is_none()
+    );
+
+    assert!((cyl.distance(Vec3f::vec([0., 0., 0.])) - 2.).abs() < 1e-5);
+    assert!((cyl.distance(Vec3f::vec([5., 0., -3.])) - 3.).abs() < 1e-5);
+    assert_eq!(cyl.distance(Vec3f::vec([1., 0.5, -3.])), 0.);
+}
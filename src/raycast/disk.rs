@@ -0,0 +1,135 @@
+use std::{f32::consts::PI, fmt::Debug};
+
+use crate::{
+    core::{math::coordinate_system, tensor::Vec3f, vec::Vector},
+    raycast::{bounds::Bounds3f, primitive::Primitive, *},
+};
+
+/// flat disk of radius r around cnt, facing unit normal n
+#[derive(Clone)]
+pub struct Disk {
+    pub cnt: Vec3f,
+    pub n: Vec3f,
+    pub r: f32,
+    /// unit tangents, azimuth is measured from u towards v
+    pub u: Vec3f,
+    pub v: Vec3f,
+}
+
+impl Disk {
+    pub fn new(cnt: Vec3f, n: Vec3f, r: f32) -> Disk {
+        let n = n.normalize();
+        let (u, v) = coordinate_system(n);
+        Disk { cnt, n, r, u, v }
+    }
+}
+
+impl Raycast for Disk {
+    fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        let denom = self.n.dot(ray.dir);
+        if denom == 0. {
+            return None;
+        }
+        let t = self.n.dot(self.cnt - ray.org) / denom;
+        if !(t >= 0. && t <= ray.t_max) {
+            return None;
+        }
+
+        let d = ray.org + ray.dir * t - self.cnt;
+        if d.dot(d) > self.r * self.r {
+            return None;
+        }
+        Some(Hit::new(t))
+    }
+}
+
+impl Debug for Disk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.cnt, self.n, self.r)
+    }
+}
+
+impl Primitive for Disk {
+    /// exact, circle spans r * sin of angle between n and axis
+    fn bounds(&self) -> Bounds3f {
+        let e = Vec3f::vec(std::array::from_fn(|i| {
+            self.r * (1. - self.n[i] * self.n[i]).max(0.).sqrt()
+        }));
+        Bounds3f::new(self.cnt - e, self.cnt + e)
+    }
+
+    fn distance(&self, p: Vec3f) -> f32 {
+        let d = p - self.cnt;
+        // offset in disk plane, clamped to rim
+        let w = d - self.n * self.n.dot(d);
+        let len = w.norm();
+        let w = if len > self.r { w * (self.r / len) } else { w };
+        (d - w).norm()
+    }
+
+    /// uv is azimuth in [0,1) and radius relative to r
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Option<Surface> {
+        let p = hit.position(ray);
+        let d = p - self.cnt;
+        let phi = d.dot(self.v).atan2(d.dot(self.u));
+        Some(Surface {
+            p,
+            n: self.n,
+            uv: [(phi / (2. * PI)).rem_euclid(1.), d.norm() / self.r],
+            bary: None,
+        })
+    }
+}
+
+#[test]
+fn test_disk() {
+    let z = Vec3f::vec([0., 0., 1.]);
+    let disk = Disk::new(Vec3f::vec([0., 0., -2.]), z, 1.);
+
+    for i in 0..11 {
+        let x = i as f32 / 10.;
+        let ray = Ray::new(Vec3f::vec([x, 0., 0.]), z * -1.);
+        let hit = disk.raycast(&ray).unwrap();
+        assert_eq!(hit.t, 2.);
+
+        let s = disk.surface(&ray, &hit).unwrap();
+        assert_eq!(s.p[2], -2.);
+        assert_eq!(s.n, z);
+        assert!((s.uv[1] - x).abs() < 1e-6);
+    }
+
+    // azimuth from u towards v, tangents of +z are x and y
+    let ray = |x: f32, y: f32| Ray::new(Vec3f::vec([x, y, 0.]), z * -1.);
+    let u = |r: &Ray| disk.surface(r, &disk.raycast(r).unwrap()).unwrap().uv[0];
+    assert_eq!(
+        (disk.u, disk.v),
+        (Vec3f::vec([1., 0., 0.]), Vec3f::vec([0., 1., 0.]))
+    );
+    assert_eq!(u(&ray(0.5, 0.)), 0.);
+    assert_eq!(u(&ray(0., 0.5)), 0.25);
+    assert_eq!(u(&ray(0., -0.5)), 0.75);
+
+    assert!(disk.raycast(&ray(0.8, 0.8)).is_none());
+    assert!(
+        disk.raycast(&Ray::segment(Vec3f::vec([0.; 3]), z * -1., 1.))
+            .is_none()
+    );
+    assert!(disk.raycast(&Ray::new(Vec3f::vec([0.; 3]), z)).is_none());
+
+    assert_eq!(
+        disk.bounds(),
+        Bounds3f::new(Vec3f::vec([-1., -1., -2.]), Vec3f::vec([1., 1., -2.]))
+    );
+    // tilted disk bounds still enclose rim
+    let tilted = Disk::new(Vec3f::vec([0.; 3]), Vec3f::vec([1., 1., 0.]), 2.);
+    let b = tilted.bounds();
+    for k in 0..64 {
+        let a = k as f32 / 64. * 2. * PI;
+        let p = tilted.cnt + (tilted.u * a.cos() + tilted.v * a.sin()) * 2.;
+        assert!(Bounds3f::new(b.min - 1e-5, b.max + 1e-5).contains(p));
+    }
+    assert!((b.max[2] - 2.).abs() < 1e-6);
+
+    assert_eq!(disk.distance(Vec3f::vec([0.5, 0., 1.])), 3.);
+    assert_eq!(disk.distance(Vec3f::vec([4., 0., -2.])), 3.);
+}
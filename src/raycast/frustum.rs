@@ -0,0 +1,104 @@
+use crate::{
+    core::{tensor::Vec3f, vec::Vector},
+    raycast::bounds::Bounds3f,
+};
+
+/// points p with n.p + d >= 0 are inside
+#[derive(Debug, Clone, Copy)]
+pub struct Plane {
+    pub n: Vec3f,
+    pub d: f32,
+}
+
+impl Plane {
+    /// plane through p, n points inside
+    pub fn new(n: Vec3f, p: Vec3f) -> Self {
+        let n = n.normalize();
+        Plane { n, d: -n.dot(p) }
+    }
+
+    /// signed distance, positive inside
+    pub fn distance(&self, p: Vec3f) -> f32 {
+        self.n.dot(p) + self.d
+    }
+}
+
+/// convex volume of six planes, near, far, left, right, bottom, top
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// perspective frustum from apex along orthonormal basis,
+    /// half extents of image plane at distance 1 along forward
+    pub fn perspective(
+        apex: Vec3f,
+        (forward, up, right): (Vec3f, Vec3f, Vec3f),
+        (half_w, half_h): (f32, f32),
+        (near, far): (f32, f32),
+    ) -> Self {
+        let corner = |x: f32, y: f32| forward + right * (x * half_w) + up * (y * half_h);
+        // side plane through apex and two corner directions, oriented towards forward
+        let side = |a: Vec3f, b: Vec3f| {
+            let n = a.cross(b);
+            let n = if n.dot(forward) < 0. { n * -1. } else { n };
+            Plane::new(n, apex)
+        };
+
+        Frustum {
+            planes: [
+                Plane::new(forward, apex + forward * near),
+                Plane::new(forward * -1., apex + forward * far),
+                side(corner(-1., -1.), corner(-1., 1.)),
+                side(corner(1., -1.), corner(1., 1.)),
+                side(corner(-1., -1.), corner(1., -1.)),
+                side(corner(-1., 1.), corner(1., 1.)),
+            ],
+        }
+    }
+
+    pub fn contains(&self, p: Vec3f) -> bool {
+        self.planes.iter().all(|plane| plane.distance(p) >= 0.)
+    }
+
+    /// conservative, false only if bounds are entirely outside one plane.
+    /// bounds near frustum edges may pass though outside
+    pub fn intersects_bounds(&self, b: &Bounds3f) -> bool {
+        self.planes.iter().all(|plane| {
+            // corner farthest along plane normal
+            let p = Vec3f::vec(std::array::from_fn(|i| {
+                if plane.n[i] >= 0. { b.max[i] } else { b.min[i] }
+            }));
+            plane.distance(p) >= 0.
+        })
+    }
+}
+
+#[test]
+fn test_frustum() {
+    let x = Vec3f::vec([1., 0., 0.]);
+    let y = Vec3f::vec([0., 1., 0.]);
+    let z = Vec3f::vec([0., 0., -1.]);
+    // 90 degree vertical and horizontal field of view, looking down -z
+    let f = Frustum::perspective(Vec3f::vec([0.; 3]), (z, y, x), (1., 1.), (0.5, 10.));
+
+    assert!(f.contains(Vec3f::vec([0., 0., -1.])));
+    assert!(f.contains(Vec3f::vec([1.9, -1.9, -2.])));
+    assert!(!f.contains(Vec3f::vec([2.1, 0., -2.])));
+    assert!(!f.contains(Vec3f::vec([0., 0., -0.4])));
+    assert!(!f.contains(Vec3f::vec([0., 0., -10.5])));
+    assert!(!f.contains(Vec3f::vec([0., 0., 1.])));
+
+    let cube = |c: [f32; 3], r: f32| {
+        let c = Vec3f::vec(c);
+        Bounds3f::new(c - r, c + r)
+    };
+    assert!(f.intersects_bounds(&cube([0., 0., -5.], 0.1)));
+    // straddling near plane and side plane
+    assert!(f.intersects_bounds(&cube([0., 0., -0.5], 0.2)));
+    assert!(f.intersects_bounds(&cube([3., 0., -2.], 1.2)));
+    assert!(!f.intersects_bounds(&cube([3., 0., -2.], 0.4)));
+    assert!(!f.intersects_bounds(&cube([0., 0., 2.], 1.)));
+    assert!(!f.intersects_bounds(&cube([0., 0., -12.], 1.)));
+}
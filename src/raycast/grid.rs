@@ -0,0 +1,649 @@
+use crate::{
+    core::tensor::Vec3f,
+    raycast::{bounds::Bounds3f, primitive::Primitive, *},
+};
+
+/// uniform grid of cells listing overlapping primitives, traversed by 3d dda.
+/// primitives keep their order, updates only touch cells of changed primitives
+pub struct UniformGrid<T: Primitive> {
+    pub primitives: Vec<T>,
+    pub bounds: Bounds3f,
+    /// cells along each axis
+    pub res: [usize; 3],
+    /// primitive indices overlapping each cell, x varies fastest
+    pub cells: Vec<Vec<usize>>,
+    /// cells along widest axis per cube root of primitive count
+    pub density: f32,
+    /// primitives with NaN or infinite bounds, left out of grid
+    pub invalid_prims: Vec<usize>,
+    /// invalid primitives that are Primitive::unbounded, traced next to the grid
+    pub unbounded_prims: Vec<usize>,
+}
+
+impl<T: Primitive> UniformGrid<T> {
+    pub const MAX_RES: usize = 256;
+
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            primitives: Vec::with_capacity(capacity),
+            bounds: Bounds3f::empty(),
+            res: [0; 3],
+            cells: Vec::new(),
+            density: 3.,
+            invalid_prims: Vec::new(),
+            unbounded_prims: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, prim: T) {
+        self.primitives.push(prim);
+    }
+
+    /// resolution from density, primitives with NaN or infinite bounds are reported and left out.
+    /// unbounded ones are traced next to the grid
+    pub fn build(&mut self) {
+        self.invalid_prims = (0..self.primitives.len())
+            .filter(|&i| !self.primitives[i].bounds().is_finite())
+            .collect();
+        self.unbounded_prims = self
+            .invalid_prims
+            .iter()
+            .copied()
+            .filter(|&i| self.primitives[i].unbounded())
+            .collect();
+        if self.invalid_prims.len() > self.unbounded_prims.len() {
+            eprintln!(
+                "warn: {} primitives with NaN or infinite bounds left out of grid",
+                self.invalid_prims.len() - self.unbounded_prims.len()
+            );
+        }
+
+        self.bounds = (0..self.primitives.len())
+            .filter(|i| !self.invalid_prims.contains(i))
+            .fold(Bounds3f::empty(), |b, i| {
+                b.union(self.primitives[i].bounds())
+            });
+        let nvalid = self.primitives.len() - self.invalid_prims.len();
+        if nvalid == 0 {
+            self.res = [0; 3];
+            self.cells.clear();
+            return;
+        }
+
+        let d = self.bounds.diagonal();
+        let widest = d[self.bounds.max_dim()];
+        let per_unit = if widest > 0. {
+            self.density * (nvalid as f32).cbrt() / widest
+        } else {
+            0.
+        };
+        self.res =
+            std::array::from_fn(|i| ((d[i] * per_unit).round() as usize).clamp(1, Self::MAX_RES));
+        self.cells = vec![Vec::new(); self.res.iter().product()];
+
+        for prim_i in 0..self.primitives.len() {
+            if !self.invalid_prims.contains(&prim_i) {
+                self.add_to_cells(prim_i);
+            }
+        }
+    }
+
+    /// returns index of inserted primitive, grid is rebuilt if primitive is outside it
+    pub fn insert(&mut self, prim: T) -> usize {
+        let bounds = prim.bounds();
+        self.primitives.push(prim);
+        let prim_i = self.primitives.len() - 1;
+
+        if !bounds.is_finite() {
+            self.leave_out(prim_i);
+        } else if self.covers(&bounds) {
+            self.add_to_cells(prim_i);
+        } else {
+            self.build();
+        }
+        prim_i
+    }
+
+    /// last primitive takes index of removed one, as Vec::swap_remove
+    pub fn remove(&mut self, prim_i: usize) -> T {
+        self.remove_from_cells(prim_i);
+        let last = self.primitives.len() - 1;
+        if last != prim_i {
+            self.remove_from_cells(last);
+        }
+
+        let prim = self.primitives.swap_remove(prim_i);
+        let last_invalid = self.invalid_prims.contains(&last);
+        let last_unbounded = self.unbounded_prims.contains(&last);
+        self.invalid_prims.retain(|&i| i != prim_i && i != last);
+        self.unbounded_prims.retain(|&i| i != prim_i && i != last);
+        if last != prim_i {
+            if last_unbounded {
+                self.unbounded_prims.push(prim_i);
+            }
+            if last_invalid {
+                self.invalid_prims.push(prim_i);
+            } else {
+                self.add_to_cells(prim_i);
+            }
+        }
+        prim
+    }
+
+    /// replace primitive, e.g. moved one, grid is rebuilt if new one is outside it
+    pub fn update(&mut self, prim_i: usize, prim: T) {
+        self.remove_from_cells(prim_i);
+        let bounds = prim.bounds();
+        self.primitives[prim_i] = prim;
+        self.invalid_prims.retain(|&i| i != prim_i);
+        self.unbounded_prims.retain(|&i| i != prim_i);
+
+        if !bounds.is_finite() {
+            self.leave_out(prim_i);
+        } else if self.covers(&bounds) {
+            self.add_to_cells(prim_i);
+        } else {
+            self.build();
+        }
+    }
+
+    /// primitive with NaN or infinite bounds, reported unless it is unbounded
+    fn leave_out(&mut self, prim_i: usize) {
+        if self.primitives[prim_i].unbounded() {
+            self.unbounded_prims.push(prim_i);
+        } else {
+            eprintln!("warn: primitive {prim_i} with NaN or infinite bounds left out of grid");
+        }
+        self.invalid_prims.push(prim_i);
+    }
+
+    fn covers(&self, b: &Bounds3f) -> bool {
+        !self.cells.is_empty() && self.bounds.contains(b.min) && self.bounds.contains(b.max)
+    }
+
+    /// cell coordinate of p, clamped into grid
+    fn cell_of(&self, p: Vec3f) -> [usize; 3] {
+        let o = self.bounds.offset(p);
+        std::array::from_fn(|i| {
+            let c = (o[i] * self.res[i] as f32).floor();
+            (c.max(0.) as usize).min(self.res[i] - 1)
+        })
+    }
+
+    /// indices of cells overlapping b
+    fn cells_of(&self, b: &Bounds3f) -> Vec<usize> {
+        let (lo, hi) = (self.cell_of(b.min), self.cell_of(b.max));
+        let mut found = Vec::new();
+        for z in lo[2]..=hi[2] {
+            for y in lo[1]..=hi[1] {
+                for x in lo[0]..=hi[0] {
+                    found.push((z * self.res[1] + y) * self.res[0] + x);
+                }
+            }
+        }
+        found
+    }
+
+    fn add_to_cells(&mut self, prim_i: usize) {
+        for c in self.cells_of(&self.primitives[prim_i].bounds()) {
+            self.cells[c].push(prim_i);
+        }
+    }
+
+    fn remove_from_cells(&mut self, prim_i: usize) {
+        if self.cells.is_empty() || self.invalid_prims.contains(&prim_i) {
+            return;
+        }
+        for c in self.cells_of(&self.primitives[prim_i].bounds()) {
+            self.cells[c].retain(|&i| i != prim_i);
+        }
+    }
+
+    pub fn raycast_node(&self, ray: &Ray) -> Option<(Hit, usize)> {
+        self.traverse(ray, false, false, |_, _, _| false)
+    }
+
+    /// F (ray,hit, primitve index) -> if skip, every primitive is reported once.
+    /// return cloest hit in not skiiped hits
+    pub fn any_raycast<F>(&self, ray: &Ray, anyhit: F) -> Option<(Hit, usize)>
+    where
+        F: FnMut(&Ray, Hit, usize) -> bool,
+    {
+        self.traverse(ray, true, false, anyhit)
+    }
+
+    /// any primitive hit within ray segment, stops at first hit found
+    pub fn occluded(&self, ray: &Ray) -> bool {
+        self.traverse(ray, false, true, |_, _, _| false).is_some()
+    }
+
+    /// every hit within ray segment in increasing t, F (hit, primitive index) -> if stop.
+    /// hits are handed out once no later cell can hold a nearer one
+    pub fn ordered_hits<F>(&self, ray: &Ray, mut f: F)
+    where
+        F: FnMut(Hit, usize) -> bool,
+    {
+        // primitives spanning cells hit again, few hits so a list is enough
+        let mut reported = Vec::new();
+        // far to near, nearest popped first
+        let mut pending: Vec<(Hit, usize)> = self
+            .unbounded_prims
+            .iter()
+            .filter_map(|&prim_i| Some((self.primitives[prim_i].raycast(ray)?, prim_i)))
+            .collect();
+        pending.sort_by(|a, b| b.0.t.total_cmp(&a.0.t));
+        let mut stopped = false;
+
+        self.walk(ray, |prims, t_exit| {
+            let mut found = false;
+            for &prim_i in prims {
+                if let Some(hit) = self.primitives[prim_i].raycast(ray)
+                    && first_report(&mut reported, prim_i)
+                {
+                    pending.push((hit, prim_i));
+                    found = true;
+                }
+            }
+            if found {
+                pending.sort_by(|a, b| b.0.t.total_cmp(&a.0.t));
+            }
+            while let Some(&(hit, prim_i)) = pending.last()
+                && hit.t <= t_exit
+            {
+                pending.pop();
+                if f(hit, prim_i) {
+                    stopped = true;
+                    return true;
+                }
+            }
+            false
+        });
+
+        // hits rounded past exit of last cell
+        while !stopped && let Some((hit, prim_i)) = pending.pop() {
+            stopped = f(hit, prim_i);
+        }
+    }
+
+    /// unique: report hit primitive once, as it may overlap many cells.
+    /// first: return first accepted hit instead of nearest
+    fn traverse<F>(
+        &self,
+        ray: &Ray,
+        unique: bool,
+        first: bool,
+        mut anyhit: F,
+    ) -> Option<(Hit, usize)>
+    where
+        F: FnMut(&Ray, Hit, usize) -> bool,
+    {
+        let mut hit: Option<(Hit, usize)> = None;
+        let mut reported = Vec::new();
+        let cur = &mut ray.clone();
+
+        // unbounded primitives first, their hit ends the walk early
+        for &prim_i in self.unbounded_prims.iter() {
+            if let Some(hit_p) = self.primitives[prim_i].raycast(cur)
+                && !anyhit(cur, hit_p, prim_i)
+            {
+                cur.t_max = hit_p.t;
+                hit = Some((hit_p, prim_i));
+                if first {
+                    return hit;
+                }
+            }
+        }
+
+        self.walk(ray, |prims, t_exit| {
+            for &prim_i in prims {
+                if let Some(hit_p) = self.primitives[prim_i].raycast(cur)
+                    && (!unique || first_report(&mut reported, prim_i))
+                    && !anyhit(cur, hit_p, prim_i)
+                {
+                    cur.t_max = hit_p.t;
+                    hit = Some((hit_p, prim_i));
+                    if first {
+                        return true;
+                    }
+                }
+            }
+            // hits in later cells are farther
+            hit.is_some_and(|(h, _)| h.t <= t_exit)
+        });
+
+        hit
+    }
+
+    /// visit cells along ray within its segment in order, F (primitives of cell, t leaving cell) -> if stop
+    fn walk<F>(&self, ray: &Ray, mut f: F)
+    where
+        F: FnMut(&[usize], f32) -> bool,
+    {
+        if self.cells.is_empty() {
+            return;
+        }
+        let inv = InvRay::new(ray);
+        let Some((t0, t1)) = self.bounds.raycast_inv(&inv, ray.t_max) else {
+            return;
+        };
+
+        let d = self.bounds.diagonal();
+        let mut cell = self.cell_of(ray.org + ray.dir * t0);
+        let mut step = [0isize; 3];
+        let mut next_t = [f32::INFINITY; 3];
+        let mut delta_t = [f32::INFINITY; 3];
+        for i in 0..3 {
+            // one cell has no plane to cross inside grid
+            if self.res[i] == 1 || ray.dir[i] == 0. {
+                continue;
+            }
+            let size = d[i] / self.res[i] as f32;
+            let plane_cell = cell[i] + (1 - inv.neg[i]);
+            let plane = self.bounds.min[i] + size * plane_cell as f32;
+            next_t[i] = (plane - inv.org[i]) * inv.inv_dir[i];
+            delta_t[i] = size * inv.inv_dir[i].abs();
+            step[i] = if inv.neg[i] == 1 { -1 } else { 1 };
+        }
+
+        loop {
+            let axis = (0..3)
+                .min_by(|&a, &b| next_t[a].total_cmp(&next_t[b]))
+                .unwrap();
+            let t_exit = next_t[axis].min(t1);
+            let cell_i = (cell[2] * self.res[1] + cell[1]) * self.res[0] + cell[0];
+            if f(&self.cells[cell_i], t_exit) || t_exit >= t1 {
+                return;
+            }
+
+            let c = cell[axis] as isize + step[axis];
+            if c < 0 || c >= self.res[axis] as isize {
+                return;
+            }
+            cell[axis] = c as usize;
+            next_t[axis] += delta_t[axis];
+        }
+    }
+}
+
+/// mailbox of primitives already reported for a ray
+fn first_report(reported: &mut Vec<usize>, prim_i: usize) -> bool {
+    if reported.contains(&prim_i) {
+        return false;
+    }
+    reported.push(prim_i);
+    true
+}
+
+impl<T: Primitive> Raycast for UniformGrid<T> {
+    fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        self.raycast_node(ray).map(|(hit, _)| hit)
+    }
+}
+
+#[test]
+fn test_grid() {
+    use crate::core::vec::Vector;
+    use crate::raycast::{
+        bruteforce::{BruteForce, assert_matches_bruteforce, random_point},
+        sphere::Sphere,
+        triangle::Triangle,
+    };
+    use rand::Rng;
+
+    // same answers as brute force over same primitive order
+    fn check<T: Primitive>(grid: &UniformGrid<T>, oracle: &BruteForce<T>, rays: &[Ray]) {
+        assert_matches_bruteforce(grid, &oracle.primitives, rays, "grid");
+        for ray in rays {
+            let mut all = Vec::new();
+            oracle.any_raycast(ray, |_, h, i| {
+                all.push((h.t, i));
+                true
+            });
+            let by_t = |a: &(f32, usize), b: &(f32, usize)| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1));
+            all.sort_by(by_t);
+
+            let mut ordered = Vec::new();
+            grid.ordered_hits(ray, |h, i| {
+                ordered.push((h.t, i));
+                false
+            });
+            assert!(ordered.is_sorted_by(|a, b| a.0 <= b.0));
+            ordered.sort_by(by_t);
+            assert_eq!(ordered, all);
+
+            // stopped early keeps nearest ones
+            let mut two = Vec::new();
+            grid.ordered_hits(ray, |h, _| {
+                two.push(h.t);
+                two.len() == 2
+            });
+            let first: Vec<f32> = all.iter().take(2).map(|a| a.0).collect();
+            assert_eq!(two, first);
+        }
+    }
+
+    let mut rng = rand::rng();
+    // dense spheres, origins outside and inside, axis aligned and segment rays
+    let mut grid = UniformGrid::new(512);
+    let mut oracle = BruteForce::new(512);
+    for _ in 0..512 {
+        let s = Sphere::new(
+            random_point(&mut rng, 0., 32.),
+            0.5 + random_point(&mut rng, 0., 1.)[0],
+        );
+        grid.push(s.clone());
+        oracle.push(s);
+    }
+    grid.build();
+    assert!(grid.res.iter().all(|&r| r > 1));
+    let mut rays: Vec<Ray> = (0..256)
+        .map(|i| {
+            let org = if i % 2 == 0 {
+                Vec3f::vec([16.; 3]) + random_point(&mut rng, -1., 1.).normalize() * 48.
+            } else {
+                random_point(&mut rng, 0., 32.)
+            };
+            let dir = random_point(&mut rng, 0., 32.) - org;
+            if i % 4 == 1 {
+                Ray::segment(org, dir, 0.5)
+            } else {
+                Ray::new(org, dir)
+            }
+        })
+        .collect();
+    for axis in 0..3 {
+        let mut dir = Vec3f::vec([0.; 3]);
+        dir[axis] = -1.;
+        rays.push(Ray::new(Vec3f::vec([16.3, 15.7, 16.1]) + dir * -40., dir));
+    }
+    check(&grid, &oracle, &rays);
+
+    // moved, inserted, removed as swap_remove, one outside grid rebuilds it
+    for _ in 0..64 {
+        let i = rng.random_range(0..oracle.primitives.len());
+        let s = Sphere::new(random_point(&mut rng, 0., 32.), 0.7);
+        grid.update(i, s.clone());
+        oracle.primitives[i] = s;
+        let s = Sphere::new(random_point(&mut rng, 0., 32.), 0.7);
+        assert_eq!(grid.insert(s.clone()), oracle.primitives.len());
+        oracle.push(s);
+        let i = rng.random_range(0..oracle.primitives.len());
+        grid.remove(i);
+        oracle.primitives.swap_remove(i);
+    }
+    grid.insert(Sphere::new(Vec3f::vec([40.; 3]), 1.));
+    oracle.push(Sphere::new(Vec3f::vec([40.; 3]), 1.));
+    assert!(grid.bounds.contains(Vec3f::vec([41.; 3])));
+    rays.push(Ray::new(
+        Vec3f::vec([40., 40., 60.]),
+        Vec3f::vec([0., 0., -1.]),
+    ));
+    check(&grid, &oracle, &rays);
+
+    // flat scene of triangles, infinite bounds left out
+    let mut grid = UniformGrid::new(256);
+    let mut oracle = BruteForce::new(256);
+    for _ in 0..256 {
+        let p = random_point(&mut rng, 0., 32.);
+        let tri = Triangle::new(
+            Vec3f::vec([p[0], p[1], 0.]),
+            Vec3f::vec([p[0] + 2., p[1], 0.]),
+            Vec3f::vec([p[0], p[1] + 2., 0.]),
+        );
+        grid.push(tri.clone());
+        oracle.push(tri);
+    }
+    grid.push(Triangle::new(
+        Vec3f::vec([f32::INFINITY, 0., 0.]),
+        Vec3f::vec([0., 1., 0.]),
+        Vec3f::vec([0., 0., 1.]),
+    ));
+    grid.build();
+    assert_eq!(grid.invalid_prims, vec![256]);
+    assert_eq!(grid.res[2], 1);
+    let rays: Vec<Ray> = (0..256)
+        .map(|_| {
+            let org = random_point(&mut rng, -8., 40.) + Vec3f::vec([0., 0., 20.]);
+            Ray::new(org, random_point(&mut rng, 0., 32.) - org)
+        })
+        .collect();
+    check(&grid, &oracle, &rays);
+}
+
+#[test]
+fn test_grid_matches_bvh() {
+    use crate::core::vec::Vector;
+    use crate::raycast::{bvh::BVH, sphere::Sphere};
+    use rand::Rng;
+
+    // isotropic spheres filling a cube, same hits as bvh
+    let mut rng = rand::rng();
+    let n = 1024;
+    let side = (n as f32).cbrt() * 2.;
+    let mut bvh = BVH::new(n);
+    let mut grid = UniformGrid::new(n);
+    for _ in 0..n {
+        let cnt = Vec3f::vec(std::array::from_fn(|_| rng.random_range(0. ..side)));
+        bvh.push(Sphere::new(cnt, 0.4));
+        grid.push(Sphere::new(cnt, 0.4));
+    }
+    bvh.build(4, false);
+    grid.build();
+
+    for _ in 0..512 {
+        let org = Vec3f::vec(std::array::from_fn(|_| rng.random_range(0. ..side)));
+        let dir = Vec3f::vec(std::array::from_fn(|_| rng.random_range(-1. ..1.)));
+        let ray = Ray::new(org, dir.normalize());
+        assert_eq!(
+            bvh.raycast(&ray).map(|h| h.t),
+            grid.raycast(&ray).map(|h| h.t)
+        );
+        assert_eq!(bvh.occluded(&ray), grid.occluded(&ray));
+
+        // first 16 hits in order
+        let mut bvh_first = Vec::new();
+        bvh.ordered_hits(&ray, |h, _| {
+            bvh_first.push(h.t);
+            bvh_first.len() == 16
+        });
+        let mut grid_first = Vec::new();
+        grid.ordered_hits(&ray, |h, _| {
+            grid_first.push(h.t);
+            grid_first.len() == 16
+        });
+        assert_eq!(bvh_first, grid_first);
+    }
+}
+
+/// timings against bvh, run with --ignored
+#[test]
+#[ignore]
+fn test_grid_perf() {
+    use crate::core::vec::Vector;
+    use crate::raycast::{bvh::BVH, sphere::Sphere};
+    use rand::Rng;
+    use std::time::Instant;
+
+    let mut rng = rand::rng();
+    for n in [4096, 16384] {
+        // isotropic spheres filling a cube
+        let side = (n as f32).cbrt() * 2.;
+        let spheres: Vec<Sphere> = (0..n)
+            .map(|_| {
+                let cnt = Vec3f::vec(std::array::from_fn(|_| rng.random_range(0. ..side)));
+                Sphere::new(cnt, 0.4)
+            })
+            .collect();
+        let rays: Vec<Ray> = (0..4096)
+            .map(|_| {
+                let org = Vec3f::vec(std::array::from_fn(|_| rng.random_range(0. ..side)));
+                let dir = Vec3f::vec(std::array::from_fn(|_| rng.random_range(-1. ..1.)));
+                Ray::new(org, dir.normalize())
+            })
+            .collect();
+
+        let sw = Instant::now();
+        let mut bvh = BVH::new(n);
+        spheres.iter().for_each(|s| bvh.push(s.clone()));
+        bvh.build(4, false);
+        let bvh_build = sw.elapsed().as_millis();
+        let sw = Instant::now();
+        let mut grid = UniformGrid::new(n);
+        spheres.iter().for_each(|s| grid.push(s.clone()));
+        grid.build();
+        let grid_build = sw.elapsed().as_millis();
+        println!(
+            "{n} spheres, build bvh {bvh_build}ms, grid {:?} {grid_build}ms",
+            grid.res
+        );
+
+        let sw = Instant::now();
+        let bvh_hits: Vec<Option<f32>> = rays.iter().map(|r| bvh.raycast(r).map(|h| h.t)).collect();
+        let bvh_closest = sw.elapsed().as_millis();
+        let sw = Instant::now();
+        let grid_hits: Vec<Option<f32>> =
+            rays.iter().map(|r| grid.raycast(r).map(|h| h.t)).collect();
+        let grid_closest = sw.elapsed().as_millis();
+        assert_eq!(bvh_hits, grid_hits);
+        println!("closest hit, bvh {bvh_closest}ms, grid {grid_closest}ms");
+
+        let sw = Instant::now();
+        let bvh_occluded = rays.iter().filter(|r| bvh.occluded(r)).count();
+        let bvh_any = sw.elapsed().as_millis();
+        let sw = Instant::now();
+        let grid_occluded = rays.iter().filter(|r| grid.occluded(r)).count();
+        let grid_any = sw.elapsed().as_millis();
+        assert_eq!(bvh_occluded, grid_occluded);
+        println!("occluded, bvh {bvh_any}ms, grid {grid_any}ms");
+
+        // first 16 hits in order
+        let sw = Instant::now();
+        let bvh_first: Vec<Vec<f32>> = rays
+            .iter()
+            .map(|r| {
+                let mut ts = Vec::new();
+                bvh.ordered_hits(r, |h, _| {
+                    ts.push(h.t);
+                    ts.len() == 16
+                });
+                ts
+            })
+            .collect();
+        let bvh_ordered = sw.elapsed().as_millis();
+        let sw = Instant::now();
+        let grid_first: Vec<Vec<f32>> = rays
+            .iter()
+            .map(|r| {
+                let mut ts = Vec::new();
+                grid.ordered_hits(r, |h, _| {
+                    ts.push(h.t);
+                    ts.len() == 16
+                });
+                ts
+            })
+            .collect();
+        let grid_ordered = sw.elapsed().as_millis();
+        assert_eq!(bvh_first, grid_first);
+        println!("first 16 ordered hits, bvh {bvh_ordered}ms, grid {grid_ordered}ms");
+    }
+}
@@ -0,0 +1,264 @@
+use std::{fmt::Debug, sync::Arc};
+
+use crate::{
+    core::{
+        transform::{MotionTransform, Transform},
+        vec::Vector,
+    },
+    raycast::{bounds::Bounds3f, bvh::BVH, primitive::Primitive, *},
+};
+
+/// placement of a shared bottom level BVH in world space.
+/// a BVH over instances is the top level structure
+#[derive(Clone)]
+pub struct Instance<T: Primitive> {
+    pub id: usize,
+    pub blas: Arc<BVH<T>>,
+    motion: MotionTransform,
+    bounds: Bounds3f,
+}
+
+impl<T: Primitive> Instance<T> {
+    /// blas must be built before instancing
+    pub fn new(id: usize, blas: Arc<BVH<T>>, transform: Transform) -> Self {
+        Self::moving(id, blas, MotionTransform::fixed(transform))
+    }
+
+    /// placed by ray time, bounds cover whole motion
+    pub fn moving(id: usize, blas: Arc<BVH<T>>, motion: MotionTransform) -> Self {
+        let bounds = blas.bounds().transform_motion(&motion);
+        Instance {
+            id,
+            blas,
+            motion,
+            bounds,
+        }
+    }
+
+    /// transform of first key
+    pub fn transform(&self) -> &Transform {
+        &self.motion.keys()[0].1
+    }
+
+    pub fn motion(&self) -> &MotionTransform {
+        &self.motion
+    }
+
+    /// ray in object space at ray time, direction is not normalized so t stays the same
+    pub fn object_ray(&self, ray: &Ray) -> Ray {
+        ray.to_object(&self.motion.at(ray.time))
+    }
+
+    /// nearest hit and its primitive index in blas
+    pub fn raycast_node(&self, ray: &Ray) -> Option<(Hit, usize)> {
+        self.blas.raycast_node(&self.object_ray(ray))
+    }
+}
+
+impl<T: Primitive> Raycast for Instance<T> {
+    /// part of hit is primitive index in blas
+    fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        self.raycast_node(ray)
+            .map(|(hit, prim)| Hit { part: prim, ..hit })
+    }
+}
+
+impl<T: Primitive> Debug for Instance<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "instance {} {} prims {:?}",
+            self.id,
+            self.blas.primitives.len(),
+            self.bounds
+        )
+    }
+}
+
+impl<T: Primitive> Primitive for Instance<T> {
+    fn bounds(&self) -> Bounds3f {
+        self.bounds
+    }
+
+    /// hit must come from raycast of this instance, its part is the blas primitive.
+    /// instances of instances lose the inner part
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Option<Surface> {
+        let t = self.motion.at(ray.time);
+        let obj_ray = ray.to_object(&t);
+        // object ray keeps t
+        let s = self.blas.primitives.get(hit.part)?.surface(&obj_ray, hit)?;
+        Some(Surface {
+            p: hit.position(ray),
+            n: t.normal(s.n).normalize(),
+            uv: s.uv,
+            bary: s.bary,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct InstanceHit {
+    pub hit: Hit,
+    /// Instance::id of hit instance
+    pub instance: usize,
+    /// primitive index in blas of hit instance
+    pub prim: usize,
+}
+
+impl<T: Primitive> BVH<Instance<T>> {
+    /// nearest hit over all instances, blas primitive comes with the hit
+    pub fn raycast_instance(&self, ray: &Ray) -> Option<InstanceHit> {
+        let (hit, i) = self.raycast_node(ray)?;
+        Some(InstanceHit {
+            hit,
+            instance: self.primitives[i].id,
+            prim: hit.part,
+        })
+    }
+}
+
+#[test]
+fn test_instance() {
+    use crate::core::{quaternion::Quat, vec::Vector};
+    use crate::raycast::sphere::Sphere;
+    use rand::Rng;
+
+    let mut rng = rand::rng();
+    let mut blas = BVH::new(64);
+    for _ in 0..64 {
+        let cnt = Vec3f::vec([
+            rng.random_range(-1.0..1.0),
+            rng.random_range(-1.0..1.0),
+            rng.random_range(-1.0..1.0),
+        ]);
+        blas.push(Sphere::new(cnt, rng.random_range(0.02..0.1)));
+    }
+    blas.build(4, false);
+    let blas = Arc::new(blas);
+
+    // uniform scale keeps spheres as spheres in world space
+    let transforms: Vec<Transform> = (0..16)
+        .map(|i| {
+            let s = rng.random_range(0.5..2.0);
+            Transform::new(
+                Vec3f::vec([(i % 4) as f32 * 3., (i / 4) as f32 * 3., 0.]),
+                Quat::angle_axis(
+                    rng.random_range(0.0..360.0),
+                    Vec3f::vec([0.3, 1., -0.5]).normalize(),
+                ),
+                Vec3f::vec([s; 3]),
+            )
+        })
+        .collect();
+
+    let mut tlas = BVH::new(transforms.len());
+    for (i, t) in transforms.iter().enumerate() {
+        tlas.push(Instance::new(100 + i, blas.clone(), *t));
+    }
+    tlas.build(2, false);
+
+    for b in tlas.primitives.iter() {
+        for s in blas.primitives.iter() {
+            let c = b.transform().point(s.cnt);
+            assert!(b.bounds().contains(c));
+        }
+    }
+
+    for _ in 0..512 {
+        let org = Vec3f::vec([
+            rng.random_range(-2.0..12.0),
+            rng.random_range(-2.0..12.0),
+            -10.,
+        ]);
+        let dst = Vec3f::vec([
+            rng.random_range(-2.0..12.0),
+            rng.random_range(-2.0..12.0),
+            0.,
+        ]);
+        let ray = Ray::new(org, dst - org);
+
+        // brute force over spheres placed in world space,
+        // rays grazing a sphere are skipped since both spaces round differently
+        let mut expected: Option<(f32, usize, usize)> = None;
+        let mut grazing = false;
+        for (i, t) in transforms.iter().enumerate() {
+            for (k, s) in blas.primitives.iter().enumerate() {
+                let ws = Sphere::new(t.point(s.cnt), s.r * t.scale[0]);
+                let op = ws.cnt - ray.org;
+                let d = op.cross(ray.dir).norm() / ray.dir.norm();
+                grazing |= (d - ws.r).abs() < 1e-3;
+                if let Some(hit) = ws.raycast(&ray)
+                    && expected.is_none_or(|(et, _, _)| hit.t < et)
+                {
+                    expected = Some((hit.t, 100 + i, k));
+                }
+            }
+        }
+
+        if grazing {
+            continue;
+        }
+
+        let got = tlas.raycast_instance(&ray);
+        match (expected, got) {
+            (None, None) => {}
+            (Some((t, id, k)), Some(h)) => {
+                assert!((t - h.hit.t).abs() < 1e-3 * t.max(1.));
+                assert_eq!(id, h.instance);
+                assert_eq!(k, h.prim);
+            }
+            _ => panic!("instance hit mismatch {:?} {:?}", expected, got),
+        }
+    }
+}
+
+#[test]
+fn test_moving_instance() {
+    use crate::raycast::sphere::Sphere;
+
+    let mut blas = BVH::new(1);
+    blas.push(Sphere::new(Vec3f::vec([0.; 3]), 0.5));
+    blas.build(2, false);
+    let blas = Arc::new(blas);
+
+    // slides along x during shutter, second stays
+    let motion = MotionTransform::new(vec![
+        (0., Transform::identity()),
+        (1., Transform::translate(Vec3f::vec([4., 0., 0.]))),
+    ]);
+    let mut tlas = BVH::new(2);
+    tlas.push(Instance::moving(0, blas.clone(), motion));
+    tlas.push(Instance::new(
+        1,
+        blas.clone(),
+        Transform::translate(Vec3f::vec([0., 0., -3.])),
+    ));
+    tlas.build(2, false);
+
+    let b = tlas.primitives.iter().find(|i| i.id == 0).unwrap().bounds();
+    assert!(b.min[0] <= -0.5 && b.max[0] >= 4.5);
+
+    let down = Vec3f::vec([0., -1., 0.]);
+    let mut ray = Ray::new(Vec3f::vec([2., 5., 0.]), down);
+    for (time, hit) in [(0., false), (0.5, true), (1., false)] {
+        ray.time = time;
+        assert_eq!(tlas.raycast_instance(&ray).is_some(), hit, "{time}");
+    }
+    ray.time = 0.5;
+    let h = tlas.raycast_instance(&ray).unwrap();
+    assert_eq!(h.instance, 0);
+    assert!((h.hit.t - 4.5).abs() < 1e-5);
+
+    // normal at time of ray, off center
+    ray.org = Vec3f::vec([2.3, 5., 0.]);
+    ray.time = 0.55;
+    // hit of top level traversal carries blas primitive
+    let (hit, i) = tlas.raycast_node(&ray).unwrap();
+    let s = tlas.primitives[i].surface(&ray, &hit).unwrap();
+    assert!((s.n - (s.p - Vec3f::vec([2.2, 0., 0.])) * 2.).norm() < 1e-4);
+
+    // static one is hit at any time
+    let mut ray = Ray::new(Vec3f::vec([0., 5., -3.]), down);
+    ray.time = 0.9;
+    assert_eq!(tlas.raycast_instance(&ray).map(|h| h.instance), Some(1));
+}
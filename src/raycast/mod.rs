@@ -1,17 +1,39 @@
-use crate::core::tensor::Vec3f;
+use crate::core::{tensor::Vec3f, transform::Transform, vec::Vector};
 
 pub mod bounds;
+pub mod bruteforce;
 pub mod bvh;
 pub mod bvhbuild;
+pub mod bvhcache;
+pub mod bvhquery;
+pub mod bvhstats;
+pub mod bvhupdate;
+pub mod capsule;
+pub mod cone;
+pub mod cylinder;
+pub mod disk;
+pub mod frustum;
+pub mod grid;
+pub mod instance;
 pub mod morton;
+pub mod moving;
+pub mod orientedbox;
+pub mod plane;
 pub mod primitive;
+pub mod sbvh;
+pub mod sdf;
 pub mod sphere;
+pub mod stack;
+pub mod triangle;
+pub mod widebvh;
 
 #[derive(Debug, Clone)]
 pub struct Ray {
     pub org: Vec3f,
     pub dir: Vec3f,
     pub t_max: f32,
+    /// time within camera shutter, poses moving primitives
+    pub time: f32,
 }
 
 impl Ray {
@@ -20,11 +42,26 @@ impl Ray {
             org,
             dir,
             t_max: f32::MAX,
+            time: 0.,
         }
     }
 
     pub fn segment(org: Vec3f, dir: Vec3f, t_max: f32) -> Ray {
-        Ray { org, dir, t_max }
+        Ray {
+            org,
+            dir,
+            t_max,
+            time: 0.,
+        }
+    }
+
+    /// ray in object space of t, direction is not normalized so t stays the same
+    pub fn to_object(&self, t: &Transform) -> Ray {
+        Ray {
+            org: t.inv_point(self.org),
+            dir: t.inv_vector(self.dir),
+            ..*self
+        }
     }
 
     /// move ray alone direction by scaling factor t
@@ -33,17 +70,74 @@ impl Ray {
     }
 }
 
+/// ray prepared for slab tests, inverse direction and its signs computed once per traversal
+#[derive(Debug, Clone, Copy)]
+pub struct InvRay {
+    pub org: [f32; 3],
+    pub inv_dir: [f32; 3],
+    /// 1 if direction is negative on axis, index of near slab in [min, max]
+    pub neg: [usize; 3],
+}
+
+impl InvRay {
+    pub fn new(ray: &Ray) -> InvRay {
+        let inv_dir = std::array::from_fn(|i| 1. / ray.dir[i]);
+        InvRay {
+            org: std::array::from_fn(|i| ray.org[i]),
+            inv_dir,
+            // sign of 1/dir keeps sign of -0 direction
+            neg: std::array::from_fn(|i| inv_dir[i].is_sign_negative() as usize),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Hit {
     pub t: f32,
+    /// primitive inside hit primitive, blas primitive of instance hits
+    pub part: usize,
 }
 
 impl Hit {
+    pub fn new(t: f32) -> Self {
+        Hit { t, part: 0 }
+    }
+
     pub fn position(&self, ray: &Ray) -> Vec3f {
         ray.org + ray.dir * self.t
     }
 }
 
+/// geometry at hit point, n is unit normal pointing out of primitive
+#[derive(Debug, Clone, Copy)]
+pub struct Surface {
+    pub p: Vec3f,
+    pub n: Vec3f,
+    pub uv: [f32; 2],
+    /// weights of triangle vertices, None for other shapes
+    pub bary: Option<[f32; 3]>,
+}
+
+/// relative offset of spawned rays, keeps them from hitting their own surface
+const SPAWN_EPS: f32 = 1e-4;
+
+impl Surface {
+    /// ray leaving surface towards dir, origin pushed off surface on side of dir
+    pub fn spawn_ray(&self, dir: Vec3f, time: f32) -> Ray {
+        let p = self.p;
+        let scale = 1. + p[0].abs().max(p[1].abs()).max(p[2].abs());
+        let offset = self.n * (SPAWN_EPS * scale);
+        let org = if self.n.dot(dir) < 0. {
+            p - offset
+        } else {
+            p + offset
+        };
+        let mut ray = Ray::new(org, dir);
+        ray.time = time;
+        ray
+    }
+}
+
 pub trait Raycast {
     /// ray direction not always a unit vector
     fn raycast(&self, ray: &Ray) -> Option<Hit>;
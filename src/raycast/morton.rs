@@ -1,47 +1,64 @@
 use crate::core::tensor::Vec3f;
 use std::mem;
 
-pub fn encode_morton3(p: Vec3f) -> usize {
-    let x = p[0] as usize;
-    let y = p[1] as usize;
-    let z = p[2] as usize;
-    (left_shift3(z) << 2) | (left_shift3(y) << 1) | left_shift3(x)
+/// 30-bit codes, 10 bits per axis
+pub const MORTON_BITS_30: usize = 10;
+/// 63-bit codes, 21 bits per axis
+pub const MORTON_BITS_63: usize = 21;
+
+/// bits per axis for n primitives.
+/// 30-bit codes collide once an axis holds more than 1024 distinct positions
+pub fn morton_bits(n: usize) -> usize {
+    if n > 1 << MORTON_BITS_30 {
+        MORTON_BITS_63
+    } else {
+        MORTON_BITS_30
+    }
 }
 
-pub fn left_shift3(mut x: usize) -> usize {
-    assert!(x < 1 << 10);
-    if x == (1 << 10) {
-        x -= 1
-    }
+/// coordinates must be less than 2^21. codes are u64 so 63-bit ones fit on 32-bit targets
+pub fn encode_morton3(p: Vec3f) -> u64 {
+    let x = p[0] as u64;
+    let y = p[1] as u64;
+    let z = p[2] as u64;
+    (left_shift3(z) << 2) | (left_shift3(y) << 1) | left_shift3(x)
+}
 
-    x = (x | (x << 16)) & 0b00000011000000000000000011111111;
-    // x = ---- --98 ---- ---- ---- ---- 7654 3210
-    x = (x | (x << 8)) & 0b00000011000000001111000000001111;
-    // x = ---- --98 ---- ---- 7654 ---- ---- 3210
-    x = (x | (x << 4)) & 0b00000011000011000011000011000011;
-    // x = ---- --98 ---- 76-- --54 ---- 32-- --10
-    x = (x | (x << 2)) & 0b00001001001001001001001001001001;
-    // x = ---- 9--8 --7- -6-- 5--4 --3- -2-- 1--0
+/// spread low 21 bits of x to every third bit
+pub fn left_shift3(mut x: u64) -> u64 {
+    assert!(x < 1 << MORTON_BITS_63);
+
+    x = (x | (x << 32)) & 0x001f_0000_0000_ffff;
+    // x = ---- ---- ---k jihg ---- ---- ---- ---- ---- ---- ---- ---- fedc ba98 7654 3210
+    x = (x | (x << 16)) & 0x001f_0000_ff00_00ff;
+    // x = ---- ---- ---k jihg ---- ---- ---- ---- fedc ba98 ---- ---- ---- ---- 7654 3210
+    x = (x | (x << 8)) & 0x100f_00f0_0f00_f00f;
+    // x = ---k ---- jihg ---- ---- fedc ---- ---- ba98 ---- ---- 7654 ---- ---- 3210
+    x = (x | (x << 4)) & 0x10c3_0c30_c30c_30c3;
+    // x = ---k ---- ji-- --hg ---- fe-- --dc ---- ba-- --98 ---- 76-- --54 ---- 32-- --10
+    x = (x | (x << 2)) & 0x1249_2492_4924_9249;
+    // x = ---k --j- -i-- h--g --f- -e-- d--c --b- -a-- 9--8 --7- -6-- 5--4 --3- -2-- 1--0
     x
 }
 
 pub trait MortonCode: Default {
-    fn morton_code(&self) -> usize;
+    fn morton_code(&self) -> u64;
 }
 
 pub fn radix_sort(v: &mut [impl MortonCode]) {
-    let mut orgv: Vec<(usize, usize)> = v
+    let mut orgv: Vec<(usize, u64)> = v
         .iter()
         .enumerate()
         .map(|(i, x)| (i, x.morton_code()))
         .collect();
 
-    let mut tempv: Vec<(usize, usize)> = (0..v.len()).enumerate().collect();
+    let mut tempv: Vec<(usize, u64)> = vec![(0, 0); v.len()];
 
+    // sort only bits in use, 30-bit codes need 5 passes and 63-bit ones 11
     let pass_bits = 6;
-    let nbits = 30;
-    assert_eq!(nbits % pass_bits, 0);
-    let npasses = nbits / pass_bits;
+    let max_code = orgv.iter().fold(0, |acc, (_, code)| acc | code);
+    let nbits = (u64::BITS - max_code.leading_zeros()) as usize;
+    let npasses = nbits.div_ceil(pass_bits);
 
     for pass in 0..npasses {
         // perform one pass of radix sort, sorting _bitsPerPass_ bits
@@ -59,10 +76,10 @@ pub fn radix_sort(v: &mut [impl MortonCode]) {
         let nbuckets = 1 << pass_bits;
         let mut buckets_count: Vec<usize> = vec![0; nbuckets];
         let bit_mask = (1 << pass_bits) - 1;
+        // bucket of code bits fits any usize
+        let bucket_of = |code: u64| ((code >> lowbit) & bit_mask) as usize;
         for &mp in invec.iter() {
-            let code = mp.1;
-            let bucket = (code >> lowbit) & bit_mask;
-            buckets_count[bucket] += 1;
+            buckets_count[bucket_of(mp.1)] += 1;
         }
 
         // compute starting index in output array for each bucket
@@ -73,8 +90,7 @@ pub fn radix_sort(v: &mut [impl MortonCode]) {
         }
 
         for &mp in invec.iter() {
-            let code = mp.1;
-            let bucket = (code >> lowbit) & bit_mask;
+            let bucket = bucket_of(mp.1);
             outvec[out_index[bucket]] = mp;
             out_index[bucket] += 1;
         }
@@ -151,18 +167,29 @@ fn test_encode_morton() {
     let p = Vec3f::vec([1023.; 3]);
     let m = encode_morton3(p);
     assert_eq!(m, 0b111111111111111111111111111111);
+
+    let p = Vec3f::vec([((1 << MORTON_BITS_63) - 1) as f32; 3]);
+    let m = encode_morton3(p);
+    assert_eq!(m, (1 << 63) - 1);
+
+    let p = Vec3f::vec([(1 << 20) as f32, 0., 1.]);
+    let m = encode_morton3(p);
+    assert_eq!(m, (1 << 60) | 0b100);
+
+    assert_eq!(morton_bits(1024), MORTON_BITS_30);
+    assert_eq!(morton_bits(1025), MORTON_BITS_63);
 }
 
 #[test]
 fn test_radix_sort() {
     #[derive(Default)]
     struct TestMorton {
-        morton_code: usize,
+        morton_code: u64,
         org_index: usize,
     }
 
     impl MortonCode for TestMorton {
-        fn morton_code(&self) -> usize {
+        fn morton_code(&self) -> u64 {
             self.morton_code
         }
     }
@@ -186,4 +213,20 @@ fn test_radix_sort() {
     for (i, m) in ms.iter().enumerate() {
         assert_eq!(m.org_index, i);
     }
+
+    // 63-bit codes, neighbours differ only below 30-bit precision
+    let mut ms: Vec<TestMorton> = (0..nm)
+        .map(|i| {
+            let x = (1 << MORTON_BITS_63) as f32 * (i as f32 / nm as f32) + 1.;
+            TestMorton {
+                morton_code: encode_morton3(Vec3f::vec([x, 0., x])),
+                org_index: i,
+            }
+        })
+        .collect();
+    ms.reverse();
+    radix_sort(&mut ms);
+    for (i, m) in ms.iter().enumerate() {
+        assert_eq!(m.org_index, i);
+    }
 }
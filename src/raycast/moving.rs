@@ -0,0 +1,146 @@
+use std::fmt::Debug;
+
+use crate::{
+    core::{transform::MotionTransform, vec::Vector},
+    raycast::{bounds::Bounds3f, primitive::Primitive, *},
+};
+
+/// primitive placed by keyframed transform, posed at time of each ray.
+/// bounds cover whole motion, so trees over moving primitives need no rebuild per time
+#[derive(Clone)]
+pub struct Moving<T: Primitive> {
+    pub prim: T,
+    motion: MotionTransform,
+    bounds: Bounds3f,
+}
+
+impl<T: Primitive> Moving<T> {
+    pub fn new(prim: T, motion: MotionTransform) -> Self {
+        let bounds = prim.bounds().transform_motion(&motion);
+        Moving {
+            prim,
+            motion,
+            bounds,
+        }
+    }
+
+    pub fn motion(&self) -> &MotionTransform {
+        &self.motion
+    }
+}
+
+impl<T: Primitive> Raycast for Moving<T> {
+    fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        self.prim.raycast(&ray.to_object(&self.motion.at(ray.time)))
+    }
+}
+
+impl<T: Primitive> Debug for Moving<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "moving {:?} keys {}",
+            self.prim,
+            self.motion.keys().len()
+        )
+    }
+}
+
+impl<T: Primitive> Primitive for Moving<T> {
+    fn bounds(&self) -> Bounds3f {
+        self.bounds
+    }
+
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Option<Surface> {
+        let t = self.motion.at(ray.time);
+        let s = self.prim.surface(&ray.to_object(&t), hit)?;
+        Some(Surface {
+            p: hit.position(ray),
+            n: t.normal(s.n).normalize(),
+            uv: s.uv,
+            bary: s.bary,
+        })
+    }
+
+    fn unbounded(&self) -> bool {
+        self.prim.unbounded()
+    }
+}
+
+#[test]
+fn test_moving() {
+    use crate::core::{quaternion::Quat, transform::Transform};
+    use crate::raycast::{
+        bruteforce::{assert_matches_bruteforce, random_point},
+        bvh::BVH,
+        orientedbox::OrientedBox,
+        sphere::Sphere,
+    };
+    use rand::Rng;
+
+    // sphere sliding along x
+    let slide = MotionTransform::new(vec![
+        (0., Transform::identity()),
+        (1., Transform::translate(Vec3f::vec([4., 0., 0.]))),
+    ]);
+    let sphere = Moving::new(Sphere::new(Vec3f::vec([0.; 3]), 1.), slide);
+    let b = sphere.bounds();
+    assert!(b.min[0] <= -1. && b.max[0] >= 5. && b.max[1] < 1.01);
+
+    let mut ray = Ray::new(Vec3f::vec([3., 0., 5.]), Vec3f::vec([0., 0., -1.]));
+    assert!(sphere.raycast(&ray).is_none());
+    ray.time = 0.75;
+    let hit = sphere.raycast(&ray).unwrap();
+    assert!((hit.t - 4.).abs() < 1e-5);
+    let s = sphere.surface(&ray, &hit).unwrap();
+    assert!((s.n - Vec3f::vec([0., 0., 1.])).norm() < 1e-5);
+
+    // box turning a quarter around y, face normal follows
+    let turn = MotionTransform::new(vec![
+        (0., Transform::identity()),
+        (
+            1.,
+            Transform::rigid(
+                Vec3f::vec([0.; 3]),
+                Quat::angle_axis(90., Vec3f::vec([0., 1., 0.])),
+            ),
+        ),
+    ]);
+    let half = Vec3f::vec([2., 0.5, 0.5]);
+    let obox = Moving::new(
+        OrientedBox::new(Vec3f::vec([0.; 3]), Quat::identity(), half),
+        turn,
+    );
+    let mut ray = Ray::new(Vec3f::vec([1.5, 0., 5.]), Vec3f::vec([0., 0., -1.]));
+    assert!((obox.raycast(&ray).unwrap().t - 4.5).abs() < 1e-5);
+    ray.time = 1.;
+    assert!(obox.raycast(&ray).is_none());
+    ray.org = Vec3f::vec([0.2, 0., 5.]);
+    let hit = obox.raycast(&ray).unwrap();
+    assert!((hit.t - 3.).abs() < 1e-4);
+    let n = obox.surface(&ray, &hit).unwrap().n;
+    assert!((n - Vec3f::vec([0., 0., 1.])).norm() < 1e-4);
+
+    // tree over moving spheres agrees with brute force at every time
+    let mut rng = rand::rng();
+    let mut bvh = BVH::new(128);
+    for _ in 0..128 {
+        let motion = MotionTransform::new(vec![
+            (0., Transform::translate(random_point(&mut rng, 0., 16.))),
+            (0.5, Transform::translate(random_point(&mut rng, 0., 16.))),
+            (1., Transform::translate(random_point(&mut rng, 0., 16.))),
+        ]);
+        bvh.push(Moving::new(Sphere::new(Vec3f::vec([0.; 3]), 0.5), motion));
+    }
+    bvh.build(4, false);
+    let rays: Vec<Ray> = (0..512)
+        .map(|_| {
+            let org = Vec3f::vec([rng.random_range(0. ..16.), rng.random_range(0. ..16.), 20.]);
+            let mut ray = Ray::new(org, Vec3f::vec([0.1, 0.1, -1.]));
+            ray.time = rng.random_range(0. ..1.);
+            ray
+        })
+        .collect();
+    let hits = assert_matches_bruteforce(&bvh, &bvh.primitives, &rays, "moving");
+    assert!(hits.iter().any(|h| h.is_some()));
+}
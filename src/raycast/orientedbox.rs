@@ -0,0 +1,121 @@
+use std::fmt::Debug;
+
+use crate::{
+    core::{quaternion::Quat, tensor::Vec3f, transform::Transform, vec::Vector},
+    raycast::{bounds::Bounds3f, primitive::Primitive, *},
+};
+
+/// box with half extents half around pos, rotated by rot
+#[derive(Clone)]
+pub struct OrientedBox {
+    pub half: Vec3f,
+    transform: Transform,
+}
+
+impl OrientedBox {
+    pub fn new(pos: Vec3f, rot: Quat, half: Vec3f) -> OrientedBox {
+        OrientedBox {
+            half,
+            transform: Transform::rigid(pos, rot),
+        }
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    fn local_bounds(&self) -> Bounds3f {
+        Bounds3f::new(self.half * -1., self.half)
+    }
+}
+
+impl Raycast for OrientedBox {
+    fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        let local = ray.to_object(&self.transform);
+        let (t0, t1) = self
+            .local_bounds()
+            .raycast_inv(&InvRay::new(&local), f32::INFINITY)?;
+        // leaving box if origin is inside
+        let t = if t0 > 0. { t0 } else { t1 };
+        (t <= ray.t_max).then_some(Hit::new(t))
+    }
+}
+
+impl Debug for OrientedBox {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.transform.pos, self.half)
+    }
+}
+
+impl Primitive for OrientedBox {
+    fn bounds(&self) -> Bounds3f {
+        self.local_bounds().transform(&self.transform)
+    }
+
+    fn distance(&self, p: Vec3f) -> f32 {
+        self.local_bounds().distance(self.transform.inv_point(p))
+    }
+
+    /// face is the axis p is relatively farthest along, uv spans face in [0,1]
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Option<Surface> {
+        let p = hit.position(ray);
+        let lp = self.transform.inv_point(p);
+        let rel = |i: usize| lp[i] / self.half[i];
+        let axis = (0..3)
+            .max_by(|&a, &b| rel(a).abs().total_cmp(&rel(b).abs()))
+            .unwrap();
+
+        let mut n = Vec3f::vec([0.; 3]);
+        n[axis] = 1f32.copysign(lp[axis]);
+        let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+        Some(Surface {
+            p,
+            n: self.transform.normal(n).normalize(),
+            uv: [(rel(a) + 1.) * 0.5, (rel(b) + 1.) * 0.5],
+            bary: None,
+        })
+    }
+}
+
+#[test]
+fn test_oriented_box() {
+    let half = Vec3f::vec([1., 2., 0.5]);
+    let obox = OrientedBox::new(Vec3f::vec([0., 0., -5.]), Quat::identity(), half);
+
+    for i in 0..11 {
+        let y = i as f32 / 10. * 4. - 2.;
+        let ray = Ray::new(Vec3f::vec([0.5, y, 0.]), Vec3f::vec([0., 0., -1.]));
+        let hit = obox.raycast(&ray).unwrap();
+        assert_eq!(hit.t, 4.5);
+
+        let s = obox.surface(&ray, &hit).unwrap();
+        assert_eq!(s.n, Vec3f::vec([0., 0., 1.]));
+        // face of z spans x then y
+        assert!((s.uv[0] - 0.75).abs() < 1e-6);
+        assert!((s.uv[1] - i as f32 / 10.).abs() < 1e-6);
+    }
+
+    // inside hits far side
+    let ray = Ray::new(Vec3f::vec([0., 0., -5.]), Vec3f::vec([1., 0., 0.]));
+    let hit = obox.raycast(&ray).unwrap();
+    assert!((hit.t - 1.).abs() < 1e-5);
+    let s = obox.surface(&ray, &hit).unwrap();
+    assert_eq!(s.n, Vec3f::vec([1., 0., 0.]));
+    assert!(obox.raycast(&Ray::segment(ray.org, ray.dir, 0.5)).is_none());
+    let ray = Ray::new(Vec3f::vec([1.5, 0., 0.]), Vec3f::vec([0., 0., -1.]));
+    assert!(obox.raycast(&ray).is_none());
+
+    // rotated 45 degrees around y, corner faces +z
+    let rot = Quat::angle_axis(45., Vec3f::vec([0., 1., 0.]));
+    let cube = OrientedBox::new(Vec3f::vec([0.; 3]), rot, Vec3f::vec([1.; 3]));
+    let ray = Ray::new(Vec3f::vec([0.1, 0., 5.]), Vec3f::vec([0., 0., -1.]));
+    let hit = cube.raycast(&ray).unwrap();
+    assert!((hit.t - (5. - 2f32.sqrt() + 0.1)).abs() < 1e-4);
+    let s = cube.surface(&ray, &hit).unwrap();
+    assert!((s.n[0] - 0.5f32.sqrt()).abs() < 1e-4 && (s.n[2] - 0.5f32.sqrt()).abs() < 1e-4);
+
+    let b = cube.bounds();
+    assert!((b.max[0] - 2f32.sqrt()).abs() < 1e-5 && (b.max[1] - 1.).abs() < 1e-5);
+    assert!((cube.distance(Vec3f::vec([0., 0., 3.])) - (3. - 2f32.sqrt())).abs() < 1e-5);
+    assert_eq!(cube.distance(Vec3f::vec([0.; 3])), 0.);
+}
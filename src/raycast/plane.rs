@@ -0,0 +1,282 @@
+use std::fmt::Debug;
+
+use crate::{
+    core::{math::coordinate_system, tensor::Vec3f, vec::Vector},
+    raycast::{bounds::Bounds3f, primitive::Primitive, *},
+};
+
+/// plane through p with unit normal n, infinite or a rectangle centered at p.
+/// infinite planes have infinite bounds, BVH and grid trace them next to their trees
+#[derive(Clone)]
+pub struct Plane {
+    pub p: Vec3f,
+    pub n: Vec3f,
+    /// unit tangents, u x v = n
+    pub u: Vec3f,
+    pub v: Vec3f,
+    /// half extents along u and v, infinite if None
+    pub half: Option<[f32; 2]>,
+}
+
+impl Plane {
+    pub fn infinite(p: Vec3f, n: Vec3f) -> Plane {
+        let n = n.normalize();
+        let (u, v) = coordinate_system(n);
+        Plane {
+            p,
+            n,
+            u,
+            v,
+            half: None,
+        }
+    }
+
+    /// rectangle with edges along u_axis projected onto plane
+    pub fn rect(p: Vec3f, n: Vec3f, u_axis: Vec3f, half: [f32; 2]) -> Plane {
+        let n = n.normalize();
+        let u = (u_axis - n * n.dot(u_axis)).normalize();
+        Plane {
+            p,
+            n,
+            u,
+            v: n.cross(u),
+            half: Some(half),
+        }
+    }
+
+    /// coordinates of point in plane along u and v, relative to p
+    fn local(&self, q: Vec3f) -> [f32; 2] {
+        let d = q - self.p;
+        [d.dot(self.u), d.dot(self.v)]
+    }
+}
+
+impl Raycast for Plane {
+    fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        let denom = self.n.dot(ray.dir);
+        // ray parallel to plane
+        if denom == 0. {
+            return None;
+        }
+        let t = self.n.dot(self.p - ray.org) / denom;
+        if !(t >= 0. && t <= ray.t_max) {
+            return None;
+        }
+
+        if let Some(half) = self.half {
+            let [a, b] = self.local(ray.org + ray.dir * t);
+            if a.abs() > half[0] || b.abs() > half[1] {
+                return None;
+            }
+        }
+        Some(Hit::new(t))
+    }
+}
+
+impl Debug for Plane {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {:?}", self.p, self.n, self.half)
+    }
+}
+
+impl Primitive for Plane {
+    fn bounds(&self) -> Bounds3f {
+        let Some(half) = self.half else {
+            return Bounds3f::new(
+                Vec3f::vec([f32::NEG_INFINITY; 3]),
+                Vec3f::vec([f32::INFINITY; 3]),
+            );
+        };
+        let e = Vec3f::vec(std::array::from_fn(|i| {
+            self.u[i].abs() * half[0] + self.v[i].abs() * half[1]
+        }));
+        Bounds3f::new(self.p - e, self.p + e)
+    }
+
+    fn distance(&self, q: Vec3f) -> f32 {
+        let Some(half) = self.half else {
+            return self.n.dot(q - self.p).abs();
+        };
+        let [a, b] = self.local(q);
+        let nearest =
+            self.p + self.u * a.clamp(-half[0], half[0]) + self.v * b.clamp(-half[1], half[1]);
+        (q - nearest).norm()
+    }
+
+    fn unbounded(&self) -> bool {
+        self.half.is_none()
+    }
+
+    /// uv of rectangle in [0,1], of infinite plane distances along u and v
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Option<Surface> {
+        let p = hit.position(ray);
+        let [a, b] = self.local(p);
+        let uv = match self.half {
+            Some(half) => [(a / half[0] + 1.) * 0.5, (b / half[1] + 1.) * 0.5],
+            None => [a, b],
+        };
+        Some(Surface {
+            p,
+            n: self.n,
+            uv,
+            bary: None,
+        })
+    }
+}
+
+#[test]
+fn test_plane() {
+    let up = Vec3f::vec([0., 1., 0.]);
+    let rect = Plane::rect(
+        Vec3f::vec([0., 1., 0.]),
+        up,
+        Vec3f::vec([1., 0., 0.]),
+        [2., 1.],
+    );
+
+    for i in 0..11 {
+        let x = i as f32 / 10. * 4. - 2.;
+        let ray = Ray::new(Vec3f::vec([x, 3., 0.5]), up * -1.);
+        let hit = rect.raycast(&ray).unwrap();
+        assert_eq!(hit.t, 2.);
+
+        let s = rect.surface(&ray, &hit).unwrap();
+        assert_eq!(s.p[1], 1.);
+        assert_eq!(s.n, up);
+        assert!((s.uv[0] - i as f32 / 10.).abs() < 1e-6);
+        // v = n x u points to -z
+        assert_eq!(s.uv[1], 0.25);
+    }
+
+    // beside rectangle, beyond segment, behind origin, parallel
+    let down = up * -1.;
+    assert!(
+        rect.raycast(&Ray::new(Vec3f::vec([2.1, 3., 0.]), down))
+            .is_none()
+    );
+    assert!(
+        rect.raycast(&Ray::segment(Vec3f::vec([0., 3., 0.]), down, 1.9))
+            .is_none()
+    );
+    assert!(
+        rect.raycast(&Ray::new(Vec3f::vec([0., 0., 0.]), down))
+            .is_none()
+    );
+    assert!(
+        rect.raycast(&Ray::new(
+            Vec3f::vec([0., 1., 0.]),
+            Vec3f::vec([1., 0., 0.])
+        ))
+        .is_none()
+    );
+
+    let b = rect.bounds();
+    assert_eq!(
+        b,
+        Bounds3f::new(Vec3f::vec([-2., 1., -1.]), Vec3f::vec([2., 1., 1.]))
+    );
+    assert_eq!(rect.distance(Vec3f::vec([0., 3., 0.])), 2.);
+    assert_eq!(rect.distance(Vec3f::vec([5., 5., 0.])), 5.);
+
+    // infinite plane hit far away, infinite bounds
+    let plane = Plane::infinite(Vec3f::vec([0.; 3]), up);
+    let ray = Ray::new(Vec3f::vec([100., 1., -50.]), Vec3f::vec([1., -1., 0.]));
+    let hit = plane.raycast(&ray).unwrap();
+    assert_eq!(hit.position(&ray)[0], 101.);
+    let s = plane.surface(&ray, &hit).unwrap();
+    assert_eq!(s.n, up);
+    assert!((Vec3f::vec([s.uv[0], s.uv[1], 0.]).norm() - hit.position(&ray).norm()).abs() < 1e-3);
+    assert!(!plane.bounds().is_finite());
+    assert_eq!(plane.distance(Vec3f::vec([7., -3., 9.])), 3.);
+}
+
+#[test]
+fn test_plane_in_bvh() {
+    use crate::raycast::{bvh::BVH, grid::UniformGrid, widebvh::BVHWidth};
+    use rand::Rng;
+
+    // ground plane below floating rectangles, traced next to the trees
+    let up = Vec3f::vec([0., 1., 0.]);
+    let x = Vec3f::vec([1., 0., 0.]);
+    let mut prims = vec![Plane::infinite(Vec3f::vec([0.; 3]), up)];
+    for i in 0..20 {
+        let p = Vec3f::vec([(i % 5) as f32 * 2., 1. + (i / 5) as f32, 0.]);
+        prims.push(Plane::rect(p, up, x, [0.5, 0.5]));
+    }
+
+    let mut bvh = BVH::new(prims.len());
+    let mut grid = UniformGrid::new(prims.len());
+    for prim in prims.iter() {
+        bvh.push(prim.clone());
+        grid.push(prim.clone());
+    }
+    bvh.build(2, false);
+    grid.build();
+    // build reorders primitives, hits are compared by t and plane point
+    assert_eq!(bvh.unbounded_prims.len(), 1);
+    assert!(bvh.primitives[bvh.unbounded_prims[0]].half.is_none());
+    assert_eq!(grid.unbounded_prims, vec![0]);
+
+    let nearest = |ray: &Ray| {
+        prims
+            .iter()
+            .filter_map(|prim| Some((prim.raycast(ray)?.t, prim.p)))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    };
+
+    let mut rng = rand::rng();
+    let mut plane_hits = 0;
+    for width in [BVHWidth::Binary, BVHWidth::Four] {
+        bvh.collapse(width);
+        for _ in 0..200 {
+            let org = Vec3f::vec([
+                rng.random_range(-20.0..30.),
+                rng.random_range(6.0..10.),
+                rng.random_range(-20.0..20.),
+            ]);
+            let dir = Vec3f::vec([rng.random_range(-1.0..1.), -1., rng.random_range(-1.0..1.)]);
+            let ray = Ray::new(org, dir);
+            let expected = nearest(&ray);
+            let got = bvh
+                .raycast_node(&ray)
+                .map(|(h, i)| (h.t, bvh.primitives[i].p));
+            assert_eq!(got, expected);
+            let got = grid
+                .raycast_node(&ray)
+                .map(|(h, i)| (h.t, grid.primitives[i].p));
+            assert_eq!(got, expected);
+            assert!(bvh.occluded(&ray));
+            assert!(grid.occluded(&ray));
+            plane_hits += (expected.unwrap().1 == prims[0].p) as usize;
+
+            let mut ts = Vec::new();
+            grid.ordered_hits(&ray, |hit, _| {
+                ts.push(hit.t);
+                false
+            });
+            assert_eq!(ts.first().copied(), expected.map(|e| e.0));
+            assert!(ts.is_sorted());
+        }
+    }
+    assert!(plane_hits > 0);
+
+    // upward rays miss everything, plane still traced with the ray cut short
+    let ray = Ray::new(Vec3f::vec([0., 5., 0.]), up);
+    assert!(bvh.raycast_node(&ray).is_none());
+    let seg = Ray::segment(Vec3f::vec([50., 1., 50.]), up * -1., 0.5);
+    assert!(!bvh.occluded(&seg));
+    assert!(!grid.occluded(&seg));
+
+    // inserted and removed through updates
+    let wall = bvh.insert(Plane::infinite(Vec3f::vec([100., 0., 0.]), x * -1.));
+    assert_eq!(bvh.unbounded_prims.len(), 2);
+    assert!(bvh.unbounded_prims.contains(&wall));
+    let ray = Ray::new(Vec3f::vec([90., 5., 0.]), x);
+    assert_eq!(bvh.raycast_node(&ray).unwrap().1, wall);
+    let ground = bvh.unbounded_prims[0];
+    bvh.remove(ground);
+    assert_eq!(bvh.unbounded_prims.len(), 1);
+    let i = bvh.unbounded_prims[0];
+    assert_eq!(bvh.raycast_node(&ray).unwrap().1, i);
+    assert_eq!(bvh.raycast_node(&ray).unwrap().0.t, 10.);
+}
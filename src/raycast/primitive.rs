@@ -1,8 +1,181 @@
 use std::any::Any;
 use std::fmt::Debug;
+use std::sync::Arc;
 
-use crate::raycast::{Raycast, bounds::Bounds3f};
+use crate::{
+    core::tensor::Vec3f,
+    raycast::{Hit, Ray, Raycast, Surface, bounds::Bounds3f},
+};
 
 pub trait Primitive: Raycast + Sync + Send + Debug + Any + Clone {
     fn bounds(&self) -> Bounds3f;
+
+    /// bounds of part of primitive inside clip, used by spatial split building.
+    /// must keep every hit point inside clip, default clips primitive bounds
+    fn clip_bounds(&self, clip: &Bounds3f) -> Bounds3f {
+        self.bounds().intersect(clip)
+    }
+
+    /// distance from p to primitive, used by point queries.
+    /// must not be less than distance to bounds, default is distance to bounds
+    fn distance(&self, p: Vec3f) -> f32 {
+        self.bounds().distance(p)
+    }
+
+    /// position, normal and uv where ray hit primitive, None if primitive has no surface
+    fn surface(&self, _ray: &Ray, _hit: &Hit) -> Option<Surface> {
+        None
+    }
+
+    /// infinite primitive with infinite bounds, traced next to the tree instead of left out
+    fn unbounded(&self) -> bool {
+        false
+    }
+}
+
+/// object safe Primitive, lets one accelerator hold different primitive kinds
+/// as Arc<dyn DynPrimitive>, e.g. gaussians with proxy triangles
+pub trait DynPrimitive: Raycast + Sync + Send + Debug + Any {
+    fn bounds(&self) -> Bounds3f;
+    fn clip_bounds(&self, clip: &Bounds3f) -> Bounds3f;
+    fn distance(&self, p: Vec3f) -> f32;
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Option<Surface>;
+    fn unbounded(&self) -> bool;
+}
+
+impl<T: Primitive> DynPrimitive for T {
+    fn bounds(&self) -> Bounds3f {
+        Primitive::bounds(self)
+    }
+
+    fn clip_bounds(&self, clip: &Bounds3f) -> Bounds3f {
+        Primitive::clip_bounds(self, clip)
+    }
+
+    fn distance(&self, p: Vec3f) -> f32 {
+        Primitive::distance(self, p)
+    }
+
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Option<Surface> {
+        Primitive::surface(self, ray, hit)
+    }
+
+    fn unbounded(&self) -> bool {
+        Primitive::unbounded(self)
+    }
+}
+
+impl dyn DynPrimitive {
+    /// concrete primitive behind trait object
+    pub fn downcast_ref<T: Primitive>(&self) -> Option<&T> {
+        (self as &dyn Any).downcast_ref()
+    }
+}
+
+impl Raycast for Arc<dyn DynPrimitive> {
+    fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        self.as_ref().raycast(ray)
+    }
+}
+
+impl Primitive for Arc<dyn DynPrimitive> {
+    fn bounds(&self) -> Bounds3f {
+        DynPrimitive::bounds(self.as_ref())
+    }
+
+    fn clip_bounds(&self, clip: &Bounds3f) -> Bounds3f {
+        DynPrimitive::clip_bounds(self.as_ref(), clip)
+    }
+
+    fn distance(&self, p: Vec3f) -> f32 {
+        DynPrimitive::distance(self.as_ref(), p)
+    }
+
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Option<Surface> {
+        DynPrimitive::surface(self.as_ref(), ray, hit)
+    }
+
+    fn unbounded(&self) -> bool {
+        DynPrimitive::unbounded(self.as_ref())
+    }
+}
+
+#[test]
+fn test_dyn_primitive() {
+    use crate::raycast::{
+        bruteforce::assert_matches_bruteforce, bvh::BVH, sbvh::SBVHBuilder, sphere::Sphere,
+        triangle::Triangle, widebvh::BVHWidth,
+    };
+    use crate::splat::{gaussian::Gaussian, io::RawGaussian};
+    use rand::Rng;
+
+    // splats with proxy spheres and triangles in one tree
+    let n = 1536;
+    let mut rng = rand::rng();
+    let mut random_point = || Vec3f::vec(std::array::from_fn(|_| rng.random_range(0.0..64.)));
+    let mut bvh: BVH<Arc<dyn DynPrimitive>> = BVH::new(n);
+    for i in 0..n {
+        let p = random_point();
+        match i % 3 {
+            0 => bvh.push(Arc::new(Sphere::new(p, 1.))),
+            1 => bvh.push(Arc::new(Triangle::new(
+                p,
+                p + Vec3f::vec([4., 0., 0.]),
+                p + Vec3f::vec([0., 4., 1.]),
+            ))),
+            _ => bvh.push(Arc::new(Gaussian::from_input(&RawGaussian {
+                pos: [p[0], p[1], p[2]],
+                scale: [0.5f32.ln(); 3],
+                rot: [1., 0., 0., 0.],
+                ..Default::default()
+            }))),
+        }
+    }
+    let rays: Vec<Ray> = (0..256)
+        .map(|_| {
+            let org = Vec3f::vec([-8., 0., 0.]) + random_point() * Vec3f::vec([0., 1., 1.]);
+            Ray::new(org, random_point() - org)
+        })
+        .collect();
+
+    let kinds = |prims: &[Arc<dyn DynPrimitive>]| {
+        let spheres = prims
+            .iter()
+            .filter(|p| p.downcast_ref::<Sphere>().is_some());
+        let triangles = prims
+            .iter()
+            .filter(|p| p.downcast_ref::<Triangle>().is_some());
+        (spheres.count(), triangles.count())
+    };
+    assert_eq!(kinds(&bvh.primitives), (n / 3, n / 3));
+
+    for refs in [false, true] {
+        if refs {
+            bvh.build_with(&SBVHBuilder::default(), 5);
+        } else {
+            bvh.build(5, true);
+        }
+        // reordered, not copied
+        assert_eq!(kinds(&bvh.primitives), (n / 3, n / 3));
+
+        for width in [BVHWidth::Binary, BVHWidth::Eight] {
+            bvh.collapse(width);
+            let what = format!("refs {refs} {width:?}");
+            let mut hits = [0; 3];
+            let found = assert_matches_bruteforce(&bvh, &bvh.primitives, &rays, &what);
+            for (_, i) in found.into_iter().flatten() {
+                let prim = bvh.primitives[i].as_ref();
+                let kind = if prim.downcast_ref::<Sphere>().is_some() {
+                    0
+                } else if prim.downcast_ref::<Triangle>().is_some() {
+                    1
+                } else {
+                    2
+                };
+                hits[kind] += 1;
+            }
+            // every kind is traced
+            assert!(hits.iter().all(|&h| h > 0), "{hits:?}");
+        }
+    }
 }
@@ -0,0 +1,514 @@
+use std::sync::Arc;
+
+use crate::raycast::{
+    bounds::Bounds3f,
+    bvhbuild::{
+        BVHBuildNode, BVHBuilder, BVHSplitBucket, BuildPrim, BuildTree, N_BUCKETS, PAR_BUILD_SIZE,
+        TRAVERSAL_COST, centroid_bounds, min_split_cost, split_equal_counts,
+    },
+    primitive::Primitive,
+};
+use rayon::prelude::*;
+
+/// planes tried per axis for spatial splits
+const N_SPATIAL_BINS: usize = 16;
+
+/// binned SAH with spatial splits, primitives overlapping a split plane are clipped
+/// and referenced from both children. leaves index BVH::prim_refs
+#[derive(Debug, Clone, Copy)]
+pub struct SBVHBuilder {
+    pub par_build: bool,
+    /// spatial splits tried when overlap of object split children
+    /// is larger than alpha * root area. 0 always tries, 1 almost never
+    pub alpha: f32,
+}
+
+impl Default for SBVHBuilder {
+    fn default() -> Self {
+        Self {
+            par_build: false,
+            alpha: 1e-5,
+        }
+    }
+}
+
+enum Split {
+    /// bucket index to split after on centroids
+    Object {
+        dim: usize,
+        cmin: f32,
+        cmax: f32,
+        bucket: usize,
+    },
+    Spatial {
+        dim: usize,
+        plane: f32,
+    },
+}
+
+struct SplitContext<'a, T: Primitive> {
+    primitives: &'a [T],
+    node_prims_limit: usize,
+    par_build: bool,
+    min_overlap: f32,
+}
+
+impl BVHBuilder for SBVHBuilder {
+    fn build_tree<T: Primitive>(&self, primitives: &[T], node_prims_limit: usize) -> BuildTree {
+        let refs: Vec<BuildPrim> = primitives
+            .par_iter()
+            .enumerate()
+            .map(|(index, p)| {
+                let bounds = p.bounds();
+                BuildPrim {
+                    index,
+                    bounds,
+                    centroid: bounds.centroid(),
+                }
+            })
+            .collect();
+
+        let root_bounds = refs
+            .iter()
+            .fold(Bounds3f::empty(), |acc, r| acc.union(r.bounds));
+
+        let ctx = SplitContext {
+            primitives,
+            node_prims_limit,
+            par_build: self.par_build,
+            min_overlap: self.alpha * root_bounds.area(),
+        };
+
+        let (mut root, total_nodes, prim_order) = build_recursive(&ctx, refs);
+        BVHBuildNode::assign_offsets(&mut root, &mut 0);
+
+        BuildTree {
+            root,
+            total_nodes,
+            prim_order,
+            references: true,
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("sbvh alpha {}", self.alpha)
+    }
+}
+
+/// returns root node of sub tree, created nodes num and primitive index of its leaf slots
+fn build_recursive<T: Primitive>(
+    ctx: &SplitContext<T>,
+    mut refs: Vec<BuildPrim>,
+) -> (Arc<BVHBuildNode>, usize, Vec<usize>) {
+    let n = refs.len();
+    let bounds = refs
+        .iter()
+        .fold(Bounds3f::empty(), |acc, r| acc.union(r.bounds));
+
+    let mut node = BVHBuildNode::default();
+    let children = if n > 1 {
+        split(ctx, &mut refs, &bounds)
+    } else {
+        None
+    };
+
+    let Some((dim, left, right)) = children else {
+        // offsets assigned after whole tree is built
+        node.init_leaf(0, n, bounds);
+        return (Arc::new(node), 1, refs.iter().map(|r| r.index).collect());
+    };
+
+    let ((c0, c0_created_nodes, c0_order), (c1, c1_created_nodes, mut c1_order)) =
+        if ctx.par_build && n > PAR_BUILD_SIZE {
+            rayon::join(
+                || build_recursive(ctx, left),
+                || build_recursive(ctx, right),
+            )
+        } else {
+            (build_recursive(ctx, left), build_recursive(ctx, right))
+        };
+
+    node.init_interior(dim, c0, c1);
+    let mut order = c0_order;
+    order.append(&mut c1_order);
+    (
+        Arc::new(node),
+        c0_created_nodes + c1_created_nodes + 1,
+        order,
+    )
+}
+
+/// returns split axis and references of both children, None for leaf
+fn split<T: Primitive>(
+    ctx: &SplitContext<T>,
+    refs: &mut [BuildPrim],
+    bounds: &Bounds3f,
+) -> Option<(usize, Vec<BuildPrim>, Vec<BuildPrim>)> {
+    let n = refs.len();
+
+    let mut best: Option<(f32, Split)> = None;
+    let mut overlap = f32::INFINITY;
+    if let Some((cost, split, object_overlap)) = object_split(refs, bounds) {
+        best = Some((cost, split));
+        overlap = object_overlap;
+    }
+
+    // children of object split overlap too much, try planes cutting references
+    if overlap > ctx.min_overlap
+        && let Some((cost, split)) = spatial_split(ctx, refs, bounds)
+        && best.as_ref().is_none_or(|(c, _)| cost < *c)
+    {
+        best = Some((cost, split));
+    }
+
+    if let Some((cost, _)) = &best
+        && n < ctx.node_prims_limit
+        && n as f32 <= *cost
+    {
+        return None;
+    }
+
+    let (left, right, dim) = match best {
+        Some((
+            _,
+            Split::Object {
+                dim,
+                cmin,
+                cmax,
+                bucket,
+            },
+        )) => {
+            let (left, right) = refs
+                .iter()
+                .partition(|r| bucket_of(r.centroid[dim], cmin, cmax) <= bucket);
+            (left, right, dim)
+        }
+        Some((_, Split::Spatial { dim, plane })) => {
+            let (left, right) = partition_spatial(ctx, refs, dim, plane);
+            (left, right, dim)
+        }
+        None => (Vec::new(), Vec::new(), 0),
+    };
+
+    // no split separates references, fall back to equal counts
+    if left.is_empty() || right.is_empty() || left.len() == n || right.len() == n {
+        if n < ctx.node_prims_limit {
+            return None;
+        }
+        let dim = centroid_bounds(refs).max_dim();
+        let mid = split_equal_counts(refs, dim);
+        return Some((dim, refs[..mid].to_vec(), refs[mid..].to_vec()));
+    }
+
+    Some((dim, left, right))
+}
+
+fn bucket_of(c: f32, cmin: f32, cmax: f32) -> usize {
+    ((((c - cmin) / (cmax - cmin)) * N_BUCKETS as f32) as usize).min(N_BUCKETS - 1)
+}
+
+/// binned SAH on centroids, returns cost, split and overlapped area of children
+fn object_split(refs: &[BuildPrim], bounds: &Bounds3f) -> Option<(f32, Split, f32)> {
+    let centroid_bounds = centroid_bounds(refs);
+    let dim = centroid_bounds.max_dim();
+    let (cmin, cmax) = (centroid_bounds.min[dim], centroid_bounds.max[dim]);
+    // all centroids at same place, buckets can not separate them
+    if cmax <= cmin {
+        return None;
+    }
+
+    let mut buckets = [BVHSplitBucket::empty(); N_BUCKETS];
+    refs.iter().for_each(|r| {
+        let b = bucket_of(r.centroid[dim], cmin, cmax);
+        buckets[b].count += 1;
+        buckets[b].bounds = buckets[b].bounds.union(r.bounds);
+    });
+
+    let (bucket, cost) = min_split_cost(&buckets, bounds);
+
+    let union = |bs: &[BVHSplitBucket]| {
+        bs.iter()
+            .fold(Bounds3f::empty(), |acc, b| acc.union(b.bounds))
+    };
+    let overlap = union(&buckets[..=bucket]).intersect(&union(&buckets[bucket + 1..]));
+    let overlap = if overlap.is_empty() {
+        0.
+    } else {
+        overlap.area()
+    };
+
+    let split = Split::Object {
+        dim,
+        cmin,
+        cmax,
+        bucket,
+    };
+    Some((cost, split, overlap))
+}
+
+/// bounds cut by plane on dim, left keeps part below plane
+fn cut(bounds: &Bounds3f, dim: usize, plane: f32, left: bool) -> Bounds3f {
+    let mut b = *bounds;
+    if left {
+        b.max[dim] = b.max[dim].min(plane);
+    } else {
+        b.min[dim] = b.min[dim].max(plane);
+    }
+    b
+}
+
+/// clipped bounds of reference inside clip, empty if nothing left
+fn clip_ref<T: Primitive>(ctx: &SplitContext<T>, r: &BuildPrim, clip: &Bounds3f) -> Bounds3f {
+    let clip = r.bounds.intersect(clip);
+    if clip.is_empty() {
+        return clip;
+    }
+    ctx.primitives[r.index].clip_bounds(&clip)
+}
+
+/// binned planes evenly placed in node bounds on every axis, returns cheapest one
+fn spatial_split<T: Primitive>(
+    ctx: &SplitContext<T>,
+    refs: &[BuildPrim],
+    bounds: &Bounds3f,
+) -> Option<(f32, Split)> {
+    let n = refs.len();
+    let area = bounds.area();
+    let mut best: Option<(f32, Split)> = None;
+
+    for dim in 0..3 {
+        let (bmin, bmax) = (bounds.min[dim], bounds.max[dim]);
+        if bmax <= bmin {
+            continue;
+        }
+
+        let extent = bmax - bmin;
+        let plane = |i: usize| {
+            if i == N_SPATIAL_BINS {
+                bmax
+            } else {
+                bmin + extent * i as f32 / N_SPATIAL_BINS as f32
+            }
+        };
+        let bin_of = |x: f32| {
+            ((((x - bmin) / extent) * N_SPATIAL_BINS as f32) as usize).min(N_SPATIAL_BINS - 1)
+        };
+
+        let mut bins = [Bounds3f::empty(); N_SPATIAL_BINS];
+        let mut entries = [0usize; N_SPATIAL_BINS];
+        let mut exits = [0usize; N_SPATIAL_BINS];
+
+        refs.iter().for_each(|r| {
+            let (b0, b1) = (bin_of(r.bounds.min[dim]), bin_of(r.bounds.max[dim]));
+            entries[b0] += 1;
+            exits[b1] += 1;
+            for (b, bin) in bins.iter_mut().enumerate().take(b1 + 1).skip(b0) {
+                let slab = cut(
+                    &cut(&r.bounds, dim, plane(b), false),
+                    dim,
+                    plane(b + 1),
+                    true,
+                );
+                let clipped = clip_ref(ctx, r, &slab);
+                if !clipped.is_empty() {
+                    *bin = bin.union(clipped);
+                }
+            }
+        });
+
+        // sweep from both sides, plane i+1 splits after bin i
+        let mut right = [(Bounds3f::empty(), 0usize); N_SPATIAL_BINS];
+        let (mut rb, mut rc) = (Bounds3f::empty(), 0);
+        for i in (1..N_SPATIAL_BINS).rev() {
+            rb = rb.union(bins[i]);
+            rc += exits[i];
+            right[i] = (rb, rc);
+        }
+
+        let (mut lb, mut lc) = (Bounds3f::empty(), 0);
+        for i in 0..N_SPATIAL_BINS - 1 {
+            lb = lb.union(bins[i]);
+            lc += entries[i];
+            let (rb, rc) = right[i + 1];
+            // children must shrink or building does not terminate
+            if lc == 0 || rc == 0 || lc >= n || rc >= n {
+                continue;
+            }
+
+            let cost = TRAVERSAL_COST + (lc as f32 * lb.area() + rc as f32 * rb.area()) / area;
+            if best.as_ref().is_none_or(|(c, _)| cost < *c) {
+                let split = Split::Spatial {
+                    dim,
+                    plane: plane(i + 1),
+                };
+                best = Some((cost, split));
+            }
+        }
+    }
+
+    best
+}
+
+/// references straddling plane are clipped into both children,
+/// or kept whole on one side if that is cheaper
+fn partition_spatial<T: Primitive>(
+    ctx: &SplitContext<T>,
+    refs: &[BuildPrim],
+    dim: usize,
+    plane: f32,
+) -> (Vec<BuildPrim>, Vec<BuildPrim>) {
+    let mut left = Vec::with_capacity(refs.len());
+    let mut right = Vec::with_capacity(refs.len());
+    let mut straddled = Vec::new();
+
+    refs.iter().for_each(|r| {
+        if r.bounds.max[dim] <= plane {
+            left.push(*r);
+        } else if r.bounds.min[dim] >= plane {
+            right.push(*r);
+        } else {
+            straddled.push(*r);
+        }
+    });
+
+    let side_bounds = |side: &[BuildPrim]| {
+        side.iter()
+            .fold(Bounds3f::empty(), |acc, r| acc.union(r.bounds))
+    };
+    let area = |b: Bounds3f| if b.is_empty() { 0. } else { b.area() };
+    let (lb, rb) = (side_bounds(&left), side_bounds(&right));
+    let (lc, rc) = (
+        (left.len() + straddled.len()) as f32,
+        (right.len() + straddled.len()) as f32,
+    );
+
+    for r in straddled {
+        let to_left = BuildPrim::clipped(
+            r.index,
+            clip_ref(ctx, &r, &cut(&r.bounds, dim, plane, true)),
+        );
+        let to_right = BuildPrim::clipped(
+            r.index,
+            clip_ref(ctx, &r, &cut(&r.bounds, dim, plane, false)),
+        );
+
+        // unsplit reference if whole reference on one side costs less
+        let (lb_split, rb_split) = (
+            to_left.map_or(lb, |p| lb.union(p.bounds)),
+            to_right.map_or(rb, |p| rb.union(p.bounds)),
+        );
+        let split_cost = area(lb_split) * lc + area(rb_split) * rc;
+        let left_cost = area(lb.union(r.bounds)) * lc + area(rb_split) * (rc - 1.);
+        let right_cost = area(lb_split) * (lc - 1.) + area(rb.union(r.bounds)) * rc;
+
+        if left_cost < split_cost && left_cost <= right_cost {
+            left.push(r);
+        } else if right_cost < split_cost {
+            right.push(r);
+        } else {
+            left.extend(to_left);
+            right.extend(to_right);
+        }
+    }
+
+    (left, right)
+}
+
+impl BuildPrim {
+    /// None if clipped away
+    fn clipped(index: usize, bounds: Bounds3f) -> Option<BuildPrim> {
+        (!bounds.is_empty()).then(|| BuildPrim {
+            index,
+            bounds,
+            centroid: bounds.centroid(),
+        })
+    }
+}
+
+#[test]
+fn test_sbvh() {
+    use crate::core::tensor::Vec3f;
+    use crate::raycast::{Ray, Raycast, bvh::BVH, bvhbuild::SAHBuilder, widebvh::BVHWidth};
+    use crate::splat::{gaussian::Gaussian, io::RawGaussian};
+    use rand::Rng;
+
+    // long thin gaussians crossing each other, object splits can not separate them
+    let n = 2048;
+    let node_limit = 5;
+    let mut rng = rand::rng();
+    let mut bvh = BVH::new(n);
+    for i in 0..n {
+        // half along x, half along y
+        let rot = if i % 2 == 0 {
+            [1., 0., 0., 0.]
+        } else {
+            [0.5f32.sqrt(), 0., 0., 0.5f32.sqrt()]
+        };
+        let raw = RawGaussian {
+            pos: std::array::from_fn(|_| rng.random_range(0.0..64.)),
+            scale: [16f32.ln(), 0.1f32.ln(), 0.1f32.ln()],
+            rot,
+            ..Default::default()
+        };
+        bvh.push(Gaussian::from_input(&raw));
+    }
+
+    let rays: Vec<Ray> = (0..512)
+        .map(|_| {
+            let org = Vec3f::vec([-8., rng.random_range(0.0..64.), rng.random_range(0.0..64.)]);
+            let target = Vec3f::vec([72., rng.random_range(0.0..64.), rng.random_range(0.0..64.)]);
+            Ray::new(org, target - org)
+        })
+        .collect();
+
+    bvh.build_with(&SAHBuilder { par_build: false }, node_limit);
+    let sah_cost = bvh.sah_cost();
+    bvh.build_with(&SBVHBuilder::default(), node_limit);
+    let sbvh_cost = bvh.sah_cost();
+    println!(
+        "sah cost {}, sbvh cost {}, {} references",
+        sah_cost,
+        sbvh_cost,
+        bvh.prim_refs.len()
+    );
+    assert!(bvh.prim_refs.len() >= n);
+    assert!(sbvh_cost < sah_cost);
+
+    // primitives not reordered, every primitive referenced
+    let mut referenced = vec![false; n];
+    bvh.prim_refs.iter().for_each(|&i| referenced[i] = true);
+    assert!(referenced.iter().all(|&r| r));
+
+    // all hit primitives and nearest t of every ray
+    let expected: Vec<(Vec<usize>, Option<f32>)> = rays
+        .iter()
+        .map(|r| {
+            let hits: Vec<(usize, f32)> = bvh
+                .primitives
+                .iter()
+                .enumerate()
+                .filter_map(|(i, p)| p.raycast(r).map(|h| (i, h.t)))
+                .collect();
+            let nearest = hits.iter().map(|h| h.1).reduce(f32::min);
+            (hits.iter().map(|h| h.0).collect(), nearest)
+        })
+        .collect();
+
+    for width in [BVHWidth::Binary, BVHWidth::Four] {
+        bvh.collapse(width);
+        rays.iter()
+            .zip(expected.iter())
+            .for_each(|(r, (hits, nearest))| {
+                assert_eq!(bvh.raycast(r).map(|h| h.t), *nearest);
+
+                // every hit reported once though primitive is referenced by several leaves
+                let mut reported = Vec::new();
+                bvh.any_raycast(r, |_, _, i| {
+                    reported.push(i);
+                    true
+                });
+                reported.sort();
+                assert_eq!(reported, *hits);
+            });
+    }
+}
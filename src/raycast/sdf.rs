@@ -0,0 +1,297 @@
+use std::{fmt::Debug, sync::Arc};
+
+use crate::{
+    core::{tensor::Vec3f, tsrmath::TensorMath, vec::Vector},
+    raycast::{bounds::Bounds3f, primitive::Primitive, *},
+};
+
+/// signed distance of sphere of radius r at origin, negative inside
+pub fn sd_sphere(p: Vec3f, r: f32) -> f32 {
+    p.norm() - r
+}
+
+/// signed distance of box with half extents half at origin
+pub fn sd_box(p: Vec3f, half: Vec3f) -> f32 {
+    let q = Vec3f::vec(std::array::from_fn(|i| p[i].abs() - half[i]));
+    let outside = q.max(Vec3f::vec([0.; 3])).norm();
+    let inside = q[0].max(q[1]).max(q[2]).min(0.);
+    outside + inside
+}
+
+/// signed distance of torus around y axis, ring radius major, tube radius minor
+pub fn sd_torus(p: Vec3f, major: f32, minor: f32) -> f32 {
+    let ring = (p[0] * p[0] + p[2] * p[2]).sqrt() - major;
+    (ring * ring + p[1] * p[1]).sqrt() - minor
+}
+
+/// distances sampled at vertices of a regular grid over bounds, x varies fastest
+#[derive(Debug, Clone)]
+pub struct SdfGrid {
+    pub bounds: Bounds3f,
+    /// vertices along each axis, at least 2
+    pub res: [usize; 3],
+    pub data: Vec<f32>,
+}
+
+impl SdfGrid {
+    /// samples f at every vertex
+    pub fn bake<F: Fn(Vec3f) -> f32>(bounds: Bounds3f, res: [usize; 3], f: F) -> SdfGrid {
+        let res = res.map(|n| n.max(2));
+        let d = bounds.diagonal();
+        let mut data = Vec::with_capacity(res[0] * res[1] * res[2]);
+        for z in 0..res[2] {
+            for y in 0..res[1] {
+                for x in 0..res[0] {
+                    let idx = [x, y, z];
+                    let p = Vec3f::vec(std::array::from_fn(|i| {
+                        bounds.min[i] + d[i] * idx[i] as f32 / (res[i] - 1) as f32
+                    }));
+                    data.push(f(p));
+                }
+            }
+        }
+        SdfGrid { bounds, res, data }
+    }
+
+    /// trilinear interpolation, p is clamped into bounds
+    pub fn sample(&self, p: Vec3f) -> f32 {
+        let o = self.bounds.offset(p);
+        let mut cell = [0; 3];
+        let mut frac = [0.; 3];
+        for i in 0..3 {
+            let x = (o[i].clamp(0., 1.) * (self.res[i] - 1) as f32).min((self.res[i] - 1) as f32);
+            cell[i] = (x as usize).min(self.res[i] - 2);
+            frac[i] = x - cell[i] as f32;
+        }
+
+        let at = |dx: usize, dy: usize, dz: usize| {
+            let (x, y, z) = (cell[0] + dx, cell[1] + dy, cell[2] + dz);
+            self.data[(z * self.res[1] + y) * self.res[0] + x]
+        };
+        let lerp = |a: f32, b: f32, t: f32| a * (1. - t) + b * t;
+        let [fx, fy, fz] = frac;
+        lerp(
+            lerp(
+                lerp(at(0, 0, 0), at(1, 0, 0), fx),
+                lerp(at(0, 1, 0), at(1, 1, 0), fx),
+                fy,
+            ),
+            lerp(
+                lerp(at(0, 0, 1), at(1, 0, 1), fx),
+                lerp(at(0, 1, 1), at(1, 1, 1), fx),
+                fy,
+            ),
+            fz,
+        )
+    }
+}
+
+/// surface where signed distance field is zero inside bounds, found by sphere tracing
+#[derive(Clone)]
+pub struct Sdf {
+    pub bounds: Bounds3f,
+    field: Arc<dyn Fn(Vec3f) -> f32 + Sync + Send>,
+    /// field changes at most lipschitz per unit length, steps are divided by it
+    pub lipschitz: f32,
+    /// distance counted as surface, also central difference step of normals
+    pub eps: f32,
+    pub max_steps: usize,
+}
+
+impl Sdf {
+    /// f must be negative inside and 1-lipschitz, as an exact distance is.
+    /// surface must lie inside bounds
+    pub fn new<F>(bounds: Bounds3f, f: F) -> Sdf
+    where
+        F: Fn(Vec3f) -> f32 + Sync + Send + 'static,
+    {
+        Sdf {
+            bounds,
+            field: Arc::new(f),
+            lipschitz: 1.,
+            eps: 1e-4 * bounds.diagonal().norm(),
+            max_steps: 256,
+        }
+    }
+
+    /// trilinear interpolation of exact distances may be steeper by sqrt(3)
+    pub fn from_grid(grid: SdfGrid) -> Sdf {
+        let bounds = grid.bounds;
+        let grid = Arc::new(grid);
+        let mut sdf = Sdf::new(bounds, move |p| grid.sample(p));
+        sdf.lipschitz = 3f32.sqrt();
+        sdf
+    }
+
+    pub fn eval(&self, p: Vec3f) -> f32 {
+        (self.field)(p)
+    }
+
+    /// gradient by central differences
+    pub fn normal(&self, p: Vec3f) -> Vec3f {
+        let h = self.eps;
+        let g = Vec3f::vec(std::array::from_fn(|i| {
+            let mut e = Vec3f::vec([0.; 3]);
+            e[i] = h;
+            self.eval(p + e) - self.eval(p - e)
+        }));
+        g.normalize()
+    }
+}
+
+impl Raycast for Sdf {
+    fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        let (t0, t1) = self.bounds.raycast_inv(&InvRay::new(ray), ray.t_max)?;
+        let dir_len = ray.dir.norm();
+        if dir_len == 0. {
+            return None;
+        }
+
+        // march on |f|, origin inside surface finds the way out
+        let mut t = t0;
+        let sign = self.eval(ray.org + ray.dir * t).signum();
+        for _ in 0..self.max_steps {
+            let d = sign * self.eval(ray.org + ray.dir * t);
+            if d < self.eps {
+                return (t <= ray.t_max).then_some(Hit::new(t));
+            }
+            // no surface within d / lipschitz of p
+            t += d / (self.lipschitz * dir_len);
+            if t > t1 {
+                return None;
+            }
+        }
+        None
+    }
+}
+
+impl Debug for Sdf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "sdf {:?} lipschitz {}", self.bounds, self.lipschitz)
+    }
+}
+
+impl Primitive for Sdf {
+    fn bounds(&self) -> Bounds3f {
+        self.bounds
+    }
+
+    /// lower bound, exact for exact fields inside bounds.
+    /// outside, nearest point q of bounds is no farther from surface than p
+    fn distance(&self, p: Vec3f) -> f32 {
+        let q = p.max(self.bounds.min).min(self.bounds.max);
+        let inner = (self.eval(q) / self.lipschitz).max(0.);
+        let outer = (p - q).norm();
+        (inner * inner + outer * outer).sqrt()
+    }
+
+    /// uv is not parameterized, always 0
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Option<Surface> {
+        let p = hit.position(ray);
+        Some(Surface {
+            p,
+            n: self.normal(p),
+            uv: [0., 0.],
+            bary: None,
+        })
+    }
+}
+
+#[test]
+fn test_sdf() {
+    use crate::raycast::{
+        bruteforce::{BruteForce, assert_matches_bruteforce},
+        bvh::BVH,
+        sphere::Sphere,
+    };
+    use rand::Rng;
+
+    let cube = |c: Vec3f, r: f32| Bounds3f::new(c - r, c + r);
+    let cnt = Vec3f::vec([0., 0., -4.]);
+    let sdf = Sdf::new(cube(cnt, 1.), move |p| sd_sphere(p - cnt, 1.));
+    let sphere = Sphere::new(cnt, 1.);
+
+    // same hits as analytic sphere
+    let mut rng = rand::rng();
+    for _ in 0..256 {
+        let target = cnt + Vec3f::vec(std::array::from_fn(|_| rng.random_range(-1.2..1.2)));
+        let ray = Ray::new(Vec3f::vec([0.; 3]), target);
+        let (hit, expected) = (sdf.raycast(&ray), sphere.raycast(&ray));
+        // grazing rays stop within eps of surface, but far along the ray
+        if let (Some(h), Some(e)) = (hit, expected) {
+            let s = sphere.surface(&ray, &e).unwrap();
+            let n = sdf.surface(&ray, &h).unwrap().n;
+            assert!(((h.position(&ray) - cnt).norm() - 1.).abs() < 1e-3);
+            if s.n.dot(ray.dir.normalize()).abs() > 0.2 {
+                assert!((h.t - e.t).abs() * target.norm() < 1e-2);
+                assert!((n - s.n).norm() < 1e-2);
+            }
+        } else if let Some(e) = expected.or(hit) {
+            let p = e.position(&ray) - cnt;
+            assert!((p.norm() - 1.).abs() < 1e-3 && (p.dot(ray.dir.normalize()).abs() < 0.2));
+        }
+    }
+
+    // inside leaves through surface, t_max respected, hole of torus missed
+    let ray = Ray::new(cnt, Vec3f::vec([1., 0., 0.]));
+    assert!((sdf.raycast(&ray).unwrap().t - 1.).abs() < 1e-3);
+    assert!(
+        sdf.raycast(&Ray::segment(Vec3f::vec([0.; 3]), cnt, 0.7))
+            .is_none()
+    );
+    let torus = Sdf::new(cube(cnt, 2.), move |p| sd_torus(p - cnt, 1.5, 0.25));
+    let down = Vec3f::vec([0., -1., 0.]);
+    assert!(
+        torus
+            .raycast(&Ray::new(cnt + Vec3f::vec([0., 5., 0.]), down))
+            .is_none()
+    );
+    let ray = Ray::new(cnt + Vec3f::vec([1.5, 5., 0.]), down);
+    let hit = torus.raycast(&ray).unwrap();
+    assert!((hit.t - 4.75).abs() < 1e-3);
+    assert!((torus.surface(&ray, &hit).unwrap().n - down * -1.).norm() < 1e-2);
+
+    // baked box keeps flat faces
+    let half = Vec3f::vec([1., 0.5, 0.5]);
+    let grid = SdfGrid::bake(cube(Vec3f::vec([0.; 3]), 1.5), [33; 3], |p| sd_box(p, half));
+    let baked = Sdf::from_grid(grid);
+    for i in 0..9 {
+        let x = i as f32 / 8. * 1.6 - 0.8;
+        let ray = Ray::new(Vec3f::vec([x, 0.2, 3.]), Vec3f::vec([0., 0., -1.]));
+        let hit = baked.raycast(&ray).unwrap();
+        assert!((hit.t - 2.5).abs() < 1e-2, "{x} {}", hit.t);
+        let n = baked.surface(&ray, &hit).unwrap().n;
+        assert!(n[2] > 0.99);
+    }
+    let ray = Ray::new(Vec3f::vec([1.2, 0., 3.]), Vec3f::vec([0., 0., -1.]));
+    assert!(baked.raycast(&ray).is_none());
+    // lower bound between bounds and true distance
+    let d = baked.distance(Vec3f::vec([0., 0., 3.]));
+    assert!((1.5..=2.5).contains(&d));
+    assert!((sd_box(Vec3f::vec([2., 1.5, 0.]), half) - 2f32.sqrt()).abs() < 1e-6);
+
+    // in one tree with brute force reference
+    let mut bvh = BVH::new(64);
+    for i in 0..64 {
+        let c = Vec3f::vec([(i % 8) as f32 * 3., (i / 8) as f32 * 3., 0.]);
+        let r = rng.random_range(0.5..1.2);
+        if i % 2 == 0 {
+            bvh.push(Sdf::new(cube(c, r), move |p| sd_sphere(p - c, r)));
+        } else {
+            let half = Vec3f::vec([r, r * 0.5, r * 0.8]);
+            bvh.push(Sdf::new(cube(c, r), move |p| sd_box(p - c, half)));
+        }
+    }
+    bvh.build(4, false);
+    let rays: Vec<Ray> = (0..256)
+        .map(|_| {
+            let org = Vec3f::vec([rng.random_range(-2.0..23.), rng.random_range(-2.0..23.), 8.]);
+            Ray::new(org, Vec3f::vec([0.1, -0.2, -1.]))
+        })
+        .collect();
+    assert_matches_bruteforce(&bvh, &bvh.primitives, &rays, "sdf");
+    let mut oracle = BruteForce::new(64);
+    bvh.primitives.iter().for_each(|p| oracle.push(p.clone()));
+    let p = Vec3f::vec([1.5, 1.5, 4.]);
+    assert_eq!(bvh.closest_point(p), oracle.closest_point(p));
+}
@@ -1,7 +1,11 @@
 use std::fmt::Debug;
 
 use crate::{
-    core::{spherical::xyz2spherical, tensor::Vec3f, vec::Vector},
+    core::{
+        spherical::{azimuth_u, xyz2spherical},
+        tensor::Vec3f,
+        vec::Vector,
+    },
     raycast::{bounds::Bounds3f, primitive::Primitive, *},
 };
 
@@ -29,16 +33,24 @@ impl Sphere {
         let a = ray_dir.dot(ray_dir);
         let b = op.dot(ray_dir);
         let c = op.dot(op) - self.r * self.r;
-        let det = b * b - c * a;
+        // b*b - c*a cancels for grazing rays, same value from distance of line to center
+        let l = (op - ray_dir * (b / a)).norm();
+        let det = a * (self.r - l) * (self.r + l);
         if det < 0. {
-            None
+            return None;
+        }
+        // roots without cancellation, q has sign of -b
+        let q = -b - b.signum() * det.sqrt();
+        if q == 0. {
+            return None;
+        }
+        let (t0, t1) = (c / q, q / a);
+        let (t0, t1) = (t0.min(t1), t0.max(t1));
+        if t0 >= 0. {
+            Some(t0)
+        } else if t1 >= 0. {
+            Some(t1)
         } else {
-            let det = det.sqrt();
-            if -b - det >= 0. {
-                return Some((-b - det) / a);
-            } else if -b + det >= 0. {
-                return Some((-b + det) / a);
-            }
             None
         }
     }
@@ -46,10 +58,11 @@ impl Sphere {
 
 impl Raycast for Sphere {
     fn raycast(&self, ray: &Ray) -> Option<Hit> {
-        if let Some(t) = self.intersect(ray.org, ray.dir) {
-            return Some(Hit { t });
+        match self.intersect(ray.org, ray.dir) {
+            // limited by ray segment, traversal relies on it to keep nearest hit
+            Some(t) if t <= ray.t_max => Some(Hit::new(t)),
+            _ => None,
         }
-        None
     }
 }
 
@@ -66,6 +79,23 @@ impl Primitive for Sphere {
         let max = self.cnt + r;
         Bounds3f { min, max }
     }
+
+    fn distance(&self, p: Vec3f) -> f32 {
+        ((p - self.cnt).norm() - self.r).max(0.)
+    }
+
+    /// uv is azimuth and polar angle of worldpos2sphere, both mapped to [0,1]
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Option<Surface> {
+        let p = hit.position(ray);
+        let n = (p - self.cnt).normalize();
+        let theta = n[1].clamp(-1., 1.).acos();
+        Some(Surface {
+            p,
+            n,
+            uv: [azimuth_u(n), theta / std::f32::consts::PI],
+            bary: None,
+        })
+    }
 }
 
 #[test]
@@ -84,6 +114,12 @@ fn test_sphere() {
         let hit = s.raycast(&ray).unwrap();
         assert_eq!(hit.position(&ray)[0], x);
         assert_eq!(hit.position(&ray)[1], y);
+
+        let surf = s.surface(&ray, &hit).unwrap();
+        assert!((surf.n - Vec3f::vec([x, y, z])).norm() < 1e-6);
+        // x axis is a quarter turn from z except at pole, polar angle from y
+        assert!((surf.uv[0] - 0.25).abs() < 1e-6 || y == 1.);
+        assert!((surf.uv[1] - y.acos() / std::f32::consts::PI).abs() < 1e-6);
     }
 
     let b = s.bounds();
@@ -0,0 +1,55 @@
+/// stack of nodes to visit, inline storage covers common tree depths,
+/// deeper trees spill to heap instead of overflowing
+pub(crate) struct TraversalStack<T: Copy + Default, const N: usize = 64> {
+    inline: [T; N],
+    len: usize,
+    spill: Vec<T>,
+}
+
+impl<T: Copy + Default, const N: usize> TraversalStack<T, N> {
+    pub fn new() -> Self {
+        TraversalStack {
+            inline: [T::default(); N],
+            len: 0,
+            spill: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn push(&mut self, v: T) {
+        if self.len < N {
+            self.inline[self.len] = v;
+        } else {
+            self.spill.push(v);
+        }
+        self.len += 1;
+    }
+
+    #[inline]
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.len -= 1;
+        if self.len < N {
+            Some(self.inline[self.len])
+        } else {
+            self.spill.pop()
+        }
+    }
+}
+
+#[test]
+fn test_traversal_stack() {
+    let mut stack: TraversalStack<usize, 4> = TraversalStack::new();
+    (0..10).for_each(|i| stack.push(i));
+    for i in (5..10).rev() {
+        assert_eq!(stack.pop(), Some(i));
+    }
+    (20..30).for_each(|i| stack.push(i));
+    for i in (20..30).rev().chain((0..5).rev()) {
+        assert_eq!(stack.pop(), Some(i));
+    }
+    assert_eq!(stack.pop(), None);
+}
@@ -0,0 +1,243 @@
+use std::fmt::Debug;
+
+use crate::{
+    core::{math::gamma, tensor::Vec3f, vec::Vector},
+    raycast::{bounds::Bounds3f, primitive::Primitive, *},
+};
+
+#[derive(Clone)]
+pub struct Triangle {
+    pub p: [Vec3f; 3],
+}
+
+impl Triangle {
+    pub fn new(p0: Vec3f, p1: Vec3f, p2: Vec3f) -> Triangle {
+        Triangle { p: [p0, p1, p2] }
+    }
+
+    /// point of triangle nearest to p
+    pub fn closest_point(&self, p: Vec3f) -> Vec3f {
+        // voronoi regions of vertices, edges and face, Ericson 5.1.5
+        let [a, b, c] = self.p;
+        let (ab, ac, ap) = (b - a, c - a, p - a);
+        let (d1, d2) = (ab.dot(ap), ac.dot(ap));
+        if d1 <= 0. && d2 <= 0. {
+            return a;
+        }
+
+        let bp = p - b;
+        let (d3, d4) = (ab.dot(bp), ac.dot(bp));
+        if d3 >= 0. && d4 <= d3 {
+            return b;
+        }
+
+        let vc = d1 * d4 - d3 * d2;
+        if vc <= 0. && d1 >= 0. && d3 <= 0. {
+            return a + ab * (d1 / (d1 - d3));
+        }
+
+        let cp = p - c;
+        let (d5, d6) = (ab.dot(cp), ac.dot(cp));
+        if d6 >= 0. && d5 <= d6 {
+            return c;
+        }
+
+        let vb = d5 * d2 - d1 * d6;
+        if vb <= 0. && d2 >= 0. && d6 <= 0. {
+            return a + ac * (d2 / (d2 - d6));
+        }
+
+        let va = d3 * d6 - d5 * d4;
+        if va <= 0. && d4 - d3 >= 0. && d5 - d6 >= 0. {
+            return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+        }
+
+        let denom = va + vb + vc;
+        if denom == 0. {
+            // degenerate triangle, all edge regions were rejected by rounding
+            return a;
+        }
+        a + ab * (vb / denom) + ac * (vc / denom)
+    }
+}
+
+impl Raycast for Triangle {
+    fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        // Möller–Trumbore, barycentric u, v and t from Cramer's rule
+        let [p0, p1, p2] = self.p;
+        let (e1, e2) = (p1 - p0, p2 - p0);
+        let pvec = ray.dir.cross(e2);
+        let det = e1.dot(pvec);
+        // ray parallel to triangle plane
+        if det == 0. {
+            return None;
+        }
+        let inv_det = 1. / det;
+
+        let tvec = ray.org - p0;
+        let u = tvec.dot(pvec) * inv_det;
+        if !(0. ..=1.).contains(&u) {
+            return None;
+        }
+
+        let qvec = tvec.cross(e1);
+        let v = ray.dir.dot(qvec) * inv_det;
+        if v < 0. || u + v > 1. {
+            return None;
+        }
+
+        let t = e2.dot(qvec) * inv_det;
+        if t >= 0. && t <= ray.t_max {
+            Some(Hit::new(t))
+        } else {
+            None
+        }
+    }
+}
+
+impl Debug for Triangle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.p[0], self.p[1], self.p[2])
+    }
+}
+
+impl Primitive for Triangle {
+    fn bounds(&self) -> Bounds3f {
+        let [p0, p1, p2] = self.p;
+        Bounds3f::new(p0, p0).enlarge(p1).enlarge(p2)
+    }
+
+    /// bounds of triangle clipped by box, Sutherland–Hodgman against its six planes
+    fn clip_bounds(&self, clip: &Bounds3f) -> Bounds3f {
+        let mut poly: Vec<Vec3f> = self.p.to_vec();
+        for axis in 0..3 {
+            for (plane, keep_above) in [(clip.min[axis], true), (clip.max[axis], false)] {
+                let inside = |v: &Vec3f| {
+                    if keep_above {
+                        v[axis] >= plane
+                    } else {
+                        v[axis] <= plane
+                    }
+                };
+
+                let mut clipped = Vec::with_capacity(poly.len() + 1);
+                for (i, &cur) in poly.iter().enumerate() {
+                    let prev = poly[(i + poly.len() - 1) % poly.len()];
+                    if inside(&cur) != inside(&prev) {
+                        let t = (plane - prev[axis]) / (cur[axis] - prev[axis]);
+                        let mut v = prev + (cur - prev) * t;
+                        // exactly on plane, rounding must not move it outside
+                        v[axis] = plane;
+                        clipped.push(v);
+                    }
+                    if inside(&cur) {
+                        clipped.push(cur);
+                    }
+                }
+                poly = clipped;
+                if poly.is_empty() {
+                    return Bounds3f::empty();
+                }
+            }
+        }
+
+        let b = poly.iter().fold(Bounds3f::empty(), |b, &v| b.enlarge(v));
+        // pad rounding error of clipped vertices, part of triangle inside clip stays inside clip
+        let max_abs = (0..3).fold(0f32, |m, i| m.max(b.min[i].abs()).max(b.max[i].abs()));
+        let pad = Vec3f::vec([gamma(3) * max_abs; 3]);
+        Bounds3f::new(b.min - pad, b.max + pad).intersect(clip)
+    }
+
+    fn distance(&self, p: Vec3f) -> f32 {
+        (p - self.closest_point(p)).norm()
+    }
+
+    /// normal by winding p0, p1, p2, uv are barycentric weights of p1 and p2
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Option<Surface> {
+        let p = hit.position(ray);
+        let [p0, p1, p2] = self.p;
+        let (e1, e2, d) = (p1 - p0, p2 - p0, p - p0);
+        let (d11, d12, d22) = (e1.dot(e1), e1.dot(e2), e2.dot(e2));
+        let (d1, d2) = (d.dot(e1), d.dot(e2));
+        let denom = d11 * d22 - d12 * d12;
+        let uv = if denom == 0. {
+            [0., 0.]
+        } else {
+            [(d22 * d1 - d12 * d2) / denom, (d11 * d2 - d12 * d1) / denom]
+        };
+        Some(Surface {
+            p,
+            n: e1.cross(e2).normalize(),
+            uv,
+            bary: Some([1. - uv[0] - uv[1], uv[0], uv[1]]),
+        })
+    }
+}
+
+#[test]
+fn test_triangle() {
+    let tri = Triangle::new(
+        Vec3f::vec([0., 0., 0.]),
+        Vec3f::vec([1., 0., 0.]),
+        Vec3f::vec([0., 1., 0.]),
+    );
+
+    let hit = tri.raycast(&Ray::new(
+        Vec3f::vec([0.25, 0.25, 1.]),
+        Vec3f::vec([0., 0., -1.]),
+    ));
+    assert_eq!(hit.map(|h| h.t), Some(1.));
+    let ray = Ray::new(Vec3f::vec([0.25, 0.5, 1.]), Vec3f::vec([0., 0., -1.]));
+    let s = tri.surface(&ray, &tri.raycast(&ray).unwrap()).unwrap();
+    assert_eq!(s.n, Vec3f::vec([0., 0., 1.]));
+    assert_eq!(s.uv, [0.25, 0.5]);
+    // back side is hit too
+    let hit = tri.raycast(&Ray::new(
+        Vec3f::vec([0.25, 0.25, -2.]),
+        Vec3f::vec([0., 0., 1.]),
+    ));
+    assert_eq!(hit.map(|h| h.t), Some(2.));
+    // outside edge, behind origin, beyond segment, parallel
+    let down = Vec3f::vec([0., 0., -1.]);
+    assert!(
+        tri.raycast(&Ray::new(Vec3f::vec([0.75, 0.75, 1.]), down))
+            .is_none()
+    );
+    assert!(
+        tri.raycast(&Ray::new(Vec3f::vec([0.25, 0.25, -1.]), down))
+            .is_none()
+    );
+    assert!(
+        tri.raycast(&Ray::segment(Vec3f::vec([0.25, 0.25, 1.]), down, 0.5))
+            .is_none()
+    );
+    assert!(
+        tri.raycast(&Ray::new(
+            Vec3f::vec([-1., 0.25, 0.]),
+            Vec3f::vec([1., 0., 0.])
+        ))
+        .is_none()
+    );
+
+    assert_eq!(tri.distance(Vec3f::vec([0.25, 0.25, 2.])), 2.);
+    assert_eq!(tri.distance(Vec3f::vec([-3., -4., 0.])), 5.);
+    assert_eq!(tri.distance(Vec3f::vec([0.5, -1., 0.])), 1.);
+    assert_eq!(tri.distance(Vec3f::vec([1., 1., 0.])), 0.5f32.sqrt());
+    assert!(
+        tri.distance(Vec3f::vec([2., 0.5, 1.])) >= tri.bounds().distance(Vec3f::vec([2., 0.5, 1.]))
+    );
+
+    let b = tri.bounds();
+    assert_eq!(
+        b,
+        Bounds3f::new(Vec3f::vec([0.; 3]), Vec3f::vec([1., 1., 0.]))
+    );
+    // corner of bounds off triangle is cut away
+    let clip = Bounds3f::new(Vec3f::vec([0.5, 0., -1.]), Vec3f::vec([2., 2., 1.]));
+    let c = tri.clip_bounds(&clip);
+    assert!((c.max[1] - 0.5).abs() < 1e-5);
+    assert_eq!(c.min[0], 0.5);
+    assert!((c.max[0] - 1.).abs() < 1e-5);
+    let clip = Bounds3f::new(Vec3f::vec([0.8, 0.8, -1.]), Vec3f::vec([2., 2., 1.]));
+    assert!(tri.clip_bounds(&clip).is_empty());
+}
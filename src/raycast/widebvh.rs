@@ -0,0 +1,332 @@
+use crate::{
+    core::math::gamma,
+    raycast::{
+        bounds::Bounds3f, bvh::BVH, bvhstats::TraversalCounter, primitive::Primitive,
+        stack::TraversalStack, *,
+    },
+};
+
+/// wide node of binary node not recorded
+pub(crate) const NO_WIDE: usize = usize::MAX;
+
+/// branching factor of traversed bvh
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BVHWidth {
+    #[default]
+    Binary,
+    Four,
+    Eight,
+}
+
+/// collapsed nodes, empty when traversing binary nodes
+#[derive(Debug, Default, Clone)]
+pub enum WideNodes {
+    #[default]
+    None,
+    Four(Vec<WideBVHNode<4>>),
+    Eight(Vec<WideBVHNode<8>>),
+}
+
+impl WideNodes {
+    pub fn width(&self) -> BVHWidth {
+        match self {
+            WideNodes::None => BVHWidth::Binary,
+            WideNodes::Four(_) => BVHWidth::Four,
+            WideNodes::Eight(_) => BVHWidth::Eight,
+        }
+    }
+}
+
+/// W children per node, child bounds stored as SoA so all lanes are tested in one slab pass
+#[derive(Debug, Clone, Copy)]
+pub struct WideBVHNode<const W: usize> {
+    /// [axis][lane]
+    pub min: [[f32; W]; 3],
+    pub max: [[f32; W]; 3],
+    /// wide node index or primitive offset
+    pub offset: [usize; W],
+    /// leaf child if > 0
+    pub nprimitives: [usize; W],
+    /// lanes after nchildren are empty
+    pub nchildren: usize,
+}
+
+impl<const W: usize> Default for WideBVHNode<W> {
+    fn default() -> Self {
+        Self {
+            min: [[f32::INFINITY; W]; 3],
+            max: [[f32::NEG_INFINITY; W]; 3],
+            offset: [0; W],
+            nprimitives: [0; W],
+            nchildren: 0,
+        }
+    }
+}
+
+impl<const W: usize> WideBVHNode<W> {
+    /// entry t of every lane, INFINITY if lane missed
+    pub fn intersect(&self, ray: &InvRay, t_max: f32) -> [f32; W] {
+        let mut t0 = [0f32; W];
+        let mut t1 = [t_max; W];
+        // same conservative bound as Bounds3f::raycast_inv
+        let robust = 1. + 2. * gamma(3);
+        for a in 0..3 {
+            let slabs = [&self.min[a], &self.max[a]];
+            let (near, far) = (slabs[ray.neg[a]], slabs[1 - ray.neg[a]]);
+            for k in 0..W {
+                let tnear = (near[k] - ray.org[a]) * ray.inv_dir[a];
+                let tfar = (far[k] - ray.org[a]) * ray.inv_dir[a] * robust;
+                // f32::max and f32::min drop NaN, as the scalar test does
+                t0[k] = t0[k].max(tnear);
+                t1[k] = t1[k].min(tfar);
+            }
+        }
+
+        std::array::from_fn(|k| {
+            if k < self.nchildren && t0[k] <= t1[k] {
+                t0[k]
+            } else {
+                f32::INFINITY
+            }
+        })
+    }
+
+    pub fn lane_bounds(&self, k: usize) -> Bounds3f {
+        Bounds3f::new(
+            Vec3f::vec([self.min[0][k], self.min[1][k], self.min[2][k]]),
+            Vec3f::vec([self.max[0][k], self.max[1][k], self.max[2][k]]),
+        )
+    }
+}
+
+impl<T: Primitive> BVH<T> {
+    /// build binary bvh then collapse it to given width
+    pub fn build_wide(&mut self, node_prims_limit: usize, par_build: bool, width: BVHWidth) {
+        self.build(node_prims_limit, par_build);
+        self.collapse(width);
+    }
+
+    /// collapse built binary nodes into wide nodes used by traversal,
+    /// Binary drops collapsed nodes
+    pub fn collapse(&mut self, width: BVHWidth) {
+        // wide nodes recorded by updates are replaced
+        self.update.wide_root.clear();
+        self.wide = if self.nodes.is_empty() {
+            WideNodes::None
+        } else {
+            match width {
+                BVHWidth::Binary => WideNodes::None,
+                BVHWidth::Four => WideNodes::Four(self.collapse_nodes::<4>(&mut [])),
+                BVHWidth::Eight => WideNodes::Eight(self.collapse_nodes::<8>(&mut [])),
+            }
+        };
+    }
+
+    /// roots: see collapse_node
+    pub(crate) fn collapse_nodes<const W: usize>(
+        &self,
+        roots: &mut [usize],
+    ) -> Vec<WideBVHNode<W>> {
+        let mut wide = Vec::with_capacity(self.nodes.len() / (W - 1) + 1);
+        self.collapse_node(&mut wide, 0, None, roots);
+        wide
+    }
+
+    /// returns index of created wide node, written at given index or appended.
+    /// roots: wide node of every binary node, recorded for created nodes and reused
+    /// unless NO_WIDE. empty to neither record nor reuse
+    pub(crate) fn collapse_node<const W: usize>(
+        &self,
+        wide: &mut Vec<WideBVHNode<W>>,
+        node_i: usize,
+        at: Option<usize>,
+        roots: &mut [usize],
+    ) -> usize {
+        let node = &self.nodes[node_i];
+        let mut children: Vec<usize> = if node.is_leaf() {
+            vec![node_i]
+        } else {
+            vec![node_i + 1, node.offset]
+        };
+
+        // open interior child with largest area until lanes are full
+        while children.len() < W {
+            let largest = children
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| !self.nodes[**c].is_leaf())
+                .max_by(|(_, a), (_, b)| {
+                    let (a, b) = (self.nodes[**a].bounds.area(), self.nodes[**b].bounds.area());
+                    a.total_cmp(&b)
+                });
+
+            let Some((k, &c)) = largest else {
+                break;
+            };
+            children[k] = c + 1;
+            children.push(self.nodes[c].offset);
+        }
+
+        let wide_i = at.unwrap_or_else(|| {
+            wide.push(WideBVHNode::default());
+            wide.len() - 1
+        });
+
+        let mut wnode = WideBVHNode::<W> {
+            nchildren: children.len(),
+            ..Default::default()
+        };
+
+        for (k, &c) in children.iter().enumerate() {
+            let cnode = &self.nodes[c];
+            for a in 0..3 {
+                wnode.min[a][k] = cnode.bounds.min[a];
+                wnode.max[a][k] = cnode.bounds.max[a];
+            }
+
+            if cnode.is_leaf() {
+                wnode.offset[k] = cnode.offset;
+                wnode.nprimitives[k] = cnode.nprimitives;
+            } else {
+                wnode.offset[k] = match roots.get(c) {
+                    Some(&w) if w != NO_WIDE => w,
+                    _ => self.collapse_node(wide, c, None, roots),
+                };
+            }
+        }
+
+        wide[wide_i] = wnode;
+        if let Some(root) = roots.get_mut(node_i) {
+            *root = wide_i;
+        }
+        wide_i
+    }
+
+    /// ordered traversal over wide nodes, children visited near to far.
+    /// unique: report referenced primitive once, see first_report.
+    /// first: return first accepted hit instead of nearest
+    /// F (ray,hit, primitve index) -> if skip
+    pub(crate) fn raycast_wide<const W: usize, F, C>(
+        &self,
+        nodes: &[WideBVHNode<W>],
+        ray: &Ray,
+        unique: bool,
+        first: bool,
+        mut anyhit: F,
+        counter: &mut C,
+    ) -> Option<(Hit, usize)>
+    where
+        F: FnMut(&Ray, Hit, usize) -> bool,
+        C: TraversalCounter,
+    {
+        let mut hit: Option<(Hit, usize)> = None;
+        if nodes.is_empty() {
+            return hit;
+        }
+
+        let ray = &mut ray.clone();
+        let inv = InvRay::new(ray);
+
+        // (entry t, wide node, lane) of children to visit
+        let mut nodes_to_visit: TraversalStack<(f32, usize, usize)> = TraversalStack::new();
+        let mut open = Some(0);
+        let mut reported = Vec::new();
+
+        loop {
+            if let Some(node_i) = open.take() {
+                let node = &nodes[node_i];
+                counter.node();
+                let ts = node.intersect(&inv, ray.t_max);
+
+                // push far lanes first so nearest lane is popped next
+                let mut lanes: [usize; W] = std::array::from_fn(|k| k);
+                lanes[..node.nchildren].sort_unstable_by(|a, b| ts[*b].total_cmp(&ts[*a]));
+                for &k in lanes.iter().take(node.nchildren) {
+                    if ts[k] < f32::INFINITY {
+                        nodes_to_visit.push((ts[k], node_i, k));
+                    }
+                }
+            }
+
+            let Some((t, node_i, k)) = nodes_to_visit.pop() else {
+                break;
+            };
+
+            // nearer primitive found after this entry was pushed
+            if t > ray.t_max {
+                continue;
+            }
+
+            let node = &nodes[node_i];
+            if node.nprimitives[k] == 0 {
+                open = Some(node.offset[k]);
+                continue;
+            }
+
+            for slot in node.offset[k]..node.offset[k] + node.nprimitives[k] {
+                let prim_i = self.prim_index(slot);
+                counter.prim();
+                if let Some(hit_p) = self.primitives[prim_i].raycast(ray)
+                    && (!unique || self.first_report(&mut reported, prim_i))
+                    && !anyhit(ray, hit_p, prim_i)
+                {
+                    ray.t_max = hit_p.t;
+                    hit = Some((hit_p, prim_i));
+                    if first {
+                        return hit;
+                    }
+                }
+            }
+        }
+
+        hit
+    }
+}
+
+#[test]
+fn test_wide_bvh() {
+    use crate::raycast::sphere::Sphere;
+    use rand::Rng;
+    use std::time::Instant;
+
+    let n = 512;
+    let mut rng = rand::rng();
+    let mut bvh = BVH::new(n);
+    for _ in 0..n {
+        let cnt = Vec3f::vec([
+            rng.random_range(0.0..64.),
+            rng.random_range(0.0..64.),
+            rng.random_range(0.0..64.),
+        ]);
+        bvh.push(Sphere::new(cnt, rng.random_range(0.1..1.)));
+    }
+
+    let rays: Vec<Ray> = (0..1024)
+        .map(|_| {
+            let org = Vec3f::vec([-8., rng.random_range(0.0..64.), rng.random_range(0.0..64.)]);
+            let target = Vec3f::vec([72., rng.random_range(0.0..64.), rng.random_range(0.0..64.)]);
+            Ray::new(org, target - org)
+        })
+        .collect();
+
+    bvh.build(17, true);
+    let expected: Vec<Option<f32>> = rays.iter().map(|r| bvh.raycast(r).map(|h| h.t)).collect();
+
+    for width in [BVHWidth::Four, BVHWidth::Eight] {
+        bvh.collapse(width);
+        assert_eq!(bvh.wide.width(), width);
+
+        let sw = Instant::now();
+        rays.iter().zip(expected.iter()).for_each(|(r, e)| {
+            assert_eq!(bvh.raycast(r).map(|h| h.t), *e);
+        });
+        println!(
+            "raycast 1024 times, {:?} wide bvh, {}ms",
+            width,
+            sw.elapsed().as_millis()
+        );
+    }
+
+    bvh.collapse(BVHWidth::Binary);
+    assert_eq!(bvh.wide.width(), BVHWidth::Binary);
+}
@@ -0,0 +1,610 @@
+use std::{f32::consts::PI, fmt::Debug, sync::Arc};
+
+use crate::{
+    core::{sampling::sample_cos_hemisphere, tensor::Vec3f, vec::Vector},
+    render::microfacet::TrowbridgeReitz,
+};
+
+/// direction picked by Bsdf::sample
+#[derive(Debug, Clone, Copy)]
+pub struct BsdfSample {
+    pub wi: Vec3f,
+    pub f: Vec3f,
+    /// solid angle density, or probability of lobe if specular
+    pub pdf: f32,
+    /// dirac lobe, eval and pdf never see wi
+    pub specular: bool,
+}
+
+/// scattering at a surface point. directions are unit vectors in local frame,
+/// z is surface normal pointing out of object, wo points away from surface
+pub trait Bsdf: Sync + Send + Debug {
+    /// scattered radiance towards wo per irradiance from wi, 0 for dirac lobes
+    fn eval(&self, wo: Vec3f, wi: Vec3f) -> Vec3f;
+
+    /// uc picks lobe, u picks direction inside lobe, both in [0,1)
+    fn sample(&self, wo: Vec3f, uc: f32, u: [f32; 2]) -> Option<BsdfSample>;
+
+    /// density of sample picking wi, 0 for dirac lobes
+    fn pdf(&self, wo: Vec3f, wi: Vec3f) -> f32;
+
+    /// hemispherical directional reflectance towards wo, averaged over samples of uc and u
+    fn rho(&self, wo: Vec3f, uc: &[f32], u: &[[f32; 2]]) -> Vec3f {
+        let total = uc
+            .iter()
+            .zip(u)
+            .filter_map(|(&uc, &u)| self.sample(wo, uc, u))
+            .fold(Vec3f::vec([0.; 3]), |acc, s| {
+                acc + s.f * (s.wi[2].abs() / s.pdf)
+            });
+        total / uc.len().max(1) as f32
+    }
+}
+
+fn same_hemisphere(a: Vec3f, b: Vec3f) -> bool {
+    a[2] * b[2] > 0.
+}
+
+fn reflect(wo: Vec3f, n: Vec3f) -> Vec3f {
+    n * (2. * wo.dot(n)) - wo
+}
+
+/// wi bent through surface with normal n, eta is inside over outside index.
+/// returns relative eta along the way, None on total internal reflection
+fn refract(wi: Vec3f, n: Vec3f, eta: f32) -> Option<(Vec3f, f32)> {
+    let mut cos_i = n.dot(wi);
+    let (n, eta) = if cos_i < 0. {
+        cos_i = -cos_i;
+        (n * -1., 1. / eta)
+    } else {
+        (n, eta)
+    };
+
+    let sin2_i = (1. - cos_i * cos_i).max(0.);
+    let sin2_t = sin2_i / (eta * eta);
+    if sin2_t >= 1. {
+        return None;
+    }
+    let cos_t = (1. - sin2_t).sqrt();
+    Some((wi * (-1. / eta) + n * (cos_i / eta - cos_t), eta))
+}
+
+/// unpolarized reflectance of dielectric, cos_i negative from inside
+pub fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let mut cos_i = cos_i.clamp(-1., 1.);
+    let mut eta = eta;
+    if cos_i < 0. {
+        eta = 1. / eta;
+        cos_i = -cos_i;
+    }
+
+    let sin2_t = (1. - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1. {
+        return 1.;
+    }
+    let cos_t = (1. - sin2_t).max(0.).sqrt();
+    let r_parl = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perp = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parl * r_parl + r_perp * r_perp) / 2.
+}
+
+/// complex number for conductor fresnel
+#[derive(Clone, Copy)]
+struct Complex(f32, f32);
+
+impl Complex {
+    fn add(self, o: Complex) -> Complex {
+        Complex(self.0 + o.0, self.1 + o.1)
+    }
+
+    fn sub(self, o: Complex) -> Complex {
+        Complex(self.0 - o.0, self.1 - o.1)
+    }
+
+    fn mul(self, o: Complex) -> Complex {
+        Complex(self.0 * o.0 - self.1 * o.1, self.0 * o.1 + self.1 * o.0)
+    }
+
+    fn div(self, o: Complex) -> Complex {
+        let d = o.0 * o.0 + o.1 * o.1;
+        Complex(
+            (self.0 * o.0 + self.1 * o.1) / d,
+            (self.1 * o.0 - self.0 * o.1) / d,
+        )
+    }
+
+    fn norm(self) -> f32 {
+        self.0 * self.0 + self.1 * self.1
+    }
+
+    fn sqrt(self) -> Complex {
+        let n = self.norm().sqrt();
+        if n == 0. {
+            return Complex(0., 0.);
+        }
+        let t1 = (0.5 * (n + self.0.abs())).sqrt();
+        let t2 = 0.5 * self.1 / t1;
+        if self.0 >= 0. {
+            Complex(t1, t2)
+        } else {
+            Complex(t2.abs(), t1.copysign(self.1))
+        }
+    }
+}
+
+/// reflectance of conductor with index eta + ik per channel
+pub fn fresnel_complex(cos_i: f32, eta: Vec3f, k: Vec3f) -> Vec3f {
+    let cos_i = cos_i.clamp(0., 1.);
+    Vec3f::vec(std::array::from_fn(|c| {
+        let eta = Complex(eta[c], k[c]);
+        let cos = Complex(cos_i, 0.);
+        let sin2_i = Complex(1. - cos_i * cos_i, 0.);
+        let sin2_t = sin2_i.div(eta.mul(eta));
+        let cos_t = Complex(1., 0.).sub(sin2_t).sqrt();
+
+        let r_parl = eta.mul(cos).sub(cos_t).div(eta.mul(cos).add(cos_t));
+        let r_perp = cos.sub(eta.mul(cos_t)).div(cos.add(eta.mul(cos_t)));
+        (r_parl.norm() + r_perp.norm()) / 2.
+    }))
+}
+
+/// ideal diffuse reflection on both sides
+#[derive(Debug, Clone)]
+pub struct Lambertian {
+    pub albedo: Vec3f,
+}
+
+impl Lambertian {
+    pub fn new(albedo: Vec3f) -> Self {
+        Lambertian { albedo }
+    }
+}
+
+impl Bsdf for Lambertian {
+    fn eval(&self, wo: Vec3f, wi: Vec3f) -> Vec3f {
+        if !same_hemisphere(wo, wi) {
+            return Vec3f::vec([0.; 3]);
+        }
+        self.albedo / PI
+    }
+
+    fn sample(&self, wo: Vec3f, _uc: f32, u: [f32; 2]) -> Option<BsdfSample> {
+        let mut wi = Vec3f::vec(sample_cos_hemisphere(u));
+        // rounding lands rare samples on the horizon, kept above it so no path is lost
+        wi[2] = wi[2].max(1e-6);
+        if wo[2] < 0. {
+            wi[2] = -wi[2];
+        }
+        let pdf = self.pdf(wo, wi);
+        (pdf > 0.).then_some(BsdfSample {
+            wi,
+            f: self.eval(wo, wi),
+            pdf,
+            specular: false,
+        })
+    }
+
+    fn pdf(&self, wo: Vec3f, wi: Vec3f) -> f32 {
+        if !same_hemisphere(wo, wi) {
+            return 0.;
+        }
+        wi[2].abs() / PI
+    }
+}
+
+/// metal, mirror if alpha is about 0 else GGX microfacets
+#[derive(Debug, Clone)]
+pub struct Conductor {
+    pub eta: Vec3f,
+    pub k: Vec3f,
+    pub distr: TrowbridgeReitz,
+}
+
+impl Conductor {
+    pub fn new(eta: Vec3f, k: Vec3f, alpha: f32) -> Self {
+        Conductor {
+            eta,
+            k,
+            distr: TrowbridgeReitz::new(alpha),
+        }
+    }
+}
+
+impl Bsdf for Conductor {
+    fn eval(&self, wo: Vec3f, wi: Vec3f) -> Vec3f {
+        let zero = Vec3f::vec([0.; 3]);
+        if !same_hemisphere(wo, wi) || self.distr.effectively_smooth() {
+            return zero;
+        }
+        let (cos_o, cos_i) = (wo[2].abs(), wi[2].abs());
+        let wm = wi + wo;
+        if cos_o == 0. || cos_i == 0. || wm.sqrnorm() == 0. {
+            return zero;
+        }
+        let wm = wm.normalize();
+        let f = fresnel_complex(wo.dot(wm).abs(), self.eta, self.k);
+        f * (self.distr.d(wm) * self.distr.g(wo, wi) / (4. * cos_i * cos_o))
+    }
+
+    fn sample(&self, wo: Vec3f, _uc: f32, u: [f32; 2]) -> Option<BsdfSample> {
+        if self.distr.effectively_smooth() {
+            let wi = Vec3f::vec([-wo[0], -wo[1], wo[2]]);
+            let cos = wi[2].abs();
+            if cos == 0. {
+                return None;
+            }
+            return Some(BsdfSample {
+                wi,
+                f: fresnel_complex(cos, self.eta, self.k) / cos,
+                pdf: 1.,
+                specular: true,
+            });
+        }
+
+        if wo[2] == 0. {
+            return None;
+        }
+        let wm = self.distr.sample_wm(wo, u);
+        let wi = reflect(wo, wm);
+        if !same_hemisphere(wo, wi) {
+            return None;
+        }
+        let pdf = self.distr.d_visible(wo, wm) / (4. * wo.dot(wm).abs());
+        (pdf > 0.).then_some(BsdfSample {
+            wi,
+            f: self.eval(wo, wi),
+            pdf,
+            specular: false,
+        })
+    }
+
+    fn pdf(&self, wo: Vec3f, wi: Vec3f) -> f32 {
+        if !same_hemisphere(wo, wi) || self.distr.effectively_smooth() {
+            return 0.;
+        }
+        let wm = wo + wi;
+        if wm.sqrnorm() == 0. {
+            return 0.;
+        }
+        let wm = wm.normalize();
+        let wm = if wm[2] < 0. { wm * -1. } else { wm };
+        self.distr.d_visible(wo, wm) / (4. * wo.dot(wm).abs())
+    }
+}
+
+/// glass, mirror and refraction if alpha is about 0 else GGX microfacets.
+/// transmitted radiance is not scaled by eta^2, the factors cancel for
+/// paths entering and leaving a closed object
+#[derive(Debug, Clone)]
+pub struct Dielectric {
+    /// index inside over index outside
+    pub eta: f32,
+    pub distr: TrowbridgeReitz,
+}
+
+impl Dielectric {
+    pub fn new(eta: f32, alpha: f32) -> Self {
+        Dielectric {
+            eta,
+            distr: TrowbridgeReitz::new(alpha),
+        }
+    }
+
+    /// relative eta and facing microfacet normal of wo, wi pair.
+    /// None if normal is degenerate or a microfacet faces away from wo or wi
+    fn half_vector(&self, wo: Vec3f, wi: Vec3f) -> Option<(f32, Vec3f)> {
+        let (cos_o, cos_i) = (wo[2], wi[2]);
+        if cos_o == 0. || cos_i == 0. {
+            return None;
+        }
+        let etap = match (same_hemisphere(wo, wi), cos_o > 0.) {
+            (true, _) => 1.,
+            (false, true) => self.eta,
+            (false, false) => 1. / self.eta,
+        };
+        let wm = wi * etap + wo;
+        if wm.sqrnorm() == 0. {
+            return None;
+        }
+        let wm = wm.normalize();
+        let wm = if wm[2] < 0. { wm * -1. } else { wm };
+        if wm.dot(wi) * cos_i < 0. || wm.dot(wo) * cos_o < 0. {
+            return None;
+        }
+        Some((etap, wm))
+    }
+}
+
+impl Bsdf for Dielectric {
+    fn eval(&self, wo: Vec3f, wi: Vec3f) -> Vec3f {
+        let zero = Vec3f::vec([0.; 3]);
+        if self.eta == 1. || self.distr.effectively_smooth() {
+            return zero;
+        }
+        let Some((etap, wm)) = self.half_vector(wo, wi) else {
+            return zero;
+        };
+
+        let (cos_o, cos_i) = (wo[2], wi[2]);
+        let fr = fresnel_dielectric(wo.dot(wm), self.eta);
+        let dg = self.distr.d(wm) * self.distr.g(wo, wi);
+        let f = if same_hemisphere(wo, wi) {
+            dg * fr / (4. * cos_i * cos_o).abs()
+        } else {
+            let denom = wi.dot(wm) + wo.dot(wm) / etap;
+            dg * (1. - fr) * (wi.dot(wm) * wo.dot(wm) / (cos_i * cos_o * denom * denom)).abs()
+        };
+        Vec3f::vec([f; 3])
+    }
+
+    fn sample(&self, wo: Vec3f, uc: f32, u: [f32; 2]) -> Option<BsdfSample> {
+        if self.eta == 1. || self.distr.effectively_smooth() {
+            let r = fresnel_dielectric(wo[2], self.eta);
+            let t = 1. - r;
+            if uc < r / (r + t) {
+                let wi = Vec3f::vec([-wo[0], -wo[1], wo[2]]);
+                return Some(BsdfSample {
+                    wi,
+                    f: Vec3f::vec([r / wi[2].abs(); 3]),
+                    pdf: r / (r + t),
+                    specular: true,
+                });
+            }
+            let (wi, _) = refract(wo, Vec3f::vec([0., 0., 1.]), self.eta)?;
+            if wi[2] == 0. {
+                return None;
+            }
+            return Some(BsdfSample {
+                wi,
+                f: Vec3f::vec([t / wi[2].abs(); 3]),
+                pdf: t / (r + t),
+                specular: true,
+            });
+        }
+
+        if wo[2] == 0. {
+            return None;
+        }
+        let wm = self.distr.sample_wm(wo, u);
+        let r = fresnel_dielectric(wo.dot(wm), self.eta);
+        let t = 1. - r;
+        let wi = if uc < r / (r + t) {
+            let wi = reflect(wo, wm);
+            if !same_hemisphere(wo, wi) {
+                return None;
+            }
+            wi
+        } else {
+            let (wi, _) = refract(wo, wm, self.eta)?;
+            if same_hemisphere(wo, wi) || wi[2] == 0. {
+                return None;
+            }
+            wi
+        };
+        let pdf = self.pdf(wo, wi);
+        (pdf > 0.).then_some(BsdfSample {
+            wi,
+            f: self.eval(wo, wi),
+            pdf,
+            specular: false,
+        })
+    }
+
+    fn pdf(&self, wo: Vec3f, wi: Vec3f) -> f32 {
+        if self.eta == 1. || self.distr.effectively_smooth() {
+            return 0.;
+        }
+        let Some((etap, wm)) = self.half_vector(wo, wi) else {
+            return 0.;
+        };
+
+        let r = fresnel_dielectric(wo.dot(wm), self.eta);
+        let t = 1. - r;
+        let d = self.distr.d_visible(wo, wm);
+        if same_hemisphere(wo, wi) {
+            d / (4. * wo.dot(wm).abs()) * r / (r + t)
+        } else {
+            let denom = wi.dot(wm) + wo.dot(wm) / etap;
+            d * wi.dot(wm).abs() / (denom * denom) * t / (r + t)
+        }
+    }
+}
+
+/// blend of two bsdfs, b weighted by t
+#[derive(Debug, Clone)]
+pub struct Mix {
+    pub a: Arc<dyn Bsdf>,
+    pub b: Arc<dyn Bsdf>,
+    pub t: f32,
+}
+
+impl Mix {
+    pub fn new(a: Arc<dyn Bsdf>, b: Arc<dyn Bsdf>, t: f32) -> Self {
+        Mix {
+            a,
+            b,
+            t: t.clamp(0., 1.),
+        }
+    }
+}
+
+impl Bsdf for Mix {
+    fn eval(&self, wo: Vec3f, wi: Vec3f) -> Vec3f {
+        self.a.eval(wo, wi) * (1. - self.t) + self.b.eval(wo, wi) * self.t
+    }
+
+    fn sample(&self, wo: Vec3f, uc: f32, u: [f32; 2]) -> Option<BsdfSample> {
+        // uc picks bsdf, remapped to [0,1) for picking lobe inside it
+        let (bsdf, w, uc) = if uc < 1. - self.t {
+            (&self.a, 1. - self.t, uc / (1. - self.t))
+        } else {
+            (&self.b, self.t, (uc - (1. - self.t)) / self.t)
+        };
+        let mut s = bsdf.sample(wo, uc.min(1. - f32::EPSILON), u)?;
+        if s.specular {
+            // other bsdf has no density on dirac direction
+            s.f = s.f * w;
+            s.pdf *= w;
+            return Some(s);
+        }
+        s.f = self.eval(wo, s.wi);
+        s.pdf = self.pdf(wo, s.wi);
+        (s.pdf > 0.).then_some(s)
+    }
+
+    fn pdf(&self, wo: Vec3f, wi: Vec3f) -> f32 {
+        self.a.pdf(wo, wi) * (1. - self.t) + self.b.pdf(wo, wi) * self.t
+    }
+}
+
+#[test]
+fn test_bsdf() {
+    use crate::core::sampling::{radical_inverse, square2unitsphere};
+
+    let gold = (
+        Vec3f::vec([0.143, 0.374, 1.442]),
+        Vec3f::vec([3.983, 2.385, 1.603]),
+    );
+    let bsdfs: Vec<(Arc<dyn Bsdf>, bool)> = vec![
+        (Arc::new(Lambertian::new(Vec3f::vec([1.; 3]))), false),
+        (Arc::new(Conductor::new(gold.0, gold.1, 0.)), true),
+        (Arc::new(Conductor::new(gold.0, gold.1, 0.3)), false),
+        (Arc::new(Conductor::new(gold.0, gold.1, 0.8)), false),
+        (Arc::new(Dielectric::new(1.5, 0.)), true),
+        (Arc::new(Dielectric::new(1.5, 0.3)), false),
+        (Arc::new(Dielectric::new(1.33, 0.7)), false),
+        (
+            Arc::new(Mix::new(
+                Arc::new(Lambertian::new(Vec3f::vec([0.8, 0.2, 0.2]))),
+                Arc::new(Conductor::new(gold.0, gold.1, 0.4)),
+                0.5,
+            )),
+            false,
+        ),
+        (
+            Arc::new(Mix::new(
+                Arc::new(Lambertian::new(Vec3f::vec([0.5; 3]))),
+                Arc::new(Dielectric::new(1.5, 0.)),
+                0.3,
+            )),
+            true,
+        ),
+    ];
+    let dirs = [
+        Vec3f::vec([0., 0., 1.]),
+        Vec3f::vec([0.6, 0., 0.8]),
+        Vec3f::vec([0.3, -0.8, 0.2]).normalize(),
+        Vec3f::vec([-0.2, 0.3, -0.9]).normalize(),
+    ];
+    // halton points, stratified in 3 dimensions
+    let halton = |i: usize| {
+        (
+            radical_inverse(i, 0),
+            [radical_inverse(i, 1), radical_inverse(i, 2)],
+        )
+    };
+    let n = 256;
+    let sphere = |i: usize| {
+        let p = [(i % n) as f32 + 0.5, (i / n) as f32 + 0.5].map(|v| v / n as f32);
+        Vec3f::vec(square2unitsphere(p))
+    };
+    let da = 4. * PI / (n * n) as f32;
+
+    for (k, (bsdf, dirac)) in bsdfs.iter().enumerate() {
+        for &wo in dirs.iter() {
+            // albedo by importance sampling never above 1
+            let m = 16384;
+            let (mut albedo, mut valid) = (Vec3f::vec([0.; 3]), 0);
+            for i in 0..m {
+                let (uc, u) = halton(i);
+                if let Some(s) = bsdf.sample(wo, uc, u) {
+                    valid += 1;
+                    assert!((s.wi.norm() - 1.).abs() < 1e-3, "{k} {:?}", s.wi);
+                    assert!(s.pdf > 0. && s.f.dot(s.f).is_finite());
+                    albedo = albedo + s.f * (s.wi[2].abs() / s.pdf);
+                    if !s.specular {
+                        let f = bsdf.eval(wo, s.wi);
+                        let pdf = bsdf.pdf(wo, s.wi);
+                        assert!(
+                            (f - s.f).norm() <= 1e-3 * (1. + f.norm()),
+                            "{k} {f:?} {:?}",
+                            s.f
+                        );
+                        assert!((pdf - s.pdf).abs() <= 1e-3 * (1. + pdf), "{k}");
+                    }
+                }
+            }
+            let albedo = albedo / m as f32;
+            assert!((0..3).all(|c| albedo[c] <= 1.01), "{k} {wo:?} {albedo:?}");
+
+            if *dirac {
+                continue;
+            }
+            // sampled and integrated albedo agree.
+            // density integrates to share of samples not lost below surface
+            let (mut integral, mut total) = (Vec3f::vec([0.; 3]), 0.);
+            for i in 0..n * n {
+                let wi = sphere(i);
+                integral = integral + bsdf.eval(wo, wi) * (wi[2].abs() * da);
+                total += bsdf.pdf(wo, wi) * da;
+            }
+            assert!(
+                (integral - albedo).norm() < 0.03,
+                "{k} {wo:?} {integral:?} {albedo:?}"
+            );
+            let valid = valid as f32 / m as f32;
+            assert!((total - valid).abs() < 0.03, "{k} {wo:?} {total} {valid}");
+        }
+    }
+
+    // energy conserving lobes
+    let white = &bsdfs[0].0;
+    for &wo in dirs.iter() {
+        let mut albedo = 0.;
+        for i in 0..1024 {
+            let (uc, u) = halton(i);
+            let s = white.sample(wo, uc, u).unwrap();
+            albedo += s.f[0] * s.wi[2].abs() / s.pdf;
+        }
+        assert!((albedo / 1024. - 1.).abs() < 1e-4);
+        // smooth glass splits all energy into reflection and refraction
+        let glass = &bsdfs[4].0;
+        let (mut albedo, mut refracted) = (0., 0);
+        for i in 0..1024 {
+            let (uc, u) = halton(i);
+            let s = glass.sample(wo, uc, u).unwrap();
+            albedo += s.f[0] * s.wi[2].abs() / s.pdf;
+            refracted += !same_hemisphere(wo, s.wi) as usize;
+        }
+        assert!((albedo / 1024. - 1.).abs() < 1e-4);
+        assert!(refracted > 0);
+    }
+    // grazing view inside glass reflects totally
+    let wo = Vec3f::vec([0.9, 0., -0.1]).normalize();
+    assert_eq!(fresnel_dielectric(wo[2], 1.5), 1.);
+    // conductor fresnel goes to 1 at grazing angles, dielectric of eta 1 has none
+    assert!((fresnel_complex(0., gold.0, gold.1) - Vec3f::vec([1.; 3])).norm() < 1e-4);
+    assert!(fresnel_dielectric(0.5, 1.).abs() < 1e-6);
+
+    // reciprocity, transmission scaled by squared index of wi side
+    let eta_of = |w: Vec3f, eta: f32| if w[2] > 0. { 1. } else { eta };
+    for (k, (bsdf, dirac)) in bsdfs.iter().enumerate() {
+        if *dirac {
+            continue;
+        }
+        let eta = match k {
+            5 => 1.5,
+            6 => 1.33,
+            _ => 1.,
+        };
+        for i in 0..1024 {
+            let (wo, wi) = (sphere(i * 61 % (n * n)), sphere((i * 97 + 7) % (n * n)));
+            let a = bsdf.eval(wo, wi) / eta_of(wi, eta).powi(2);
+            let b = bsdf.eval(wi, wo) / eta_of(wo, eta).powi(2);
+            assert!(
+                (a - b).norm() <= 1e-3 * (1. + a.norm()),
+                "{k} {wo:?} {wi:?} {a:?} {b:?}"
+            );
+        }
+    }
+}
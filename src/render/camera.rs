@@ -3,6 +3,7 @@ use num_traits::Zero;
 use crate::{
     core::{math::orthogonalization, quaternion::Quat},
     prelude::*,
+    raycast::frustum::Frustum,
 };
 
 //TODO: camera types
@@ -13,6 +14,8 @@ pub struct Camera {
     pub fov: f32, // degree
     pub near: f32,
     pub far: f32,
+    /// open and close time, rays are spread over it for motion blur
+    pub shutter: (f32, f32),
 
     forward: Vec3f,
     up: Vec3f,
@@ -34,6 +37,7 @@ impl Camera {
             fov,
             near,
             far,
+            shutter: (0., 0.),
         }
     }
 
@@ -46,7 +50,22 @@ impl Camera {
         self.right = right;
     }
 
-    /// return ray with unnormalized dir
+    /// unit view direction
+    pub fn forward(&self) -> Vec3f {
+        self.forward
+    }
+
+    /// time of shutter sample u in [0,1]
+    pub fn shutter_time(&self, u: f32) -> f32 {
+        self.shutter.0 + (self.shutter.1 - self.shutter.0) * u
+    }
+
+    /// shutter opens and closes at once, one time sample is enough
+    pub fn is_instant(&self) -> bool {
+        self.shutter.0 == self.shutter.1
+    }
+
+    /// return ray with unnormalized dir at shutter open
     pub fn gen_ray(
         &self,
         (ix, iy): (usize, usize),
@@ -63,7 +82,24 @@ impl Camera {
         let y = 0.5 - (iy as f32 + 0.5 + dy) / res_h as f32;
 
         let dir = self.right * x + self.up * y + self.forward * focal;
-        Ray::new(self.pos, dir)
+        let mut ray = Ray::new(self.pos, dir);
+        ray.time = self.shutter.0;
+        ray
+    }
+
+    /// volume covered by gen_ray rays between near and far planes
+    pub fn frustum(&self, (res_w, res_h): (usize, usize)) -> Frustum {
+        let aspect = res_w as f32 / res_h as f32;
+        let focal = 0.5 / (self.fov.to_radians() * 0.5).tan();
+
+        // half image plane at distance 1 along forward
+        let half = (0.5 * aspect / focal, 0.5 / focal);
+        Frustum::perspective(
+            self.pos,
+            (self.forward, self.up, self.right),
+            half,
+            (self.near, self.far),
+        )
     }
 
     pub fn gen_ray_orthogonal(
@@ -84,7 +120,9 @@ impl Camera {
 
         let origin = self.pos + self.right * x + self.up * y;
         let dir = self.forward;
-        Ray::new(origin, dir)
+        let mut ray = Ray::new(origin, dir);
+        ray.time = self.shutter.0;
+        ray
     }
 }
 
@@ -108,5 +146,43 @@ fn test_camera() {
 
         assert_eq!(iw as f32 + 0.5, (ray.dir[0] + 0.5) * w as f32);
         assert_eq!((h - ih - 1) as f32 + 0.5, (ray.dir[1] + 0.5) * h as f32);
+        assert_eq!(ray.time, 0.);
     });
+
+    let mut cam = cam;
+    assert!(cam.is_instant());
+    cam.shutter = (1., 1.5);
+    assert!(!cam.is_instant());
+    assert_eq!(cam.shutter_time(0.5), 1.25);
+    assert_eq!(cam.gen_ray((0, 0), (0., 0.), (w, h)).time, 1.);
+}
+
+#[test]
+fn test_camera_frustum() {
+    let mut cam = Camera::new(
+        Vec3f::vec([1., 2., 3.]),
+        Vec3f::vec([0., 0., -1.]),
+        60.,
+        0.5,
+        8.,
+    );
+    cam.look_at(Vec3f::vec([-2., 0., -1.]));
+
+    let (w, h) = (16, 9);
+    let frustum = cam.frustum((w, h));
+    let focal = 0.5 / (cam.fov.to_radians() * 0.5).tan();
+    for (ix, iy) in [(0, 0), (15, 0), (7, 4), (0, 8), (15, 8)] {
+        // corner rays of border pixels, depth along forward is t * focal
+        for (dx, dy, inside) in [(0., 0., true), (-0.45, -0.45, true), (-0.6, 0., ix > 0)] {
+            let ray = cam.gen_ray((ix, iy), (dx, dy), (w, h));
+            for depth in [0.6, 2., 7.9] {
+                assert_eq!(
+                    frustum.contains(ray.org + ray.dir * (depth / focal)),
+                    inside
+                );
+            }
+            assert!(!frustum.contains(ray.org + ray.dir * (0.4 / focal)));
+            assert!(!frustum.contains(ray.org + ray.dir * (8.1 / focal)));
+        }
+    }
 }
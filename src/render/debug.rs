@@ -0,0 +1,403 @@
+use anyhow::{Result, anyhow};
+use rand::Rng;
+
+use crate::{
+    core::{math::coordinate_system, sampling::sample_cos_hemisphere, vec::Vector},
+    img::{PixelType, RawImage},
+    prelude::*,
+    raycast::{Surface, instance::Instance, primitive::Primitive},
+    render::scene::{Material, Scene},
+};
+
+/// cheap integrators for inspecting geometry, no lights and materials only for albedo.
+/// rays missing everything are black
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DebugIntegrator {
+    /// fraction of cosine weighted rays leaving hit point unoccluded within radius
+    AmbientOcclusion {
+        radius: f32,
+        samples: usize,
+    },
+    /// outward shading normal mapped from [-1,1] to [0,1]
+    Normal,
+    /// distance along camera forward, white at near plane to black at far plane
+    Depth,
+    Uv,
+    /// hashed colour of primitive, index into BVH primitives or instance blas
+    PrimitiveId,
+    /// hashed colour of Instance::id, primitive colour without instances
+    InstanceId,
+    /// weights of triangle vertices as red, green and blue, black on other shapes
+    Barycentric,
+    /// reflectance of hit material towards camera from bsdf samples, white without scene
+    Albedo,
+}
+
+/// bsdf samples averaged by albedo
+const ALBEDO_SAMPLES: usize = 16;
+
+/// nearest hit as seen by debug integrators
+#[derive(Debug, Clone, Copy)]
+pub struct DebugHit {
+    pub surface: Surface,
+    pub prim: usize,
+    pub instance: Option<usize>,
+    /// index into scene materials, None without scene
+    pub material: Option<usize>,
+}
+
+/// distinct colour for every id, neighbouring ids differ clearly
+pub fn id_colour(id: usize) -> [f32; 3] {
+    // murmur3 finalizer
+    let mut h = id as u64;
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51afd7ed558ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ceb9fe1a85ec53);
+    h ^= h >> 33;
+    // keep away from black so ids stand out from background
+    std::array::from_fn(|c| 0.2 + 0.8 * ((h >> (c * 16)) & 0xffff) as f32 / 65535.)
+}
+
+impl DebugIntegrator {
+    /// ao, normal, depth, uv, primid, instid, bary or albedo. ao uses radius 1 and 16 samples
+    pub fn from_name(name: &str) -> Result<Self> {
+        Ok(match name {
+            "ao" => DebugIntegrator::AmbientOcclusion {
+                radius: 1.,
+                samples: 16,
+            },
+            "normal" => DebugIntegrator::Normal,
+            "depth" => DebugIntegrator::Depth,
+            "uv" => DebugIntegrator::Uv,
+            "primid" => DebugIntegrator::PrimitiveId,
+            "instid" => DebugIntegrator::InstanceId,
+            "bary" => DebugIntegrator::Barycentric,
+            "albedo" => DebugIntegrator::Albedo,
+            _ => return Err(anyhow!("err: unknown integrator {name}")),
+        })
+    }
+
+    pub fn render<T: Primitive, P: PixelType>(
+        &self,
+        bvh: &BVH<T>,
+        cam: &Camera,
+        res: (usize, usize),
+    ) -> RawImage<P> {
+        self.render_with(
+            cam,
+            res,
+            &[],
+            |ray| {
+                let (hit, prim) = bvh.raycast_node(ray)?;
+                let surface = bvh.primitives[prim].surface(ray, &hit)?;
+                Some(DebugHit {
+                    surface,
+                    prim,
+                    instance: None,
+                    material: None,
+                })
+            },
+            |ray| bvh.occluded(ray),
+        )
+    }
+
+    /// top level BVH over instances, primitive ids index blas of hit instance
+    pub fn render_instances<T: Primitive, P: PixelType>(
+        &self,
+        bvh: &BVH<Instance<T>>,
+        cam: &Camera,
+        res: (usize, usize),
+    ) -> RawImage<P> {
+        self.render_with(
+            cam,
+            res,
+            &[],
+            |ray| {
+                let (hit, i) = bvh.raycast_node(ray)?;
+                let inst = &bvh.primitives[i];
+                let surface = inst.surface(ray, &hit)?;
+                Some(DebugHit {
+                    surface,
+                    prim: hit.part,
+                    instance: Some(inst.id),
+                    material: None,
+                })
+            },
+            |ray| bvh.occluded(ray),
+        )
+    }
+
+    /// primitives of scene with their materials, primitive ids index scene BVH
+    pub fn render_scene<T: Primitive, P: PixelType>(
+        &self,
+        scene: &Scene<T>,
+        cam: &Camera,
+        res: (usize, usize),
+    ) -> RawImage<P> {
+        self.render_with(
+            cam,
+            res,
+            &scene.materials,
+            |ray| {
+                let (hit, prim) = scene.bvh.raycast_node(ray)?;
+                let obj = &scene.bvh.primitives[prim];
+                let surface = obj.surface(ray, &hit)?;
+                Some(DebugHit {
+                    surface,
+                    prim,
+                    instance: None,
+                    material: Some(obj.material),
+                })
+            },
+            |ray| scene.bvh.occluded(ray),
+        )
+    }
+
+    fn render_with<P: PixelType>(
+        &self,
+        cam: &Camera,
+        (w, h): (usize, usize),
+        materials: &[Material],
+        intersect: impl Fn(&Ray) -> Option<DebugHit> + Sync,
+        occluded: impl Fn(&Ray) -> bool + Sync,
+    ) -> RawImage<P> {
+        let mut img: RawImage<P> = RawImage::new(w, h);
+        img.par_iter_pixels(|(i, pix)| {
+            let ray = cam.gen_ray((i % w, i / w), (0., 0.), (w, h));
+            let c = match intersect(&ray) {
+                Some(hit) => self.shade(cam, &ray, &hit, materials, &occluded),
+                None => [0.; 3],
+            };
+            *pix = P::from(&c);
+        });
+        img
+    }
+
+    fn shade(
+        &self,
+        cam: &Camera,
+        ray: &Ray,
+        hit: &DebugHit,
+        materials: &[Material],
+        occluded: &impl Fn(&Ray) -> bool,
+    ) -> [f32; 3] {
+        let s = &hit.surface;
+        match *self {
+            DebugIntegrator::AmbientOcclusion { radius, samples } => {
+                // hemisphere on side of camera
+                let n = if s.n.dot(ray.dir) > 0. {
+                    s.n * -1.
+                } else {
+                    s.n
+                };
+                let (u, v) = coordinate_system(n);
+                let mut rng = rand::rng();
+                let open = (0..samples)
+                    .filter(|_| {
+                        let [x, y, z] = sample_cos_hemisphere([rng.random(), rng.random()]);
+                        let mut ao_ray = s.spawn_ray(u * x + v * y + n * z, ray.time);
+                        ao_ray.t_max = radius;
+                        !occluded(&ao_ray)
+                    })
+                    .count();
+                [open as f32 / samples.max(1) as f32; 3]
+            }
+            DebugIntegrator::Normal => std::array::from_fn(|c| s.n[c] * 0.5 + 0.5),
+            DebugIntegrator::Depth => {
+                let depth = (s.p - cam.pos).dot(cam.forward());
+                [1. - (depth - cam.near) / (cam.far - cam.near); 3]
+            }
+            DebugIntegrator::Uv => [s.uv[0], s.uv[1], 0.],
+            DebugIntegrator::PrimitiveId => id_colour(hit.prim),
+            DebugIntegrator::InstanceId => id_colour(hit.instance.unwrap_or(hit.prim)),
+            DebugIntegrator::Barycentric => s.bary.unwrap_or([0.; 3]),
+            DebugIntegrator::Albedo => {
+                let Some(material) = hit.material else {
+                    return [1.; 3];
+                };
+                // bsdf works in frame of outward normal
+                let (u, v) = coordinate_system(s.n);
+                let d = ray.dir.normalize();
+                let wo = Vec3f::vec([-d.dot(u), -d.dot(v), -d.dot(s.n)]);
+                let mut rng = rand::rng();
+                let uc: Vec<f32> = (0..ALBEDO_SAMPLES).map(|_| rng.random()).collect();
+                let u2: Vec<[f32; 2]> = (0..ALBEDO_SAMPLES)
+                    .map(|_| [rng.random(), rng.random()])
+                    .collect();
+                let rho = materials[material].bsdf.rho(wo, &uc, &u2);
+                std::array::from_fn(|c| rho[c])
+            }
+        }
+    }
+}
+
+#[test]
+fn test_debug_integrators() {
+    use crate::{
+        core::{quaternion::Quat, transform::Transform},
+        raycast::{Raycast, triangle::Triangle},
+    };
+    use std::sync::Arc;
+
+    assert_eq!(
+        DebugIntegrator::from_name("bary").unwrap(),
+        DebugIntegrator::Barycentric
+    );
+    assert_eq!(
+        DebugIntegrator::from_name("albedo").unwrap(),
+        DebugIntegrator::Albedo
+    );
+    assert!(DebugIntegrator::from_name("phong").is_err());
+    for i in 0..256 {
+        assert_ne!(id_colour(i), id_colour(i + 1));
+        assert!(id_colour(i).iter().all(|&c| (0.2..=1.).contains(&c)));
+    }
+
+    // wall of two triangles facing camera at z = -3
+    let corner = |x: f32, y: f32| Vec3f::vec([x, y, -3.]);
+    let mut bvh = BVH::new(2);
+    bvh.push(Triangle::new(
+        corner(-4., -4.),
+        corner(4., -4.),
+        corner(4., 4.),
+    ));
+    bvh.push(Triangle::new(
+        corner(-4., -4.),
+        corner(4., 4.),
+        corner(-4., 4.),
+    ));
+    bvh.build(1, false);
+    let cam = Camera::default();
+    let (w, h) = (16, 16);
+    let render = |integrator: DebugIntegrator| -> RawImage<Rgb<f32>> {
+        integrator.render(&bvh, &cam, (w, h))
+    };
+
+    let close = |a: [f32; 3], b: [f32; 3]| a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-4);
+    let depth = 1. - (3. - cam.near) / (cam.far - cam.near);
+    let normals = render(DebugIntegrator::Normal);
+    let depths = render(DebugIntegrator::Depth);
+    let ids = render(DebugIntegrator::PrimitiveId);
+    let bary = render(DebugIntegrator::Barycentric);
+    let uv = render(DebugIntegrator::Uv);
+    let ao = render(DebugIntegrator::AmbientOcclusion {
+        radius: 10.,
+        samples: 8,
+    });
+    for i in 0..w * h {
+        let ray = cam.gen_ray((i % w, i / w), (0., 0.), (w, h));
+        let (_, prim) = bvh.raycast_node(&ray).unwrap();
+        assert!(close(normals.data()[i].0, [0.5, 0.5, 1.]));
+        // linear depth is flat over plane facing camera
+        assert!(
+            close(depths.data()[i].0, [depth; 3]),
+            "{:?}",
+            depths.data()[i]
+        );
+        assert_eq!(ids.data()[i].0, id_colour(prim));
+        // weights of hit triangle vertices land on hit point
+        let b = bary.data()[i].0;
+        assert!((b.iter().sum::<f32>() - 1.).abs() < 1e-4 && b.iter().all(|&c| c >= -1e-4));
+        let hit = bvh.raycast(&ray).unwrap().position(&ray);
+        let [p0, p1, p2] = bvh.primitives[prim].p;
+        assert!((p0 * b[0] + p1 * b[1] + p2 * b[2] - hit).norm() < 1e-3);
+        // triangle uv are weights of p1 and p2
+        assert_eq!(uv.data()[i].0, [b[1], b[2], 0.]);
+        // nothing in front of wall
+        assert_eq!(ao.data()[i].0, [1.; 3]);
+    }
+
+    // sphere in front of wall darkens wall around its shadow, not far corners
+    let mut bvh = BVH::new(3);
+    bvh.push(Arc::new(Triangle::new(
+        corner(-4., -4.),
+        corner(4., -4.),
+        corner(4., 4.),
+    )) as Arc<dyn crate::raycast::primitive::DynPrimitive>);
+    bvh.push(Arc::new(Triangle::new(
+        corner(-4., -4.),
+        corner(4., 4.),
+        corner(-4., 4.),
+    )));
+    bvh.push(Arc::new(Sphere::new(Vec3f::vec([0., -0.9, -2.3]), 0.6)));
+    bvh.build(1, false);
+    let ao: RawImage<Rgb<f32>> = DebugIntegrator::AmbientOcclusion {
+        radius: 1.,
+        samples: 256,
+    }
+    .render(&bvh, &cam, (w, h));
+    assert_eq!(ao.data()[0].0, [1.; 3]);
+    let on_wall = |i: usize| {
+        let ray = cam.gen_ray((i % w, i / w), (0., 0.), (w, h));
+        let (_, prim) = bvh.raycast_node(&ray).unwrap();
+        bvh.primitives[prim].downcast_ref::<Triangle>().is_some()
+    };
+    let darkest = (0..w * h)
+        .filter(|&i| on_wall(i))
+        .map(|i| ao.data()[i].0[0])
+        .fold(1., f32::min);
+    assert!(darkest < 0.9, "{darkest}");
+
+    // sphere has no barycentrics, black
+    let bary: RawImage<Rgb<f32>> = DebugIntegrator::Barycentric.render(&bvh, &cam, (w, h));
+    assert!((0..w * h).any(|i| !on_wall(i)));
+    for i in 0..w * h {
+        let b = bary.data()[i].0;
+        if on_wall(i) {
+            assert!((b.iter().sum::<f32>() - 1.).abs() < 1e-4);
+        } else {
+            assert_eq!(b, [0.; 3]);
+        }
+    }
+
+    // albedo of diffuse wall in scene, white without materials
+    let white: RawImage<Rgb<f32>> = DebugIntegrator::Albedo.render(&bvh, &cam, (w, h));
+    assert_eq!(white.data()[0].0, [1.; 3]);
+    let mut scene = Scene::new(Vec3f::vec([0.; 3]));
+    let paint = scene.add_material(Material::diffuse(Vec3f::vec([0.2, 0.4, 0.6])));
+    scene.push(
+        Triangle::new(corner(-4., -4.), corner(4., -4.), corner(4., 4.)),
+        paint,
+    );
+    scene.push(
+        Triangle::new(corner(-4., -4.), corner(4., 4.), corner(-4., 4.)),
+        paint,
+    );
+    scene.build(1, false);
+    let albedo: RawImage<Rgb<f32>> = DebugIntegrator::Albedo.render_scene(&scene, &cam, (w, h));
+    assert!(albedo.data().iter().all(|p| close(p.0, [0.2, 0.4, 0.6])));
+
+    // ids of instances, primitives index blas
+    let mut blas = BVH::new(1);
+    blas.push(Sphere::new(Vec3f::vec([0.; 3]), 0.5));
+    blas.build(1, false);
+    let blas = Arc::new(blas);
+    let mut tlas = BVH::new(2);
+    for (i, x) in [-1., 1.].into_iter().enumerate() {
+        let t = Transform::new(
+            Vec3f::vec([x, 0., -3.]),
+            Quat::identity(),
+            Vec3f::vec([1.; 3]),
+        );
+        tlas.push(Instance::new(7 + i, blas.clone(), t));
+    }
+    tlas.build(1, false);
+    let inst: RawImage<Rgb<f32>> =
+        DebugIntegrator::InstanceId.render_instances(&tlas, &cam, (w, h));
+    let prim: RawImage<Rgb<f32>> =
+        DebugIntegrator::PrimitiveId.render_instances(&tlas, &cam, (w, h));
+    let mut seen = [false; 2];
+    for i in 0..w * h {
+        let ray = cam.gen_ray((i % w, i / w), (0., 0.), (w, h));
+        match tlas.raycast_instance(&ray) {
+            Some(hit) => {
+                assert_eq!(inst.data()[i].0, id_colour(hit.instance));
+                assert_eq!(prim.data()[i].0, id_colour(0));
+                seen[hit.instance - 7] = true;
+            }
+            None => assert!(tlas.raycast(&ray).is_none() && inst.data()[i].0 == [0.; 3]),
+        }
+    }
+    assert_eq!(seen, [true; 2]);
+}
@@ -0,0 +1,88 @@
+use rayon::prelude::*;
+
+use crate::{
+    img::{PixelType, RawImage},
+    prelude::*,
+    raycast::primitive::Primitive,
+};
+
+/// blue, cyan, green, yellow, red for x in [0,1]
+pub fn false_colour(x: f32) -> [f32; 3] {
+    const STOPS: [[f32; 3]; 5] = [
+        [0., 0., 1.],
+        [0., 1., 1.],
+        [0., 1., 0.],
+        [1., 1., 0.],
+        [1., 0., 0.],
+    ];
+
+    let x = if x.is_nan() { 0. } else { x.clamp(0., 1.) } * (STOPS.len() - 1) as f32;
+    let i = (x as usize).min(STOPS.len() - 2);
+    let f = x - i as f32;
+    std::array::from_fn(|c| STOPS[i][c] * (1. - f) + STOPS[i + 1][c] * f)
+}
+
+/// traversal cost per pixel, node visits plus primitive tests of camera ray.
+/// costs are scaled by max_cost, or by largest cost in image if none
+pub fn render_heatmap<T: Primitive, P: PixelType>(
+    bvh: &BVH<T>,
+    cam: &Camera,
+    (w, h): (usize, usize),
+    max_cost: Option<usize>,
+) -> RawImage<P> {
+    let costs: Vec<usize> = (0..w * h)
+        .into_par_iter()
+        .map(|i| {
+            let ray = cam.gen_ray((i % w, i / w), (0., 0.), (w, h));
+            let (_, counters) = bvh.raycast_counted(&ray);
+            counters.nodes + counters.prims
+        })
+        .collect();
+
+    let max_cost = max_cost
+        .unwrap_or_else(|| costs.iter().copied().max().unwrap_or(0))
+        .max(1);
+
+    let mut img: RawImage<P> = RawImage::new(w, h);
+    img.data_mut()
+        .par_iter_mut()
+        .zip(costs.par_iter())
+        .for_each(|(pix, &cost)| {
+            *pix = P::from(&false_colour(cost as f32 / max_cost as f32));
+        });
+    img
+}
+
+#[test]
+fn test_heatmap() {
+    assert_eq!(false_colour(0.), [0., 0., 1.]);
+    assert_eq!(false_colour(0.5), [0., 1., 0.]);
+    assert_eq!(false_colour(1.), [1., 0., 0.]);
+    assert_eq!(false_colour(2.), [1., 0., 0.]);
+
+    let mut bvh = BVH::new(64);
+    for i in 0..64 {
+        let cnt = Vec3f::vec([(i % 8) as f32 - 3.5, (i / 8) as f32 - 3.5, -10.]);
+        bvh.push(Sphere::new(cnt, 0.4));
+    }
+    bvh.build(4, false);
+
+    let cam = Camera::default();
+    let (w, h) = (32, 32);
+    let colour = |cost: f32| <Rgb<u8> as PixelType>::from(&false_colour(cost));
+
+    let img: RawImage<Rgb<u8>> = render_heatmap(&bvh, &cam, (w, h), Some(64));
+    assert_eq!(img.shape(), (w, h));
+    for (i, pix) in img.data().iter().enumerate() {
+        let ray = cam.gen_ray((i % w, i / w), (0., 0.), (w, h));
+        let (_, c) = bvh.raycast_counted(&ray);
+        assert_eq!(*pix, colour((c.nodes + c.prims) as f32 / 64.));
+    }
+    // corner ray misses root, center ray tests spheres
+    assert_eq!(img.data()[0], colour(1. / 64.));
+    assert_ne!(img.data()[w * h / 2 + w / 2], colour(1. / 64.));
+
+    // scaled by most expensive pixel
+    let img: RawImage<Rgb<u8>> = render_heatmap(&bvh, &cam, (w, h), None);
+    assert!(img.data().contains(&colour(1.)));
+}
@@ -0,0 +1,698 @@
+use std::{f32::consts::PI, fmt::Debug};
+
+use anyhow::{Result, anyhow};
+
+use crate::{
+    core::{
+        math::coordinate_system,
+        sampling::{Distribution2D, square2unitsphere},
+        spherical::azimuth_u,
+        tensor::Vec3f,
+        vec::Vector,
+    },
+    raycast::{
+        Ray, Raycast, bounds::Bounds3f, primitive::Primitive, sphere::Sphere, triangle::Triangle,
+    },
+    render::lightsampler::LightBounds,
+};
+
+/// light arriving at a point from one direction
+#[derive(Debug, Clone, Copy)]
+pub struct LightSample {
+    /// incident radiance, or intensity over squared distance of delta lights
+    pub li: Vec3f,
+    /// unit direction towards light
+    pub wi: Vec3f,
+    /// distance to light along wi, infinite for lights at infinity
+    pub dist: f32,
+    /// solid angle density, 1 for delta lights
+    pub pdf: f32,
+}
+
+/// emitter used by integrators for sampling light directly
+pub trait Light: Sync + Send + Debug {
+    /// light arriving at p, u picks point on light
+    fn sample_li(&self, p: Vec3f, u: [f32; 2]) -> Option<LightSample>;
+
+    /// density of sample_li picking unit wi from p, 0 for delta lights
+    fn pdf_li(&self, p: Vec3f, wi: Vec3f) -> f32;
+
+    /// radiance leaving point p with normal n of area light along w
+    fn l(&self, _p: Vec3f, _n: Vec3f, _w: Vec3f) -> Vec3f {
+        Vec3f::vec([0.; 3])
+    }
+
+    /// radiance of light at infinity along ray leaving scene
+    fn le(&self, _ray: &Ray) -> Vec3f {
+        Vec3f::vec([0.; 3])
+    }
+
+    /// only a position or direction, never hit by rays
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    fn is_infinite(&self) -> bool {
+        false
+    }
+
+    /// emitted flux, lights at infinity shine on a scene of scene_radius
+    fn power(&self, scene_radius: f32) -> Vec3f;
+
+    /// None for lights at infinity
+    fn bounds(&self) -> Option<LightBounds> {
+        None
+    }
+}
+
+/// bounds of light at a single point
+fn point_bounds(pos: Vec3f, phi: f32, w: Vec3f, cos_theta_o: f32, cos_theta_e: f32) -> LightBounds {
+    LightBounds {
+        bounds: Bounds3f::new(pos, pos),
+        phi,
+        w,
+        cos_theta_o,
+        cos_theta_e,
+        two_sided: false,
+    }
+}
+
+/// same intensity in every direction
+#[derive(Debug, Clone)]
+pub struct PointLight {
+    pub pos: Vec3f,
+    pub intensity: Vec3f,
+}
+
+impl PointLight {
+    pub fn new(pos: Vec3f, intensity: Vec3f) -> Self {
+        PointLight { pos, intensity }
+    }
+}
+
+/// direction and distance from p to pos
+fn towards(p: Vec3f, pos: Vec3f) -> Option<(Vec3f, f32)> {
+    let d = pos - p;
+    let dist = d.norm();
+    (dist > 0.).then(|| (d / dist, dist))
+}
+
+impl Light for PointLight {
+    fn sample_li(&self, p: Vec3f, _u: [f32; 2]) -> Option<LightSample> {
+        let (wi, dist) = towards(p, self.pos)?;
+        Some(LightSample {
+            li: self.intensity / (dist * dist),
+            wi,
+            dist,
+            pdf: 1.,
+        })
+    }
+
+    fn pdf_li(&self, _p: Vec3f, _wi: Vec3f) -> f32 {
+        0.
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn power(&self, _scene_radius: f32) -> Vec3f {
+        self.intensity * (4. * PI)
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let phi = 4. * PI * luminance(self.intensity);
+        Some(point_bounds(
+            self.pos,
+            phi,
+            Vec3f::vec([0., 0., 1.]),
+            -1.,
+            0.,
+        ))
+    }
+}
+
+/// point light shining into a cone, fading out smoothly towards its edge
+#[derive(Debug, Clone)]
+pub struct SpotLight {
+    pub pos: Vec3f,
+    /// unit axis of cone
+    pub dir: Vec3f,
+    pub intensity: Vec3f,
+    /// full intensity inside
+    pub cos_falloff_start: f32,
+    /// no light outside
+    pub cos_falloff_end: f32,
+}
+
+impl SpotLight {
+    /// half angles of cone in degrees
+    pub fn new(pos: Vec3f, dir: Vec3f, intensity: Vec3f, angle: f32, falloff_start: f32) -> Self {
+        SpotLight {
+            pos,
+            dir: dir.normalize(),
+            intensity,
+            cos_falloff_start: falloff_start.min(angle).to_radians().cos(),
+            cos_falloff_end: angle.to_radians().cos(),
+        }
+    }
+
+    /// intensity towards unit w
+    pub fn i(&self, w: Vec3f) -> Vec3f {
+        let (a, b) = (self.cos_falloff_end, self.cos_falloff_start);
+        let cos = w.dot(self.dir);
+        let falloff = if a == b {
+            (cos >= b) as u8 as f32
+        } else {
+            let t = ((cos - a) / (b - a)).clamp(0., 1.);
+            t * t * (3. - 2. * t)
+        };
+        self.intensity * falloff
+    }
+}
+
+impl Light for SpotLight {
+    fn sample_li(&self, p: Vec3f, _u: [f32; 2]) -> Option<LightSample> {
+        let (wi, dist) = towards(p, self.pos)?;
+        let li = self.i(wi * -1.) / (dist * dist);
+        (li.dot(li) > 0.).then_some(LightSample {
+            li,
+            wi,
+            dist,
+            pdf: 1.,
+        })
+    }
+
+    fn pdf_li(&self, _p: Vec3f, _wi: Vec3f) -> f32 {
+        0.
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    /// smoothstep falloff integrates to half of its band
+    fn power(&self, _scene_radius: f32) -> Vec3f {
+        let (a, b) = (self.cos_falloff_end, self.cos_falloff_start);
+        self.intensity * (2. * PI * ((1. - b) + (b - a) / 2.))
+    }
+
+    /// full intensity within falloff start, reaches out to falloff end
+    fn bounds(&self) -> Option<LightBounds> {
+        let (a, b) = (self.cos_falloff_end, self.cos_falloff_start);
+        let cos_theta_e = (a.clamp(-1., 1.).acos() - b.clamp(-1., 1.).acos()).cos();
+        let phi = 4. * PI * luminance(self.intensity);
+        Some(point_bounds(self.pos, phi, self.dir, b, cos_theta_e))
+    }
+}
+
+/// parallel light from far away, e.g. sun
+#[derive(Debug, Clone)]
+pub struct DirectionalLight {
+    /// unit direction light travels
+    pub dir: Vec3f,
+    /// on surfaces facing light
+    pub irradiance: Vec3f,
+}
+
+impl DirectionalLight {
+    pub fn new(dir: Vec3f, irradiance: Vec3f) -> Self {
+        DirectionalLight {
+            dir: dir.normalize(),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample_li(&self, _p: Vec3f, _u: [f32; 2]) -> Option<LightSample> {
+        Some(LightSample {
+            li: self.irradiance,
+            wi: self.dir * -1.,
+            dist: f32::INFINITY,
+            pdf: 1.,
+        })
+    }
+
+    fn pdf_li(&self, _p: Vec3f, _wi: Vec3f) -> f32 {
+        0.
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+
+    /// falls on disk of scene
+    fn power(&self, scene_radius: f32) -> Vec3f {
+        self.irradiance * (PI * scene_radius * scene_radius)
+    }
+}
+
+/// emitting surface of area light
+#[derive(Debug, Clone)]
+pub enum AreaShape {
+    Triangle(Triangle),
+    Sphere(Sphere),
+}
+
+impl AreaShape {
+    pub fn area(&self) -> f32 {
+        match self {
+            AreaShape::Triangle(t) => {
+                let [a, b, c] = t.p;
+                (b - a).cross(c - a).norm() / 2.
+            }
+            AreaShape::Sphere(s) => 4. * PI * s.r * s.r,
+        }
+    }
+
+    /// solid angle density from p of point with normal n at dist along unit wi,
+    /// when points are picked uniformly by area
+    fn area_pdf(&self, n: Vec3f, wi: Vec3f, dist: f32) -> f32 {
+        let cos = n.dot(wi).abs();
+        if cos == 0. {
+            return 0.;
+        }
+        dist * dist / (cos * self.area())
+    }
+
+    /// sine squared of half angle of sphere seen from p, None if p is inside
+    fn cone(s: &Sphere, p: Vec3f) -> Option<f32> {
+        let d2 = (s.cnt - p).sqrnorm();
+        (d2 > s.r * s.r).then(|| s.r * s.r / d2)
+    }
+
+    /// point and its normal seen from p, with solid angle density
+    fn sample(&self, p: Vec3f, u: [f32; 2]) -> Option<(Vec3f, Vec3f, f32)> {
+        let (pl, n) = match self {
+            AreaShape::Triangle(t) => {
+                let [a, b, c] = t.p;
+                let su = u[0].sqrt();
+                let (b0, b1) = (1. - su, u[1] * su);
+                let pl = a * b0 + b * b1 + c * (1. - b0 - b1);
+                (pl, (b - a).cross(c - a).normalize())
+            }
+            AreaShape::Sphere(s) => match Self::cone(s, p) {
+                // only visible cap, directions uniform in cone
+                Some(sin2_max) => {
+                    let dc = (s.cnt - p).norm();
+                    let cos_max = (1. - sin2_max).max(0.).sqrt();
+                    let cos = (1. - u[0]) + u[0] * cos_max;
+                    let sin2 = (1. - cos * cos).max(0.);
+                    let phi = 2. * PI * u[1];
+                    let wc = (s.cnt - p) / dc;
+                    let (wx, wy) = coordinate_system(wc);
+                    let wi =
+                        wx * (sin2.sqrt() * phi.cos()) + wy * (sin2.sqrt() * phi.sin()) + wc * cos;
+                    let ds = dc * cos - (s.r * s.r - dc * dc * sin2).max(0.).sqrt();
+                    let pl = p + wi * ds;
+                    // stable 1 - cos_max for small cones
+                    let pdf = 1. / (2. * PI * sin2_max / (1. + cos_max));
+                    return Some((pl, (pl - s.cnt).normalize(), pdf));
+                }
+                None => {
+                    let n = Vec3f::vec(square2unitsphere(u));
+                    (s.cnt + n * s.r, n)
+                }
+            },
+        };
+        let (wi, dist) = towards(p, pl)?;
+        let pdf = self.area_pdf(n, wi, dist);
+        (pdf > 0.).then_some((pl, n, pdf))
+    }
+
+    fn pdf(&self, p: Vec3f, wi: Vec3f) -> f32 {
+        let ray = Ray::new(p, wi);
+        match self {
+            AreaShape::Triangle(t) => t.raycast(&ray).map_or(0., |hit| {
+                let n = {
+                    let [a, b, c] = t.p;
+                    (b - a).cross(c - a).normalize()
+                };
+                self.area_pdf(n, wi, hit.t * wi.norm())
+            }),
+            AreaShape::Sphere(s) => {
+                let Some(hit) = s.raycast(&ray) else {
+                    return 0.;
+                };
+                match Self::cone(s, p) {
+                    Some(sin2_max) => {
+                        let cos_max = (1. - sin2_max).max(0.).sqrt();
+                        1. / (2. * PI * sin2_max / (1. + cos_max))
+                    }
+                    None => {
+                        let n = (hit.position(&ray) - s.cnt).normalize();
+                        self.area_pdf(n, wi, hit.t * wi.norm())
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// surface of uniform radiance, on front side unless two sided
+#[derive(Debug, Clone)]
+pub struct AreaLight {
+    pub shape: AreaShape,
+    pub radiance: Vec3f,
+    pub two_sided: bool,
+}
+
+impl AreaLight {
+    pub fn new(shape: AreaShape, radiance: Vec3f, two_sided: bool) -> Self {
+        AreaLight {
+            shape,
+            radiance,
+            two_sided,
+        }
+    }
+
+    /// front side is counter clockwise
+    pub fn triangle(t: Triangle, radiance: Vec3f) -> Self {
+        AreaLight::new(AreaShape::Triangle(t), radiance, false)
+    }
+
+    pub fn sphere(s: Sphere, radiance: Vec3f) -> Self {
+        AreaLight::new(AreaShape::Sphere(s), radiance, false)
+    }
+}
+
+impl Light for AreaLight {
+    fn sample_li(&self, p: Vec3f, u: [f32; 2]) -> Option<LightSample> {
+        let (pl, n, pdf) = self.shape.sample(p, u)?;
+        let (wi, dist) = towards(p, pl)?;
+        let li = self.l(pl, n, wi * -1.);
+        (li.dot(li) > 0.).then_some(LightSample { li, wi, dist, pdf })
+    }
+
+    fn pdf_li(&self, p: Vec3f, wi: Vec3f) -> f32 {
+        self.shape.pdf(p, wi)
+    }
+
+    fn l(&self, _p: Vec3f, n: Vec3f, w: Vec3f) -> Vec3f {
+        if !self.two_sided && n.dot(w) < 0. {
+            return Vec3f::vec([0.; 3]);
+        }
+        self.radiance
+    }
+
+    fn power(&self, _scene_radius: f32) -> Vec3f {
+        let sides = if self.two_sided { 2. } else { 1. };
+        self.radiance * (PI * sides * self.shape.area())
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let sides = if self.two_sided { 2. } else { 1. };
+        let phi = luminance(self.radiance) * sides * self.shape.area();
+        let (bounds, w, cos_theta_o) = match &self.shape {
+            AreaShape::Triangle(t) => {
+                let [a, b, c] = t.p;
+                let bounds = Bounds3f::new(a, a).enlarge(b).enlarge(c);
+                (bounds, (b - a).cross(c - a).normalize(), 1.)
+            }
+            AreaShape::Sphere(s) => (s.bounds(), Vec3f::vec([0., 0., 1.]), -1.),
+        };
+        Some(LightBounds {
+            bounds,
+            phi,
+            w,
+            cos_theta_o,
+            cos_theta_e: 0.,
+            two_sided: self.two_sided,
+        })
+    }
+}
+
+/// radiance from every direction at infinity, stored in equirectangular map
+/// and sampled by luminance
+#[derive(Debug, Clone)]
+pub struct EnvironmentLight {
+    pub w: usize,
+    pub h: usize,
+    /// rows from +y down to -y, u is azimuth around y from +z
+    pub data: Vec<Vec3f>,
+    distr: Distribution2D,
+}
+
+pub fn luminance(c: Vec3f) -> f32 {
+    0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2]
+}
+
+impl EnvironmentLight {
+    pub fn new(w: usize, h: usize, data: Vec<Vec3f>) -> Self {
+        assert_eq!(data.len(), w * h);
+        // solid angle of row shrinks towards poles
+        let func: Vec<f32> = (0..w * h)
+            .map(|i| {
+                let sin = (PI * ((i / w) as f32 + 0.5) / h as f32).sin();
+                luminance(data[i]) * sin
+            })
+            .collect();
+        EnvironmentLight {
+            w,
+            h,
+            data,
+            distr: Distribution2D::new(&func, w, h),
+        }
+    }
+
+    pub fn constant(radiance: Vec3f) -> Self {
+        EnvironmentLight::new(1, 1, vec![radiance])
+    }
+
+    /// hdr or exr image, values are linear radiance
+    pub fn open(path: &str) -> Result<Self> {
+        let img = image::open(path)
+            .map_err(|e| anyhow!("err: can not open environment {path}, {e}"))?
+            .to_rgb32f();
+        let (w, h) = (img.width() as usize, img.height() as usize);
+        let data = img.pixels().map(|p| Vec3f::vec(p.0)).collect();
+        Ok(EnvironmentLight::new(w, h, data))
+    }
+
+    fn uv(w: Vec3f) -> [f32; 2] {
+        let w = w.normalize();
+        [azimuth_u(w), w[1].clamp(-1., 1.).acos() / PI]
+    }
+
+    /// radiance arriving from unit direction w
+    pub fn lookup(&self, w: Vec3f) -> Vec3f {
+        let [u, v] = Self::uv(w);
+        let x = ((u * self.w as f32) as usize).min(self.w - 1);
+        let y = ((v * self.h as f32) as usize).min(self.h - 1);
+        self.data[y * self.w + x]
+    }
+}
+
+impl Light for EnvironmentLight {
+    fn sample_li(&self, _p: Vec3f, u: [f32; 2]) -> Option<LightSample> {
+        let ([u, v], pdf) = self.distr.sample(u);
+        let (theta, phi) = (v * PI, u * 2. * PI);
+        let sin = theta.sin();
+        if pdf == 0. || sin == 0. {
+            return None;
+        }
+        let wi = Vec3f::vec([sin * phi.sin(), theta.cos(), sin * phi.cos()]);
+        Some(LightSample {
+            li: self.lookup(wi),
+            wi,
+            dist: f32::INFINITY,
+            // map area to solid angle
+            pdf: pdf / (2. * PI * PI * sin),
+        })
+    }
+
+    fn pdf_li(&self, _p: Vec3f, wi: Vec3f) -> f32 {
+        let [u, v] = Self::uv(wi);
+        let sin = (v * PI).sin();
+        if sin == 0. {
+            return 0.;
+        }
+        self.distr.pdf([u, v]) / (2. * PI * PI * sin)
+    }
+
+    fn le(&self, ray: &Ray) -> Vec3f {
+        self.lookup(ray.dir)
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+
+    /// falls on disk of scene from every direction
+    fn power(&self, scene_radius: f32) -> Vec3f {
+        // solid angle of pixel is band between polar angles of its row
+        let theta = |y: usize| PI * y as f32 / self.h as f32;
+        let total = (0..self.w * self.h).fold(Vec3f::vec([0.; 3]), |acc, i| {
+            let y = i / self.w;
+            let band = theta(y).cos() - theta(y + 1).cos();
+            acc + self.data[i] * (2. * PI / self.w as f32 * band)
+        });
+        total * (PI * scene_radius * scene_radius)
+    }
+}
+
+#[test]
+fn test_lights() {
+    use crate::core::sampling::radical_inverse;
+
+    let p = Vec3f::vec([0., 0., 0.]);
+    let halton = |i: usize| [radical_inverse(i, 0), radical_inverse(i, 1)];
+    let n = 256;
+    let sphere = |i: usize| {
+        let q = [(i % n) as f32 + 0.5, (i / n) as f32 + 0.5].map(|v| v / n as f32);
+        Vec3f::vec(square2unitsphere(q))
+    };
+    let da = 4. * PI / (n * n) as f32;
+
+    // delta lights, inverse square and cone
+    let point = PointLight::new(Vec3f::vec([0., 2., 0.]), Vec3f::vec([4.; 3]));
+    let s = point.sample_li(p, [0.5; 2]).unwrap();
+    assert_eq!((s.li[0], s.wi, s.dist), (1., Vec3f::vec([0., 1., 0.]), 2.));
+    assert!(point.is_delta() && point.pdf_li(p, s.wi) == 0.);
+    assert_eq!(point.power(1.)[0], 16. * PI);
+
+    let spot = SpotLight::new(
+        Vec3f::vec([0., 2., 0.]),
+        Vec3f::vec([0., -1., 0.]),
+        Vec3f::vec([4.; 3]),
+        30.,
+        20.,
+    );
+    assert_eq!(spot.sample_li(p, [0.5; 2]).unwrap().li[0], 1.);
+    assert!(spot.sample_li(Vec3f::vec([2., 0., 0.]), [0.5; 2]).is_none());
+    let edge = spot
+        .sample_li(
+            Vec3f::vec([2. * 25f32.to_radians().tan(), 0., 0.]),
+            [0.5; 2],
+        )
+        .unwrap();
+    assert!(edge.li[0] > 0. && edge.li[0] < 1.);
+    // power is intensity integrated over sphere
+    let flux = (0..n * n).map(|i| spot.i(sphere(i))[0] * da).sum::<f32>();
+    assert!((flux - spot.power(1.)[0]).abs() < 0.01 * flux);
+
+    let sun = DirectionalLight::new(Vec3f::vec([0., -1., 0.]), Vec3f::vec([2.; 3]));
+    let s = sun.sample_li(p, [0.5; 2]).unwrap();
+    assert_eq!(
+        (s.wi, s.dist, s.pdf),
+        (Vec3f::vec([0., 1., 0.]), f32::INFINITY, 1.)
+    );
+    assert!(sun.is_infinite());
+
+    // area and environment lights, density of samples integrates to 1,
+    // li / pdf estimates radiance integrated over sphere
+    let tri = Triangle::new(
+        Vec3f::vec([-1., 1., -1.]),
+        Vec3f::vec([1., 1., -1.]),
+        Vec3f::vec([0., 1., 1.]),
+    );
+    let ball = Sphere::new(Vec3f::vec([0.5, 0., 3.]), 1.);
+    let mut env = vec![Vec3f::vec([0.1; 3]); 8 * 4];
+    env[8 + 3] = Vec3f::vec([200., 80., 40.]);
+    env[3 * 8 + 6] = Vec3f::vec([0., 5., 0.]);
+    let lights: Vec<(Box<dyn Light>, Vec3f)> = vec![
+        (
+            Box::new(AreaLight::triangle(tri.clone(), Vec3f::vec([1.; 3]))),
+            p,
+        ),
+        (
+            Box::new(AreaLight::new(
+                AreaShape::Triangle(tri),
+                Vec3f::vec([1.; 3]),
+                true,
+            )),
+            p,
+        ),
+        (
+            Box::new(AreaLight::sphere(ball.clone(), Vec3f::vec([2.; 3]))),
+            p,
+        ),
+        (
+            Box::new(AreaLight::sphere(ball, Vec3f::vec([2.; 3]))),
+            Vec3f::vec([0.5, 0.2, 3.]),
+        ),
+        (
+            Box::new(EnvironmentLight::constant(Vec3f::vec([0.5; 3]))),
+            p,
+        ),
+        (Box::new(EnvironmentLight::new(8, 4, env)), p),
+    ];
+    for (k, (light, p)) in lights.iter().enumerate() {
+        let (mut total, mut radiance) = (0., Vec3f::vec([0.; 3]));
+        for i in 0..n * n {
+            let wi = sphere(i);
+            total += light.pdf_li(*p, wi) * da;
+            radiance = radiance + light.le(&Ray::new(*p, wi)) * da;
+        }
+        let m = 4096;
+        let mut estimate = Vec3f::vec([0.; 3]);
+        for i in 0..m {
+            if let Some(s) = light.sample_li(*p, halton(i)) {
+                // sin of polar angle loses precision at poles of environment
+                let pdf = light.pdf_li(*p, s.wi);
+                let pole = light.is_infinite() && s.wi[1].abs() > 0.999;
+                assert!(
+                    pole || (pdf - s.pdf).abs() <= 1e-2 * s.pdf,
+                    "{k} {pdf} {}",
+                    s.pdf
+                );
+                estimate = estimate + s.li / s.pdf;
+            }
+        }
+        let estimate = estimate / m as f32;
+        assert!((total - 1.).abs() < 0.03, "{k} {total}");
+        let expected = match k {
+            // solid angle of triangle by grid
+            0 | 1 => {
+                let hits = (0..n * n)
+                    .filter(|&i| light.pdf_li(*p, sphere(i)) > 0.)
+                    .count();
+                Vec3f::vec([hits as f32 * da; 3])
+            }
+            2 => {
+                let sin2: f32 = 1. / (0.25 + 9.);
+                Vec3f::vec([2. * 2. * PI * (1. - (1. - sin2).sqrt()); 3])
+            }
+            // inside sphere sees back of it
+            3 => Vec3f::vec([0.; 3]),
+            _ => radiance,
+        };
+        assert!(
+            (estimate - expected).norm() < 0.02 * (1. + expected.norm()),
+            "{k} {:?} {:?}",
+            estimate,
+            expected
+        );
+    }
+    assert!((lights[1].0.power(1.)[0] - 2. * PI * 2.).abs() < 1e-4);
+    // one sided triangle faces down
+    assert!(
+        lights[0]
+            .0
+            .sample_li(Vec3f::vec([0., 2., 0.]), [0.5; 2])
+            .is_none()
+    );
+    assert!(
+        lights[1]
+            .0
+            .sample_li(Vec3f::vec([0., 2., 0.]), [0.5; 2])
+            .is_some()
+    );
+    let env = &lights[4].0;
+    assert!((env.power(1.)[0] - 0.5 * 4. * PI * PI).abs() < 1e-3);
+    // bright pixel draws most samples
+    let bright = &lights[5].0;
+    let hits = (0..256)
+        .filter(|&i| {
+            bright
+                .sample_li(p, halton(i))
+                .is_some_and(|s| s.li[0] == 200.)
+        })
+        .count();
+    assert!(hits > 128, "{hits}");
+}
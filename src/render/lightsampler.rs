@@ -0,0 +1,485 @@
+use std::{f32::consts::PI, fmt::Debug, sync::Arc};
+
+use crate::{
+    core::{quaternion::Quat, sampling::Distribution1D, tensor::Vec3f, vec::Vector},
+    raycast::bounds::Bounds3f,
+    render::light::{Light, luminance},
+};
+
+/// picks one light for shading point p with normal n, zero n if there is no surface
+pub trait LightSampler: Sync + Send + Debug {
+    /// index of light and probability of picking it
+    fn sample(&self, p: Vec3f, n: Vec3f, u: f32) -> Option<(usize, f32)>;
+
+    fn pmf(&self, p: Vec3f, n: Vec3f, light: usize) -> f32;
+}
+
+/// how an integrator picks lights
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightSelection {
+    Uniform,
+    /// by emitted flux
+    Power,
+    /// by importance bounded over a tree of lights, for many lights
+    BVH,
+}
+
+impl LightSelection {
+    pub fn build(&self, lights: &[Arc<dyn Light>], scene_radius: f32) -> Box<dyn LightSampler> {
+        match self {
+            LightSelection::Uniform => Box::new(UniformLightSampler { n: lights.len() }),
+            LightSelection::Power => Box::new(PowerLightSampler::new(lights, scene_radius)),
+            LightSelection::BVH => Box::new(BVHLightSampler::new(lights)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UniformLightSampler {
+    pub n: usize,
+}
+
+impl LightSampler for UniformLightSampler {
+    fn sample(&self, _p: Vec3f, _n: Vec3f, u: f32) -> Option<(usize, f32)> {
+        if self.n == 0 {
+            return None;
+        }
+        let i = ((u * self.n as f32) as usize).min(self.n - 1);
+        Some((i, 1. / self.n as f32))
+    }
+
+    fn pmf(&self, _p: Vec3f, _n: Vec3f, _light: usize) -> f32 {
+        if self.n == 0 { 0. } else { 1. / self.n as f32 }
+    }
+}
+
+/// probability proportional to luminance of flux, same for every shading point
+#[derive(Debug, Clone)]
+pub struct PowerLightSampler {
+    distr: Option<Distribution1D>,
+}
+
+impl PowerLightSampler {
+    pub fn new(lights: &[Arc<dyn Light>], scene_radius: f32) -> Self {
+        let power: Vec<f32> = lights
+            .iter()
+            .map(|l| luminance(l.power(scene_radius)))
+            .collect();
+        PowerLightSampler {
+            distr: (!lights.is_empty()).then(|| Distribution1D::new(&power)),
+        }
+    }
+}
+
+impl LightSampler for PowerLightSampler {
+    fn sample(&self, _p: Vec3f, _n: Vec3f, u: f32) -> Option<(usize, f32)> {
+        let distr = self.distr.as_ref()?;
+        let (_, pdf, i) = distr.sample(u);
+        let pmf = pdf / distr.count() as f32;
+        (pmf > 0.).then_some((i, pmf))
+    }
+
+    fn pmf(&self, _p: Vec3f, _n: Vec3f, light: usize) -> f32 {
+        self.distr.as_ref().map_or(0., |d| {
+            let x = (light as f32 + 0.5) / d.count() as f32;
+            d.pdf(x) / d.count() as f32
+        })
+    }
+}
+
+/// where a light emits and how much, bounds its contribution to any point
+#[derive(Debug, Clone, Copy)]
+pub struct LightBounds {
+    pub bounds: Bounds3f,
+    pub phi: f32,
+    /// axis of normals of emitting surfaces
+    pub w: Vec3f,
+    /// normals lie within this cone around w
+    pub cos_theta_o: f32,
+    /// light leaves normals at most this far
+    pub cos_theta_e: f32,
+    pub two_sided: bool,
+}
+
+/// cos(a - b) with angles clamped to not go below 0
+fn cos_sub_clamped(sin_a: f32, cos_a: f32, sin_b: f32, cos_b: f32) -> f32 {
+    if cos_a > cos_b {
+        1.
+    } else {
+        cos_a * cos_b + sin_a * sin_b
+    }
+}
+
+fn sin_sub_clamped(sin_a: f32, cos_a: f32, sin_b: f32, cos_b: f32) -> f32 {
+    if cos_a > cos_b {
+        0.
+    } else {
+        sin_a * cos_b - cos_a * sin_b
+    }
+}
+
+fn sin_of(cos: f32) -> f32 {
+    (1. - cos * cos).max(0.).sqrt()
+}
+
+impl LightBounds {
+    /// upper bound of light arriving at p on surface with normal n, Conty and Kulla 2018
+    pub fn importance(&self, p: Vec3f, n: Vec3f) -> f32 {
+        let pc = self.bounds.centroid();
+        let d2 = (p - pc).sqrnorm().max(self.bounds.diagonal().norm() / 2.);
+
+        // angle between emitting axis and p, minus spread of normals and bounds
+        let wp = (p - pc).normalize();
+        let mut cos_w = if wp.dot(wp).is_finite() {
+            wp.dot(self.w)
+        } else {
+            1.
+        };
+        if self.two_sided {
+            cos_w = cos_w.abs();
+        }
+        let sin_w = sin_of(cos_w);
+
+        let cos_b = self.cos_subtended(p);
+        let sin_b = sin_of(cos_b);
+        let sin_o = sin_of(self.cos_theta_o);
+        let cos_x = cos_sub_clamped(sin_w, cos_w, sin_o, self.cos_theta_o);
+        let sin_x = sin_sub_clamped(sin_w, cos_w, sin_o, self.cos_theta_o);
+        let cos_p = cos_sub_clamped(sin_x, cos_x, sin_b, cos_b);
+        if cos_p <= self.cos_theta_e {
+            return 0.;
+        }
+
+        let mut importance = self.phi * cos_p / d2;
+        if n.sqrnorm() > 0. {
+            let cos_i = (pc - p).normalize().dot(n).abs();
+            let cos_i = if cos_i.is_finite() { cos_i } else { 1. };
+            importance *= cos_sub_clamped(sin_of(cos_i), cos_i, sin_b, cos_b);
+        }
+        importance.max(0.)
+    }
+
+    /// cos of half angle of bounding sphere of bounds seen from p, -1 inside
+    fn cos_subtended(&self, p: Vec3f) -> f32 {
+        let c = self.bounds.centroid();
+        let r2 = (self.bounds.diagonal() / 2.).sqrnorm();
+        let d2 = (p - c).sqrnorm();
+        if d2 < r2 {
+            return -1.;
+        }
+        (1. - r2 / d2).max(0.).sqrt()
+    }
+
+    pub fn union(&self, o: &LightBounds) -> LightBounds {
+        if self.phi == 0. {
+            return *o;
+        }
+        if o.phi == 0. {
+            return *self;
+        }
+        let (w, cos_theta_o) = cone_union((self.w, self.cos_theta_o), (o.w, o.cos_theta_o));
+        LightBounds {
+            bounds: self.bounds.union(o.bounds),
+            phi: self.phi + o.phi,
+            w,
+            cos_theta_o,
+            cos_theta_e: self.cos_theta_e.min(o.cos_theta_e),
+            two_sided: self.two_sided || o.two_sided,
+        }
+    }
+}
+
+/// smallest cone around both cones, each an axis and cos of its half angle
+fn cone_union(a: (Vec3f, f32), b: (Vec3f, f32)) -> (Vec3f, f32) {
+    let (theta_a, theta_b) = (a.1.clamp(-1., 1.).acos(), b.1.clamp(-1., 1.).acos());
+    let theta_d = a.0.dot(b.0).clamp(-1., 1.).acos();
+    if (theta_d + theta_b).min(PI) <= theta_a {
+        return a;
+    }
+    if (theta_d + theta_a).min(PI) <= theta_b {
+        return b;
+    }
+
+    let theta_o = (theta_a + theta_d + theta_b) / 2.;
+    if theta_o >= PI {
+        return (a.0, -1.);
+    }
+    // turn a towards b until cone touches both
+    let axis = a.0.cross(b.0);
+    if axis.sqrnorm() == 0. {
+        return (a.0, -1.);
+    }
+    let rot = Quat::angle_axis((theta_o - theta_a).to_degrees(), axis.normalize());
+    (rot.transform_vec(a.0).normalize(), theta_o.cos())
+}
+
+#[derive(Debug, Clone)]
+struct LightNode {
+    lb: LightBounds,
+    /// light index of leaf, index of second child otherwise, first child follows node
+    index: usize,
+    is_leaf: bool,
+}
+
+/// tree of bounded lights, traversed towards children of higher importance.
+/// lights at infinity have no bounds and are picked uniformly beside tree
+#[derive(Debug, Clone)]
+pub struct BVHLightSampler {
+    nodes: Vec<LightNode>,
+    infinite: Vec<usize>,
+    /// left or right turns from root to each bounded light, lowest bit first
+    trails: Vec<Option<u64>>,
+}
+
+impl BVHLightSampler {
+    pub fn new(lights: &[Arc<dyn Light>]) -> Self {
+        let mut infinite = vec![];
+        let mut bounded = vec![];
+        for (i, light) in lights.iter().enumerate() {
+            match light.bounds() {
+                Some(lb) if lb.phi > 0. => bounded.push((i, lb)),
+                Some(_) => {}
+                None => infinite.push(i),
+            }
+        }
+
+        let mut sampler = BVHLightSampler {
+            nodes: vec![],
+            infinite,
+            trails: vec![None; lights.len()],
+        };
+        if !bounded.is_empty() {
+            sampler.build(&mut bounded, 0, 0);
+        }
+        sampler
+    }
+
+    /// returns bounds of subtree
+    fn build(
+        &mut self,
+        lights: &mut [(usize, LightBounds)],
+        trail: u64,
+        depth: u32,
+    ) -> LightBounds {
+        // median splits keep depth within bits of trail
+        if lights.len() == 1 {
+            let (i, lb) = lights[0];
+            self.trails[i] = Some(trail);
+            self.nodes.push(LightNode {
+                lb,
+                index: i,
+                is_leaf: true,
+            });
+            return lb;
+        }
+
+        // split at median centroid of widest axis
+        let centroids = lights.iter().fold(Bounds3f::empty(), |b, (_, lb)| {
+            b.enlarge(lb.bounds.centroid())
+        });
+        let axis = centroids.max_dim();
+        lights.sort_by(|a, b| a.1.bounds.centroid()[axis].total_cmp(&b.1.bounds.centroid()[axis]));
+        let mid = lights.len() / 2;
+
+        let node = self.nodes.len();
+        self.nodes.push(LightNode {
+            lb: lights[0].1,
+            index: 0,
+            is_leaf: false,
+        });
+        let (left, right) = lights.split_at_mut(mid);
+        let lb_left = self.build(left, trail, depth + 1);
+        self.nodes[node].index = self.nodes.len();
+        let lb_right = self.build(right, trail | (1 << depth), depth + 1);
+        let lb = lb_left.union(&lb_right);
+        self.nodes[node].lb = lb;
+        lb
+    }
+
+    fn p_infinite(&self) -> f32 {
+        let bvh = !self.nodes.is_empty() as usize;
+        let total = self.infinite.len() + bvh;
+        if total == 0 {
+            0.
+        } else {
+            self.infinite.len() as f32 / total as f32
+        }
+    }
+
+    /// chance of going left at interior node, None if neither child lights p
+    fn p_left(&self, node: usize, p: Vec3f, n: Vec3f) -> Option<f32> {
+        let left = self.nodes[node + 1].lb.importance(p, n);
+        let right = self.nodes[self.nodes[node].index].lb.importance(p, n);
+        if left + right == 0. {
+            return None;
+        }
+        Some(left / (left + right))
+    }
+}
+
+impl LightSampler for BVHLightSampler {
+    fn sample(&self, p: Vec3f, n: Vec3f, u: f32) -> Option<(usize, f32)> {
+        let p_inf = self.p_infinite();
+        if u < p_inf {
+            let k = self.infinite.len();
+            let i = ((u / p_inf * k as f32) as usize).min(k - 1);
+            return Some((self.infinite[i], p_inf / k as f32));
+        }
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut u = ((u - p_inf) / (1. - p_inf)).min(1. - f32::EPSILON);
+        let mut pmf = 1. - p_inf;
+        let mut node = 0;
+        if self.nodes[0].lb.importance(p, n) == 0. {
+            return None;
+        }
+        while !self.nodes[node].is_leaf {
+            let p_left = self.p_left(node, p, n)?;
+            if u < p_left {
+                u = (u / p_left).min(1. - f32::EPSILON);
+                pmf *= p_left;
+                node += 1;
+            } else {
+                u = ((u - p_left) / (1. - p_left)).min(1. - f32::EPSILON);
+                pmf *= 1. - p_left;
+                node = self.nodes[node].index;
+            }
+        }
+        (pmf > 0.).then_some((self.nodes[node].index, pmf))
+    }
+
+    fn pmf(&self, p: Vec3f, n: Vec3f, light: usize) -> f32 {
+        let p_inf = self.p_infinite();
+        if self.infinite.contains(&light) {
+            return p_inf / self.infinite.len() as f32;
+        }
+        let Some(trail) = self.trails.get(light).copied().flatten() else {
+            return 0.;
+        };
+        if self.nodes[0].lb.importance(p, n) == 0. {
+            return 0.;
+        }
+
+        let mut pmf = 1. - p_inf;
+        let (mut node, mut depth) = (0, 0);
+        while !self.nodes[node].is_leaf {
+            let Some(p_left) = self.p_left(node, p, n) else {
+                return 0.;
+            };
+            if trail & (1 << depth) == 0 {
+                pmf *= p_left;
+                node += 1;
+            } else {
+                pmf *= 1. - p_left;
+                node = self.nodes[node].index;
+            }
+            depth += 1;
+        }
+        if self.nodes[node].index == light {
+            pmf
+        } else {
+            0.
+        }
+    }
+}
+
+#[test]
+fn test_light_sampler() {
+    use crate::{
+        prelude::*,
+        raycast::triangle::Triangle,
+        render::light::{AreaLight, EnvironmentLight, PointLight, SpotLight},
+    };
+
+    let mut lights: Vec<Arc<dyn Light>> = vec![];
+    for i in 0..6 {
+        let pos = Vec3f::vec([i as f32 * 1.5 - 4., 2., (i % 2) as f32]);
+        lights.push(Arc::new(PointLight::new(
+            pos,
+            Vec3f::vec([1. + i as f32; 3]),
+        )));
+    }
+    lights.push(Arc::new(SpotLight::new(
+        Vec3f::vec([0., 3., 0.]),
+        Vec3f::vec([0., -1., 0.]),
+        Vec3f::vec([5.; 3]),
+        30.,
+        20.,
+    )));
+    let tri = Triangle::new(
+        Vec3f::vec([-1., 4., -1.]),
+        Vec3f::vec([1., 4., -1.]),
+        Vec3f::vec([0., 4., 1.]),
+    );
+    lights.push(Arc::new(AreaLight::triangle(tri, Vec3f::vec([2.; 3]))));
+    let sphere = Sphere::new(Vec3f::vec([3., 1., -2.]), 0.5);
+    lights.push(Arc::new(AreaLight::sphere(sphere, Vec3f::vec([3.; 3]))));
+    lights.push(Arc::new(EnvironmentLight::constant(Vec3f::vec([0.1; 3]))));
+
+    let points = [
+        (Vec3f::vec([0.; 3]), Vec3f::vec([0., 1., 0.])),
+        (Vec3f::vec([2., 0.5, -1.]), Vec3f::vec([0.; 3])),
+        (Vec3f::vec([-3., 1., 2.]), Vec3f::vec([1., 0., 0.])),
+    ];
+    let n = 20000;
+    for selection in [
+        LightSelection::Uniform,
+        LightSelection::Power,
+        LightSelection::BVH,
+    ] {
+        let sampler = selection.build(&lights, 10.);
+        for (p, nrm) in points {
+            let pmfs: Vec<f32> = (0..lights.len()).map(|i| sampler.pmf(p, nrm, i)).collect();
+            let sum: f32 = pmfs.iter().sum();
+            assert!(sum <= 1. + 1e-4, "{selection:?} {sum}");
+            if nrm.sqrnorm() == 0. {
+                assert!((sum - 1.).abs() < 1e-4, "{selection:?} {sum}");
+            }
+
+            // sampled light and its pmf agree with pmf, frequencies follow pmf
+            let mut count = vec![0; lights.len()];
+            for k in 0..n {
+                let u = (k as f32 + 0.5) / n as f32;
+                if let Some((i, pmf)) = sampler.sample(p, nrm, u) {
+                    assert!(
+                        (pmf - pmfs[i]).abs() < 1e-4,
+                        "{selection:?} {i} {pmf} {}",
+                        pmfs[i]
+                    );
+                    count[i] += 1;
+                }
+            }
+            for (i, c) in count.iter().enumerate() {
+                let freq = *c as f32 / n as f32;
+                assert!(
+                    (freq - pmfs[i]).abs() < 1e-3,
+                    "{selection:?} {i} {freq} {}",
+                    pmfs[i]
+                );
+            }
+        }
+    }
+
+    // union cone contains both cones
+    let angle = |a: Vec3f, b: Vec3f| a.dot(b).clamp(-1., 1.).acos();
+    let cones = [
+        (Vec3f::vec([0., 0., 1.]), 0.9),
+        (Vec3f::vec([1., 0., 0.]), 0.8),
+        (Vec3f::vec([0., 0.6, 0.8]), 1.),
+        (Vec3f::vec([0., -1., 0.]), 0.),
+    ];
+    for a in cones {
+        for b in cones {
+            let (w, cos_o) = cone_union(a, b);
+            let theta_o = cos_o.acos();
+            for (axis, cos) in [a, b] {
+                assert!(
+                    angle(w, axis) + cos.acos() <= theta_o + 1e-3,
+                    "{a:?} {b:?} {w:?} {cos_o}"
+                );
+            }
+        }
+    }
+}
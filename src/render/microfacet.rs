@@ -0,0 +1,123 @@
+use std::f32::consts::PI;
+
+use crate::core::{sampling::sample_uni_disk_concentric, tensor::Vec3f, vec::Vector};
+
+/// isotropic GGX (Trowbridge-Reitz) distribution of microfacet normals around +z
+#[derive(Debug, Clone, Copy)]
+pub struct TrowbridgeReitz {
+    /// roughness, square of perceptual roughness
+    pub alpha: f32,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha: f32) -> Self {
+        TrowbridgeReitz { alpha }
+    }
+
+    /// alpha too small to sample without precision trouble, treated as perfect mirror
+    pub fn effectively_smooth(&self) -> bool {
+        self.alpha < 1e-3
+    }
+
+    /// density of microfacet normal wm, projected area per unit solid angle
+    pub fn d(&self, wm: Vec3f) -> f32 {
+        let cos2 = wm[2] * wm[2];
+        if cos2 == 0. {
+            return 0.;
+        }
+        let tan2 = (1. - cos2).max(0.) / cos2;
+        let a2 = self.alpha * self.alpha;
+        let e = 1. + tan2 / a2;
+        1. / (PI * a2 * cos2 * cos2 * e * e)
+    }
+
+    /// smith auxiliary function, masked area relative to visible area of w
+    pub fn lambda(&self, w: Vec3f) -> f32 {
+        let cos2 = w[2] * w[2];
+        if cos2 == 0. {
+            return f32::INFINITY;
+        }
+        let tan2 = (1. - cos2).max(0.) / cos2;
+        ((1. + self.alpha * self.alpha * tan2).sqrt() - 1.) / 2.
+    }
+
+    /// fraction of microfacets visible from w
+    pub fn g1(&self, w: Vec3f) -> f32 {
+        1. / (1. + self.lambda(w))
+    }
+
+    /// fraction visible from both wo and wi, height correlated
+    pub fn g(&self, wo: Vec3f, wi: Vec3f) -> f32 {
+        1. / (1. + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// density of normals visible from w, either side of surface
+    pub fn d_visible(&self, w: Vec3f, wm: Vec3f) -> f32 {
+        if w[2] == 0. {
+            return 0.;
+        }
+        self.g1(w) / w[2].abs() * self.d(wm) * w.dot(wm).abs()
+    }
+
+    /// visible normal in upper hemisphere, Heitz 2018
+    pub fn sample_wm(&self, w: Vec3f, u: [f32; 2]) -> Vec3f {
+        // stretch to hemisphere configuration
+        let wh = Vec3f::vec([self.alpha * w[0], self.alpha * w[1], w[2]]).normalize();
+        let wh = if wh[2] < 0. { wh * -1. } else { wh };
+        let t1 = if wh[2] < 0.99999 {
+            Vec3f::vec([0., 0., 1.]).cross(wh).normalize()
+        } else {
+            Vec3f::vec([1., 0., 0.])
+        };
+        let t2 = wh.cross(t1);
+
+        // disk point warped to projected area of hemisphere seen from wh
+        let [px, py] = sample_uni_disk_concentric(u);
+        let h = (1. - px * px).max(0.).sqrt();
+        let s = (1. + wh[2]) / 2.;
+        let py = (1. - s) * h + s * py;
+        let pz = (1. - px * px - py * py).max(0.).sqrt();
+        let nh = t1 * px + t2 * py + wh * pz;
+
+        Vec3f::vec([self.alpha * nh[0], self.alpha * nh[1], nh[2].max(1e-6)]).normalize()
+    }
+}
+
+#[test]
+fn test_trowbridge_reitz() {
+    use crate::core::sampling::square2unitsphere;
+
+    // projected normal density integrates to 1, visible density too
+    let n = 256;
+    let wo = Vec3f::vec([0.6, 0., 0.8]);
+    for alpha in [0.1, 0.5, 1.] {
+        let distr = TrowbridgeReitz::new(alpha);
+        let (mut projected, mut visible) = (0., 0.);
+        for i in 0..n * n {
+            let p = [(i % n) as f32 + 0.5, (i / n) as f32 + 0.5].map(|v| v / n as f32);
+            let wm = Vec3f::vec(square2unitsphere(p));
+            if wm[2] > 0. {
+                projected += distr.d(wm) * wm[2];
+            }
+            if wm[2] > 0. && wm.dot(wo) > 0. {
+                visible += distr.d_visible(wo, wm);
+            }
+        }
+        let da = 4. * PI / (n * n) as f32;
+        assert!(
+            (projected * da - 1.).abs() < 0.02,
+            "{alpha} {}",
+            projected * da
+        );
+        assert!((visible * da - 1.).abs() < 0.02, "{alpha} {}", visible * da);
+
+        // sampled normals face wo
+        for i in 0..64 {
+            let u = [(i % 8) as f32 / 8. + 0.01, (i / 8) as f32 / 8. + 0.01];
+            let wm = distr.sample_wm(wo, u);
+            assert!((wm.norm() - 1.).abs() < 1e-4);
+            assert!(wm[2] > 0. && wm.dot(wo) > 0.);
+        }
+    }
+    assert!(TrowbridgeReitz::new(1e-4).effectively_smooth());
+}
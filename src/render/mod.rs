@@ -1 +1,9 @@
+pub mod bsdf;
 pub mod camera;
+pub mod debug;
+pub mod heatmap;
+pub mod light;
+pub mod lightsampler;
+pub mod microfacet;
+pub mod pathtracer;
+pub mod scene;
@@ -0,0 +1,355 @@
+use rand::Rng;
+
+use crate::{
+    core::{math::coordinate_system, tensor::Vec3f, vec::Vector},
+    img::RawImage,
+    prelude::*,
+    raycast::primitive::Primitive,
+    render::{
+        lightsampler::{LightSampler, LightSelection},
+        scene::Scene,
+    },
+};
+
+/// unidirectional path tracer, estimates radiance along camera rays
+#[derive(Debug, Clone)]
+pub struct PathTracer {
+    /// samples per pixel
+    pub spp: usize,
+    /// bounces after camera ray hit, 0 sees emission and background only
+    pub max_depth: usize,
+    /// bounces before russian roulette may end a path
+    pub rr_depth: usize,
+    /// lights sampled at every bounce, weighted against bsdf samples by power heuristic.
+    /// None finds lights only by hitting them, delta lights stay dark
+    pub nee: Option<LightSelection>,
+}
+
+impl Default for PathTracer {
+    fn default() -> Self {
+        PathTracer {
+            spp: 16,
+            max_depth: 16,
+            rr_depth: 3,
+            nee: Some(LightSelection::BVH),
+        }
+    }
+}
+
+/// weight of sample from strategy of density a against strategy of density b
+fn power_heuristic(a: f32, b: f32) -> f32 {
+    if a.is_infinite() {
+        return 1.;
+    }
+    let (a2, b2) = (a * a, b * b);
+    if a2 + b2 == 0. { 0. } else { a2 / (a2 + b2) }
+}
+
+impl PathTracer {
+    /// linear radiance averaged over jittered pixel area and camera shutter
+    pub fn render<T: Primitive>(
+        &self,
+        scene: &Scene<T>,
+        cam: &Camera,
+        (w, h): (usize, usize),
+    ) -> RawImage<Rgb<f32>> {
+        let sampler = self.nee.map(|s| s.build(&scene.lights, scene.radius()));
+        let sampler = sampler.as_deref();
+        let mut img: RawImage<Rgb<f32>> = RawImage::new(w, h);
+        img.par_iter_pixels(|(i, pix)| {
+            let mut rng = rand::rng();
+            let mut sum = Vec3f::vec([0.; 3]);
+            for _ in 0..self.spp {
+                let jitter = (rng.random::<f32>() - 0.5, rng.random::<f32>() - 0.5);
+                let mut ray = cam.gen_ray((i % w, i / w), jitter, (w, h));
+                ray.time = cam.shutter_time(rng.random());
+                sum = sum + self.li(scene, sampler, &ray, &mut rng);
+            }
+            let l = sum / self.spp.max(1) as f32;
+            *pix = Rgb([l[0], l[1], l[2]]);
+        });
+        img
+    }
+
+    /// radiance arriving at ray origin from -ray.dir, sampler picks lights for
+    /// next event estimation
+    pub fn li<T: Primitive, R: Rng>(
+        &self,
+        scene: &Scene<T>,
+        sampler: Option<&dyn LightSampler>,
+        ray: &Ray,
+        rng: &mut R,
+    ) -> Vec3f {
+        let mut l = Vec3f::vec([0.; 3]);
+        // throughput, product of bsdf * cos / pdf along path
+        let mut beta = Vec3f::vec([1.; 3]);
+        let mut ray = ray.clone();
+        // position, normal and bsdf density of last bounce, None after camera and dirac bounces
+        let mut prev: Option<(Vec3f, Vec3f, f32)> = None;
+
+        for depth in 0..=self.max_depth {
+            // lights hit by bsdf samples were also reachable by light sampling
+            let mis = |light: usize| match (sampler, prev) {
+                (Some(s), Some((p, n, pdf))) => {
+                    let dir = ray.dir.normalize();
+                    let pdf_light = s.pmf(p, n, light) * scene.lights[light].pdf_li(p, dir);
+                    power_heuristic(pdf, pdf_light)
+                }
+                _ => 1.,
+            };
+
+            let Some(isect) = scene.intersect(&ray) else {
+                l = l + beta * scene.escaped(&ray, mis);
+                break;
+            };
+            let s = &isect.surface;
+            let material = &scene.materials[isect.material];
+            // emissive materials are not sampled as lights, nothing to weight against
+            l = l + beta * scene.emitted(&isect) * isect.light.map_or(1., mis);
+            if depth == self.max_depth {
+                break;
+            }
+
+            // bsdf works in frame of outward normal
+            let n = s.n;
+            let (u, v) = coordinate_system(n);
+            let local = |w: Vec3f| Vec3f::vec([w.dot(u), w.dot(v), w.dot(n)]);
+            let wo = local(isect.wo);
+
+            if let Some(sampler) = sampler {
+                let picked = sampler.sample(s.p, n, rng.random());
+                let ls = picked.and_then(|(i, pmf)| {
+                    let light = &scene.lights[i];
+                    let ls = light.sample_li(s.p, [rng.random(), rng.random()])?;
+                    Some((light, pmf, ls))
+                });
+                if let Some((light, pmf, ls)) = ls {
+                    let wi = local(ls.wi);
+                    let f = material.bsdf.eval(wo, wi) * wi[2].abs();
+                    if f.dot(f) > 0. && scene.unoccluded(&isect, ls.wi, ls.dist, ray.time) {
+                        let pdf_light = pmf * ls.pdf;
+                        let w = if light.is_delta() {
+                            1.
+                        } else {
+                            power_heuristic(pdf_light, material.bsdf.pdf(wo, wi))
+                        };
+                        l = l + beta * f * ls.li * (w / pdf_light);
+                    }
+                }
+            }
+
+            let Some(bs) = material
+                .bsdf
+                .sample(wo, rng.random(), [rng.random(), rng.random()])
+            else {
+                break;
+            };
+            let wi = u * bs.wi[0] + v * bs.wi[1] + n * bs.wi[2];
+            beta = beta * bs.f * (bs.wi[2].abs() / bs.pdf);
+            prev = (!bs.specular).then_some((s.p, n, bs.pdf));
+
+            let max_beta = beta[0].max(beta[1]).max(beta[2]);
+            if max_beta <= 0. {
+                break;
+            }
+            // unbiased: survivors carry weight of terminated paths
+            if depth + 1 >= self.rr_depth {
+                let q = max_beta.min(1.);
+                if rng.random::<f32>() >= q {
+                    break;
+                }
+                beta = beta / q;
+            }
+
+            ray = isect.spawn_ray(wi, ray.time);
+        }
+        l
+    }
+}
+
+#[test]
+fn test_white_furnace() {
+    use crate::render::{bsdf::Dielectric, light::EnvironmentLight, scene::Material};
+    use std::sync::Arc;
+
+    // albedo 1 under uniform background, every path brings back background
+    let mut scene = Scene::new(Vec3f::vec([1.; 3]));
+    let white = scene.add_material(Material::diffuse(Vec3f::vec([1.; 3])));
+    let glass = scene.add_material(Material::new(Arc::new(Dielectric::new(1.5, 0.))));
+    for i in 0..9 {
+        let c = Vec3f::vec([(i % 3) as f32 * 2.5 - 2.5, (i / 3) as f32 * 2.5 - 2.5, -6.]);
+        scene.push(Sphere::new(c, 1.), if i == 4 { glass } else { white });
+    }
+    scene.build(4, false);
+    let cam = Camera::new(
+        Vec3f::vec([0.; 3]),
+        Vec3f::vec([0., 0., -1.]),
+        90.,
+        0.1,
+        100.,
+    );
+    let pt = PathTracer {
+        spp: 4,
+        max_depth: 64,
+        ..Default::default()
+    };
+    let img = pt.render(&scene, &cam, (32, 32));
+    for p in img.data() {
+        assert!(p.0.iter().all(|&c| (c - 1.).abs() < 1e-5), "{p:?}");
+    }
+    // same light from environment at infinity, exact without light sampling
+    scene.background = Vec3f::vec([0.; 3]);
+    scene.add_light(EnvironmentLight::constant(Vec3f::vec([1.; 3])));
+    let naive = PathTracer { nee: None, ..pt };
+    let img = naive.render(&scene, &cam, (8, 8));
+    for p in img.data() {
+        assert!(p.0.iter().all(|&c| (c - 1.).abs() < 1e-5), "{p:?}");
+    }
+    // unbiased but noisy, enough samples to average within tolerance
+    let nee = PathTracer { spp: 64, ..pt };
+    let img = nee.render(&scene, &cam, (16, 16));
+    let avg = img.data().iter().map(|p| p.0[0]).sum::<f32>() / img.data().len() as f32;
+    assert!((avg - 1.).abs() < 0.01, "{avg}");
+
+    // closed sphere around camera, emission e and albedo a converge to e / (1 - a)
+    let mut scene = Scene::new(Vec3f::vec([0.; 3]));
+    let wall = scene.add_material(Material::emissive(
+        Vec3f::vec([0.5, 0.75, 0.25]),
+        Vec3f::vec([0.5, 0.25, 0.75]),
+    ));
+    scene.push(Sphere::new(Vec3f::vec([0.; 3]), 4.), wall);
+    scene.build(4, false);
+    let pt = PathTracer {
+        spp: 64,
+        max_depth: 256,
+        rr_depth: 1,
+        nee: None,
+    };
+    let img = pt.render(&scene, &cam, (32, 32));
+    let avg = |c: usize| img.data().iter().map(|p| p.0[c]).sum::<f32>() / img.data().len() as f32;
+    assert!((avg(0) - 1.).abs() < 0.02, "{}", avg(0));
+    assert!((avg(1) - 1.).abs() < 0.02, "{}", avg(1));
+    assert!((avg(2) - 1.).abs() < 0.02, "{}", avg(2));
+
+    // depth 0 sees emission only, one bounce adds one reflection
+    for (depth, expected) in [(0, 0.5), (1, 0.5 + 0.5 * 0.5)] {
+        let pt = PathTracer {
+            spp: 1,
+            max_depth: depth,
+            rr_depth: 8,
+            nee: None,
+        };
+        let img = pt.render(&scene, &cam, (8, 8));
+        img.data()
+            .iter()
+            .for_each(|p| assert!((p.0[0] - expected).abs() < 1e-5, "{p:?}"));
+    }
+}
+
+#[test]
+fn test_nee() {
+    use crate::render::{
+        light::{AreaLight, EnvironmentLight, PointLight},
+        scene::Material,
+    };
+
+    // diffuse spheres on a floor lit by small sphere lights and dim sky
+    let mut scene = Scene::new(Vec3f::vec([0.; 3]));
+    let grey = scene.add_material(Material::diffuse(Vec3f::vec([0.5; 3])));
+    let red = scene.add_material(Material::diffuse(Vec3f::vec([0.7, 0.2, 0.2])));
+    let black = scene.add_material(Material::diffuse(Vec3f::vec([0.; 3])));
+    scene.push(Sphere::new(Vec3f::vec([0., -1000., 0.]), 1000.), grey);
+    for i in 0..3 {
+        let c = Vec3f::vec([i as f32 * 1.5 - 1.5, 0.5, -4.]);
+        scene.push(Sphere::new(c, 0.5), red);
+    }
+    for i in 0..4 {
+        let light = Sphere::new(Vec3f::vec([i as f32 * 2. - 3., 2.5, -3. - i as f32]), 0.8);
+        let radiance = Vec3f::vec([2. + i as f32; 3]);
+        scene.push_area_light(light.clone(), black, AreaLight::sphere(light, radiance));
+    }
+    scene.add_light(EnvironmentLight::constant(Vec3f::vec([0.05; 3])));
+    scene.build(4, false);
+    let mut cam = Camera::new(
+        Vec3f::vec([0., 1.5, 1.]),
+        Vec3f::vec([0., 0., -1.]),
+        70.,
+        0.1,
+        100.,
+    );
+    cam.look_at(Vec3f::vec([0., 0.5, -4.]));
+
+    // means of 4x4 pixel tiles, misplaced light shows in tiles even if image mean holds
+    let tiles = |img: &RawImage<Rgb<f32>>| -> Vec<f32> {
+        (0..16)
+            .map(|t| {
+                let (tx, ty) = (t % 4 * 4, t / 4 * 4);
+                (0..16)
+                    .map(|k| {
+                        img.data()[(ty + k / 4) * 16 + tx + k % 4]
+                            .0
+                            .iter()
+                            .sum::<f32>()
+                    })
+                    .sum::<f32>()
+                    / 16.
+            })
+            .collect()
+    };
+    let naive = PathTracer {
+        spp: 4096,
+        max_depth: 2,
+        rr_depth: 8,
+        nee: None,
+    };
+    let reference = tiles(&naive.render(&scene, &cam, (16, 16)));
+    for selection in [
+        LightSelection::Uniform,
+        LightSelection::Power,
+        LightSelection::BVH,
+    ] {
+        let pt = PathTracer {
+            spp: 1024,
+            nee: Some(selection),
+            ..naive
+        };
+        // mean relative error of tiles, noise of both images stays well below bound
+        let error = tiles(&pt.render(&scene, &cam, (16, 16)))
+            .iter()
+            .zip(&reference)
+            .map(|(t, r)| (t / r - 1.).abs())
+            .sum::<f32>()
+            / 16.;
+        assert!(error < 0.04, "{selection:?} {error}");
+    }
+
+    // point light over plane, one bounce gives lambertian radiance albedo / pi * intensity / h^2
+    let mut scene = Scene::new(Vec3f::vec([0.; 3]));
+    let grey = scene.add_material(Material::diffuse(Vec3f::vec([0.5; 3])));
+    scene.push(Sphere::new(Vec3f::vec([0., -1000., 0.]), 1000.), grey);
+    scene.add_light(PointLight::new(
+        Vec3f::vec([0., 2., 0.]),
+        Vec3f::vec([4.; 3]),
+    ));
+    scene.build(4, false);
+    let mut cam = Camera::new(
+        Vec3f::vec([0., 1., 2.]),
+        Vec3f::vec([0., 0., -1.]),
+        0.5,
+        0.1,
+        100.,
+    );
+    cam.look_at(Vec3f::vec([0.; 3]));
+    let pt = PathTracer {
+        spp: 16,
+        max_depth: 1,
+        ..Default::default()
+    };
+    let img = pt.render(&scene, &cam, (1, 1));
+    let expected = 0.5 / std::f32::consts::PI;
+    assert!(
+        (img.data()[0].0[0] - expected).abs() < 1e-3,
+        "{:?}",
+        img.data()[0]
+    );
+}
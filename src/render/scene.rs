@@ -0,0 +1,252 @@
+use std::sync::Arc;
+
+use crate::{
+    core::{tensor::Vec3f, vec::Vector},
+    raycast::{Hit, Ray, Raycast, Surface, bounds::Bounds3f, bvh::BVH, primitive::Primitive},
+    render::{
+        bsdf::{Bsdf, Lambertian},
+        light::{AreaLight, Light},
+    },
+};
+
+/// scattering of surface, emission makes it glow from both sides without being
+/// sampled as a light. surfaces of area lights emit through their light instead
+#[derive(Debug, Clone)]
+pub struct Material {
+    pub bsdf: Arc<dyn Bsdf>,
+    pub emission: Vec3f,
+}
+
+impl Material {
+    pub fn new(bsdf: Arc<dyn Bsdf>) -> Material {
+        Material {
+            bsdf,
+            emission: Vec3f::vec([0.; 3]),
+        }
+    }
+
+    pub fn diffuse(albedo: Vec3f) -> Material {
+        Material::new(Arc::new(Lambertian::new(albedo)))
+    }
+
+    pub fn emissive(albedo: Vec3f, emission: Vec3f) -> Material {
+        Material {
+            emission,
+            ..Material::diffuse(albedo)
+        }
+    }
+}
+
+/// primitive with index of its material in scene, travels with primitive when tree is built
+#[derive(Debug, Clone)]
+pub struct Object<T: Primitive> {
+    pub prim: T,
+    pub material: usize,
+    /// area light emitting from primitive
+    pub light: Option<usize>,
+}
+
+impl<T: Primitive> Raycast for Object<T> {
+    fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        self.prim.raycast(ray)
+    }
+}
+
+impl<T: Primitive> Primitive for Object<T> {
+    fn bounds(&self) -> Bounds3f {
+        self.prim.bounds()
+    }
+
+    fn clip_bounds(&self, clip: &Bounds3f) -> Bounds3f {
+        self.prim.clip_bounds(clip)
+    }
+
+    fn distance(&self, p: Vec3f) -> f32 {
+        self.prim.distance(p)
+    }
+
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Option<Surface> {
+        self.prim.surface(ray, hit)
+    }
+
+    fn unbounded(&self) -> bool {
+        self.prim.unbounded()
+    }
+}
+
+/// closest hit of ray in scene
+#[derive(Debug, Clone, Copy)]
+pub struct Interaction {
+    pub t: f32,
+    pub surface: Surface,
+    /// unit direction back along ray
+    pub wo: Vec3f,
+    pub material: usize,
+    pub light: Option<usize>,
+}
+
+impl Interaction {
+    /// ray leaving surface towards dir, origin pushed off surface on side of dir
+    pub fn spawn_ray(&self, dir: Vec3f, time: f32) -> Ray {
+        self.surface.spawn_ray(dir, time)
+    }
+}
+
+/// shadow rays stop short of light by this fraction of distance
+const SHADOW_EPS: f32 = 1e-3;
+
+/// primitives with materials and lights, rays missing every primitive see
+/// background and lights at infinity
+pub struct Scene<T: Primitive> {
+    pub bvh: BVH<Object<T>>,
+    pub materials: Vec<Material>,
+    pub lights: Vec<Arc<dyn Light>>,
+    /// radiance arriving from outside scene
+    pub background: Vec3f,
+}
+
+impl<T: Primitive> Scene<T> {
+    pub fn new(background: Vec3f) -> Self {
+        Scene {
+            bvh: BVH::new(0),
+            materials: vec![],
+            lights: vec![],
+            background,
+        }
+    }
+
+    /// returns index of material
+    pub fn add_material(&mut self, material: Material) -> usize {
+        self.materials.push(material);
+        self.materials.len() - 1
+    }
+
+    /// returns index of light
+    pub fn add_light<L: Light + 'static>(&mut self, light: L) -> usize {
+        self.lights.push(Arc::new(light));
+        self.lights.len() - 1
+    }
+
+    pub fn push(&mut self, prim: T, material: usize) {
+        assert!(material < self.materials.len());
+        self.bvh.push(Object {
+            prim,
+            material,
+            light: None,
+        });
+    }
+
+    /// prim is geometry of light shape, seen by rays and shading surface of light.
+    /// light is the only emission of surface, material must not be emissive
+    pub fn push_area_light(&mut self, prim: T, material: usize, light: AreaLight) -> usize {
+        assert!(material < self.materials.len());
+        assert!(
+            self.materials[material].emission == Vec3f::vec([0.; 3]),
+            "area light with emissive material {material} emits twice"
+        );
+        let light = self.add_light(light);
+        self.bvh.push(Object {
+            prim,
+            material,
+            light: Some(light),
+        });
+        light
+    }
+
+    /// must be called after pushing primitives and before tracing
+    pub fn build(&mut self, node_prims_limit: usize, par_build: bool) {
+        self.bvh.build(node_prims_limit, par_build);
+    }
+
+    /// None if ray misses, or hits a primitive without surface
+    pub fn intersect(&self, ray: &Ray) -> Option<Interaction> {
+        let (hit, i) = self.bvh.raycast_node(ray)?;
+        let obj = &self.bvh.primitives[i];
+        let surface = obj.surface(ray, &hit)?;
+        Some(Interaction {
+            t: hit.t,
+            surface,
+            wo: ray.dir.normalize() * -1.,
+            material: obj.material,
+            light: obj.light,
+        })
+    }
+
+    /// nothing between hit point and light at dist along unit wi
+    pub fn unoccluded(&self, isect: &Interaction, wi: Vec3f, dist: f32, time: f32) -> bool {
+        let mut ray = isect.spawn_ray(wi, time);
+        if dist.is_finite() {
+            ray.t_max = dist * (1. - SHADOW_EPS);
+        }
+        !self.bvh.occluded(&ray)
+    }
+
+    /// radiance leaving hit surface towards ray origin, from its area light or else its material
+    pub fn emitted(&self, isect: &Interaction) -> Vec3f {
+        let s = &isect.surface;
+        match isect.light {
+            Some(i) => self.lights[i].l(s.p, s.n, isect.wo),
+            None => self.materials[isect.material].emission,
+        }
+    }
+
+    /// radiance arriving along ray that missed every primitive.
+    /// mis weighs light at infinity of index against its light samples, background is not sampled
+    pub fn escaped(&self, ray: &Ray, mis: impl Fn(usize) -> f32) -> Vec3f {
+        self.lights
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| l.is_infinite())
+            .fold(self.background, |acc, (i, l)| acc + l.le(ray) * mis(i))
+    }
+
+    /// radius of sphere around primitives, for power of lights at infinity
+    pub fn radius(&self) -> f32 {
+        let bounds = self.bvh.bounds();
+        if bounds.is_empty() {
+            return 0.;
+        }
+        bounds.diagonal().norm() / 2.
+    }
+}
+
+#[test]
+fn test_scene_emitted() {
+    use crate::raycast::sphere::Sphere;
+
+    // one emission source per surface, area light or material
+    let mut scene = Scene::new(Vec3f::vec([0.; 3]));
+    let black = scene.add_material(Material::diffuse(Vec3f::vec([0.; 3])));
+    let glow = scene.add_material(Material::emissive(Vec3f::vec([0.; 3]), Vec3f::vec([3.; 3])));
+    let ball = Sphere::new(Vec3f::vec([0., 0., -4.]), 1.);
+    scene.push_area_light(
+        ball.clone(),
+        black,
+        AreaLight::sphere(ball, Vec3f::vec([2.; 3])),
+    );
+    scene.push(Sphere::new(Vec3f::vec([0., 0., 4.]), 1.), glow);
+    scene.build(4, false);
+
+    let org = Vec3f::vec([0.; 3]);
+    let isect = scene
+        .intersect(&Ray::new(org, Vec3f::vec([0., 0., -1.])))
+        .unwrap();
+    assert_eq!(isect.light, Some(0));
+    assert_eq!(scene.emitted(&isect), Vec3f::vec([2.; 3]));
+    let isect = scene
+        .intersect(&Ray::new(org, Vec3f::vec([0., 0., 1.])))
+        .unwrap();
+    assert_eq!(isect.light, None);
+    assert_eq!(scene.emitted(&isect), Vec3f::vec([3.; 3]));
+
+    // rejected, emission would be counted twice
+    let area = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+        let ball = Sphere::new(Vec3f::vec([0.; 3]), 1.);
+        scene.push_area_light(
+            ball.clone(),
+            glow,
+            AreaLight::sphere(ball, Vec3f::vec([2.; 3])),
+        )
+    }));
+    assert!(area.is_err());
+}
@@ -1,4 +1,4 @@
-const SH_C0: f32 = 0.2820948;
+pub(crate) const SH_C0: f32 = 0.2820948;
 
 use crate::{
     core::{
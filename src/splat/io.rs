@@ -7,9 +7,11 @@ use std::{
 use anyhow::{Ok, Result, anyhow};
 use ply_rs::{
     parser::{self},
-    ply::{self, Encoding, Header, PropertyType},
+    ply::{self, Encoding, Header, Property, PropertyType},
 };
 
+use crate::{core::quaternion::Quat, splat::gaussian::SH_C0};
+
 // continuous bytes gaussian splat
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -37,6 +39,120 @@ impl Default for RawGaussian {
     }
 }
 
+/// point of a scanned or trained point cloud
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RawPoint {
+    pub pos: [f32; 3],
+    /// unit normal
+    pub nor: [f32; 3],
+    /// linear rgb in [0,1]
+    pub col: [f32; 3],
+    /// disk radius if file has one
+    pub radius: Option<f32>,
+}
+
+/// vertices of any encoding and scalar type, normals from nx, ny, nz.
+/// colour from red, green, blue or from f_dc of splat plys, else white.
+/// radius from radius property or largest splat scale.
+/// splat plys with zero normals take axis of smallest scale,
+/// other points without normal are left out
+pub fn read_ply_points(path: &str) -> Result<Vec<RawPoint>> {
+    let f = File::open(path)?;
+    let mut reader = BufReader::new(f);
+    let parser = parser::Parser::<ply::DefaultElement>::new();
+    let ply = parser.read_ply(&mut reader)?;
+    let vertices = ply
+        .payload
+        .get("vertex")
+        .ok_or(anyhow!("err: cannot read vertex element"))?;
+
+    let mut points = Vec::with_capacity(vertices.len());
+    let mut no_normal = 0;
+    for v in vertices {
+        let get3 = |names: [&str; 3]| -> Option<[f32; 3]> {
+            Some([
+                scalar(v, names[0])?,
+                scalar(v, names[1])?,
+                scalar(v, names[2])?,
+            ])
+        };
+
+        let pos = get3(["x", "y", "z"]).ok_or(anyhow!("err: vertex without x, y, z"))?;
+        let scale = get3(["scale_0", "scale_1", "scale_2"]).map(|s| s.map(f32::exp));
+        let rot = (|| {
+            Some([
+                scalar(v, "rot_0")?,
+                scalar(v, "rot_1")?,
+                scalar(v, "rot_2")?,
+                scalar(v, "rot_3")?,
+            ])
+        })();
+
+        let mut nor = get3(["nx", "ny", "nz"]).unwrap_or([0.; 3]);
+        let len = nor.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if len > 0. {
+            nor = nor.map(|x| x / len);
+        } else if let (Some(scale), Some(rot)) = (scale, rot) {
+            // flattest axis of splat
+            let len = rot.iter().map(|x| x * x).sum::<f32>().sqrt();
+            let m = Quat::new(rot.map(|x| x / len)).to_matrix();
+            let axis = (0..3)
+                .min_by(|&a, &b| scale[a].total_cmp(&scale[b]))
+                .unwrap();
+            nor = std::array::from_fn(|i| m[(i, axis)]);
+        } else {
+            no_normal += 1;
+            continue;
+        }
+
+        let col = if let Some(rgb) = color(v, ["red", "green", "blue"]) {
+            rgb
+        } else if let Some(dc) = get3(["f_dc_0", "f_dc_1", "f_dc_2"]) {
+            dc.map(|c| (c * SH_C0 + 0.5).clamp(0., 1.))
+        } else {
+            [1.; 3]
+        };
+
+        let radius = scalar(v, "radius").or(scale.map(|s| s[0].max(s[1]).max(s[2])));
+        points.push(RawPoint {
+            pos,
+            nor,
+            col,
+            radius,
+        });
+    }
+
+    if no_normal > 0 {
+        eprintln!("warn: {no_normal} points without normal left out");
+    }
+    Ok(points)
+}
+
+/// scalar property of any type as f32
+fn scalar(e: &ply::DefaultElement, name: &str) -> Option<f32> {
+    match e.get(name)? {
+        Property::Char(v) => Some(*v as f32),
+        Property::UChar(v) => Some(*v as f32),
+        Property::Short(v) => Some(*v as f32),
+        Property::UShort(v) => Some(*v as f32),
+        Property::Int(v) => Some(*v as f32),
+        Property::UInt(v) => Some(*v as f32),
+        Property::Float(v) => Some(*v),
+        Property::Double(v) => Some(*v as f32),
+        _ => None,
+    }
+}
+
+/// colour channels, integer types are scaled by their max into [0,1]
+fn color(e: &ply::DefaultElement, names: [&str; 3]) -> Option<[f32; 3]> {
+    let channel = |name: &str| match e.get(name)? {
+        Property::UChar(v) => Some(*v as f32 / u8::MAX as f32),
+        Property::UShort(v) => Some(*v as f32 / u16::MAX as f32),
+        _ => scalar(e, name),
+    };
+    Some([channel(names[0])?, channel(names[1])?, channel(names[2])?])
+}
+
 pub fn read_ply(path: &str) -> Result<Vec<RawGaussian>> {
     let f = File::open(path)?;
     let mut reader = BufReader::new(f);
@@ -201,3 +317,69 @@ fn test_ply_read() -> Result<()> {
     assert!(same);
     Ok(())
 }
+
+#[test]
+fn test_ply_points() -> Result<()> {
+    use std::io::Write;
+
+    let dir = std::env::temp_dir();
+
+    // ascii scan with byte colours, one point without normal
+    let scan = dir.join("illuminator_test_scan.ply");
+    let mut f = File::create(&scan)?;
+    write!(
+        f,
+        "ply\nformat ascii 1.0\nelement vertex 3\n\
+         property double x\nproperty double y\nproperty double z\n\
+         property float nx\nproperty float ny\nproperty float nz\n\
+         property uchar red\nproperty uchar green\nproperty uchar blue\nend_header\n\
+         1 2 3 0 0 2 255 0 51\n\
+         -1 0.5 0 0 1 0 0 255 0\n\
+         0 0 0 0 0 0 10 10 10\n"
+    )?;
+    drop(f);
+
+    let points = read_ply_points(scan.to_str().unwrap())?;
+    assert_eq!(points.len(), 2);
+    assert_eq!(points[0].pos, [1., 2., 3.]);
+    assert_eq!(points[0].nor, [0., 0., 1.]);
+    assert!((0..3).all(|i| (points[0].col[i] - [1., 0., 0.2][i]).abs() < 1e-6));
+    assert_eq!(points[0].radius, None);
+    assert_eq!(points[1].nor, [0., 1., 0.]);
+
+    // binary splat with zero normals, flattest axis is z rotated to x
+    let splat = dir.join("illuminator_test_splat.ply");
+    let names = [
+        "x", "y", "z", "nx", "ny", "nz", "f_dc_0", "f_dc_1", "f_dc_2", "opacity", "scale_0",
+        "scale_1", "scale_2", "rot_0", "rot_1", "rot_2", "rot_3",
+    ];
+    let mut f = File::create(&splat)?;
+    write!(
+        f,
+        "ply\nformat binary_little_endian 1.0\nelement vertex 1\n"
+    )?;
+    for name in names {
+        writeln!(f, "property float {name}")?;
+    }
+    writeln!(f, "end_header")?;
+    let half = 0.5f32.sqrt();
+    let values: [f32; 17] = [
+        0., 1., 0., 0., 0., 0., 0., 0., 1., 0.3, 0., 0.5, -2., half, 0., half, 0.,
+    ];
+    values
+        .iter()
+        .for_each(|v| f.write_all(&v.to_le_bytes()).unwrap());
+    drop(f);
+
+    let points = read_ply_points(splat.to_str().unwrap())?;
+    assert_eq!(points.len(), 1);
+    let p = points[0];
+    assert!((p.nor[0] - 1.).abs() < 1e-6 && p.nor[1].abs() < 1e-6 && p.nor[2].abs() < 1e-6);
+    assert_eq!(p.col[0], 0.5);
+    assert!((p.col[2] - (SH_C0 + 0.5)).abs() < 1e-6);
+    assert!((p.radius.unwrap() - 0.5f32.exp()).abs() < 1e-6);
+
+    std::fs::remove_file(scan)?;
+    std::fs::remove_file(splat)?;
+    Ok(())
+}
@@ -1,4 +1,5 @@
 pub mod gaussian;
 pub mod io;
 pub mod render;
+pub mod surfel;
 
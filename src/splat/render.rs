@@ -1,7 +1,8 @@
 use crate::{
-    core::{matrix::Matrix, tensor::Mat1x3f, vec::Vector},
-    img::{RawImage, PixelType},
+    core::{matrix::Matrix, tensor::Mat1x3f, transform::MotionTransform, vec::Vector},
+    img::{PixelType, RawImage},
     prelude::*,
+    raycast::bvhbuild::HLBVHBuilder,
     splat::{gaussian::Gaussian, io::read_ply},
 };
 
@@ -11,13 +12,18 @@ use rayon::prelude::*;
 
 pub struct SplatsRenderer {
     pub bvh: BVH<Gaussian>,
+    /// placement of whole splat cloud by ray time, none if static
+    pub motion: Option<MotionTransform>,
+    /// rays per pixel spread over camera shutter
+    pub time_samples: usize,
 }
 
 impl SplatsRenderer {
-    pub const CHUNK_SIZE: usize = 64; 
+    pub const CHUNK_SIZE: usize = 64;
     pub const BVH_NODE_SIZE: usize = 256;
 
-    pub fn from_ply(path: &str) -> Result<Self> {
+    /// bvh_cache: tree file reused while ply is unchanged, without it tree is built every time
+    pub fn from_ply(path: &str, bvh_cache: Option<&str>) -> Result<Self> {
         let input_gs = read_ply(path)?;
         let splats: Vec<Gaussian> = input_gs.par_iter().map(Gaussian::from_input).collect();
 
@@ -27,9 +33,19 @@ impl SplatsRenderer {
             bvh.push(splats[i]);
         });
 
-        bvh.build(Self::BVH_NODE_SIZE + 1, true);
+        let builder = HLBVHBuilder { par_build: true };
+        match bvh_cache {
+            Some(cache) => {
+                bvh.build_cached(&builder, Self::BVH_NODE_SIZE + 1, cache)?;
+            }
+            None => bvh.build_with(&builder, Self::BVH_NODE_SIZE + 1),
+        }
 
-        Ok(SplatsRenderer { bvh })
+        Ok(SplatsRenderer {
+            bvh,
+            motion: None,
+            time_samples: 8,
+        })
     }
 
     pub fn render<P: PixelType>(&self, cam: &Camera, (w, h): (usize, usize)) -> RawImage<P> {
@@ -58,8 +74,21 @@ impl SplatsRenderer {
             .for_each(|(i, pix)| {
                 let (iw, ih) = (i % w, i / w);
                 // let ray = cam.gen_ray_orthogonal((iw, ih), (0., 0.), (w, h), 1.5);
-                let ray = cam.gen_ray((iw, ih), (0., 0.), (w, h));
-                let col = self.trace(&ray);
+                let mut ray = cam.gen_ray((iw, ih), (0., 0.), (w, h));
+
+                // stratified times over shutter
+                let n = if cam.is_instant() {
+                    1
+                } else {
+                    self.time_samples.max(1)
+                };
+                let mut col = Vec3f::zero();
+                for s in 0..n {
+                    let u = (s as f32 + rand::random::<f32>()) / n as f32;
+                    ray.time = cam.shutter_time(u);
+                    col = col + self.trace(&ray);
+                }
+                let col = col / n as f32;
                 *pix = P::from(&[col[0], col[1], col[2]]);
                 finished_pixs.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
             });
@@ -77,7 +106,11 @@ impl SplatsRenderer {
         let mut tsm = 1.; // transmittance
         let mut buf;
 
-        let mut ray = in_ray.clone();
+        // splats stay in cloud space, ray is moved instead
+        let mut ray = match &self.motion {
+            Some(m) => in_ray.to_object(&m.at(in_ray.time)),
+            None => in_ray.clone(),
+        };
 
         loop {
             let mut end_trace = false;
@@ -190,7 +223,7 @@ fn test_trace_splats() -> Result<()> {
     use std::path::Path;
 
     let ply_path = "./target/bicycle.ply";
-    let rdr = SplatsRenderer::from_ply(ply_path)?;
+    let rdr = SplatsRenderer::from_ply(ply_path, None)?;
 
     let mut cam = Camera::default();
     cam.pos = Vec3f::vec([-3., 0., 0.]);
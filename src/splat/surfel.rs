@@ -0,0 +1,161 @@
+use std::fmt::Debug;
+
+use anyhow::Result;
+
+use crate::{
+    core::vec::Vector,
+    img::{PixelType, RawImage},
+    prelude::*,
+    raycast::{Hit, Surface, bounds::Bounds3f, disk::Disk, primitive::Primitive},
+    splat::io::{RawPoint, read_ply_points},
+};
+
+/// oriented disk sampling a surface, with its colour
+#[derive(Clone)]
+pub struct Surfel {
+    pub disk: Disk,
+    pub col: Vec3f,
+}
+
+impl Surfel {
+    pub fn new(pos: Vec3f, nor: Vec3f, r: f32, col: Vec3f) -> Self {
+        Surfel {
+            disk: Disk::new(pos, nor, r),
+            col,
+        }
+    }
+
+    /// radius of point if it has one, else default_r
+    pub fn from_point(p: &RawPoint, default_r: f32) -> Self {
+        Surfel::new(
+            Vec3f::vec(p.pos),
+            Vec3f::vec(p.nor),
+            p.radius.unwrap_or(default_r),
+            Vec3f::vec(p.col),
+        )
+    }
+}
+
+/// surfels of ply points, see read_ply_points.
+/// points without radius cover bounds area shared by all points, a rough guess of spacing
+pub fn read_surfels(path: &str) -> Result<Vec<Surfel>> {
+    let points = read_ply_points(path)?;
+    let bounds = points
+        .iter()
+        .fold(Bounds3f::empty(), |b, p| b.enlarge(Vec3f::vec(p.pos)));
+    let area = if points.is_empty() {
+        0.
+    } else {
+        bounds.area() * 0.5
+    };
+    let default_r = (area / (std::f32::consts::PI * points.len().max(1) as f32)).sqrt();
+    Ok(points
+        .iter()
+        .map(|p| Surfel::from_point(p, default_r))
+        .collect())
+}
+
+/// preview of nearest surfel colours, lit from camera
+pub fn render_surfels<P: PixelType>(
+    bvh: &BVH<Surfel>,
+    cam: &Camera,
+    (w, h): (usize, usize),
+) -> RawImage<P> {
+    let mut img: RawImage<P> = RawImage::new(w, h);
+    img.par_iter_pixels(|(i, pix)| {
+        let ray = cam.gen_ray((i % w, i / w), (0., 0.), (w, h));
+        if let Some((hit, prim_i)) = bvh.raycast_node(&ray) {
+            let surfel = &bvh.primitives[prim_i];
+            let n = surfel.surface(&ray, &hit).map_or(surfel.disk.n, |s| s.n);
+            // two sided, faces at grazing angles stay visible
+            let light = 0.3 + 0.7 * n.dot(ray.dir.normalize()).abs();
+            let col = surfel.col * light;
+            *pix = P::from(&[col[0], col[1], col[2]]);
+        }
+    });
+    img
+}
+
+impl Raycast for Surfel {
+    fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        self.disk.raycast(ray)
+    }
+}
+
+impl Debug for Surfel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "surfel {:?} {}", self.disk, self.col)
+    }
+}
+
+impl Primitive for Surfel {
+    fn bounds(&self) -> Bounds3f {
+        self.disk.bounds()
+    }
+
+    fn distance(&self, p: Vec3f) -> f32 {
+        self.disk.distance(p)
+    }
+
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Option<Surface> {
+        self.disk.surface(ray, hit)
+    }
+}
+
+#[test]
+fn test_surfel() {
+    use crate::raycast::bruteforce::BruteForce;
+    use rand::Rng;
+
+    // flat patch of surfels facing +z, overlapping so patch has no holes
+    let mut bvh = BVH::new(256);
+    let mut oracle = BruteForce::new(256);
+    for i in 0..256 {
+        let (x, y) = ((i % 16) as f32 * 0.25, (i / 16) as f32 * 0.25);
+        let col = Vec3f::vec([x / 4., y / 4., 1.]);
+        let s = Surfel::new(Vec3f::vec([x, y, 0.]), Vec3f::vec([0., 0., 1.]), 0.2, col);
+        bvh.push(s.clone());
+        oracle.push(s);
+    }
+    bvh.build(4, false);
+
+    let mut rng = rand::rng();
+    for _ in 0..256 {
+        let org = Vec3f::vec([rng.random_range(0. ..3.75), rng.random_range(0. ..3.75), 2.]);
+        let ray = Ray::new(org, Vec3f::vec([0., 0., -1.]));
+        let (hit, i) = bvh.raycast_node(&ray).unwrap();
+        assert_eq!(hit.t, 2.);
+        assert_eq!(Some(hit.t), oracle.raycast(&ray).map(|h| h.t));
+        // hit surfel is within radius
+        let s = &bvh.primitives[i];
+        assert!((hit.position(&ray) - s.disk.cnt).norm() <= 0.2);
+        assert_eq!(s.surface(&ray, &hit).unwrap().n, Vec3f::vec([0., 0., 1.]));
+    }
+    let miss = Ray::new(Vec3f::vec([5., 5., 2.]), Vec3f::vec([0., 0., -1.]));
+    assert!(bvh.raycast(&miss).is_none());
+
+    // radius of point or default
+    let p = RawPoint {
+        pos: [1., 2., 3.],
+        nor: [0., 1., 0.],
+        col: [0.5; 3],
+        radius: Some(0.1),
+    };
+    assert_eq!(Surfel::from_point(&p, 1.).disk.r, 0.1);
+    let p = RawPoint { radius: None, ..p };
+    assert_eq!(Surfel::from_point(&p, 1.).disk.r, 1.);
+
+    // preview sees patch colour in middle and black around
+    let cam = Camera::new(
+        Vec3f::vec([1.9, 1.9, 5.]),
+        Vec3f::vec([0., 0., -1.]),
+        90.,
+        0.1,
+        10.,
+    );
+    let img: RawImage<Rgb<u8>> = render_surfels(&bvh, &cam, (16, 16));
+    let mid = img.data()[8 * 16 + 8];
+    assert!(mid.0[2] > 250 && mid.0[0] > 76, "{mid:?}");
+    let corner = img.data()[0];
+    assert_eq!(corner.0, [0; 3]);
+}